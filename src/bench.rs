@@ -0,0 +1,67 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use crate::{Funge, Int, IO};
+
+
+pub struct Workload {
+    pub name: &'static str,
+    pub code: &'static str
+}
+
+pub const WORKLOADS: &[Workload] = &[
+    Workload { name: "mandelbrot", code: include_str!("../examples/mandelbrot.bf") },
+    Workload { name: "quine", code: include_str!("../examples/quine1.bf") },
+    Workload { name: "sieve", code: include_str!("../examples/sieve.bf") },
+    Workload { name: "stack churn", code: "0123456789::::\\\\$$$$$$$$$$$$$$" },
+    Workload { name: "put get", code: "0>:00p00g1+:00p00g1+" },
+];
+
+
+pub struct BenchResult {
+    pub name: &'static str,
+    pub steps: usize,
+    pub elapsed: Duration
+}
+
+impl BenchResult {
+    pub fn steps_per_second(&self) -> f64 {
+        self.steps as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl Display for BenchResult {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:<12} {:>10} steps {:>9.3} s {:>12.0} steps/s", self.name, self.steps, self.elapsed.as_secs_f64(),
+               self.steps_per_second())
+    }
+}
+
+
+impl Workload {
+    pub fn funge<I: Int>(&self) -> Result<Funge<I>> {
+        let code = self.code.lines().filter(|line| !line.starts_with("#!")).collect::<Vec<&str>>().join("\n");
+        Ok(Funge::new(code)?.deterministic().with_output(IO::new().with_output(|_, _| Ok(()))))
+    }
+
+    // programs that stop early are restarted until the step budget is used up
+    pub fn run<I: Int>(&self, steps: usize) -> Result<BenchResult> {
+        let start = self.funge::<I>()?;
+        let mut funge = start.clone();
+        let mut done = 0;
+        let instant = Instant::now();
+        while done < steps {
+            funge = match funge.step() {
+                Ok(funge) => funge,
+                Err(_) => start.clone()
+            };
+            done += 1;
+        }
+        Ok(BenchResult { name: self.name, steps: done, elapsed: instant.elapsed() })
+    }
+}
+
+
+pub fn run_all<I: Int>(steps: usize) -> Result<Vec<BenchResult>> {
+    WORKLOADS.iter().map(|workload| workload.run::<I>(steps)).collect()
+}
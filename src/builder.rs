@@ -0,0 +1,351 @@
+use anyhow::{Error, Result};
+use chrono::NaiveDateTime;
+use crate::{Argument, Execute, Finalizer, Funge, FungeError, Hook, Int, IO, IpName, LineEndings, Limits, NumberFormat, OnIpCap,
+            OnUnderflow, Reporter, Schedule, Timer, Validation, Wrap};
+use crate::trace::Tracer;
+
+
+enum Source {
+    Code(String),
+    File(String)
+}
+
+
+// collects settings without failing, build validates them all and names the option that is wrong
+pub struct FungeBuilder<I: Int> {
+    source: Option<Source>,
+    line_endings: LineEndings,
+    version: Option<String>,
+    ascii: Option<Validation>,
+    wrap: Option<Wrap>,
+    schedule: Option<Schedule>,
+    execute: Option<Execute>,
+    underflow: Option<OnUnderflow>,
+    limits: Option<Limits>,
+    max_ips: Option<(usize, OnIpCap)>,
+    deterministic: bool,
+    seed: Option<u64>,
+    clock: Option<NaiveDateTime>,
+    handprint: Option<i128>,
+    timer: Option<Timer<I>>,
+    env: Option<Vec<(String, String)>>,
+    argv: Option<Vec<String>>,
+    without: String,
+    network: bool,
+    input: Option<IO>,
+    typed_arguments: Vec<Argument>,
+    output: Option<IO>,
+    number_format: Option<NumberFormat>,
+    diagnostics: Option<IO>,
+    trace: Option<Tracer>,
+    ip_names: Vec<(usize, IpName)>,
+    hooks: Vec<Hook<I>>,
+    finalizers: Vec<Finalizer>,
+    reporter: Option<Reporter>,
+    literal_cache: bool
+}
+
+impl<I: Int> Default for FungeBuilder<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: Int> FungeBuilder<I> {
+    pub fn new() -> Self {
+        Self {
+            source: None,
+            line_endings: LineEndings::Normalize,
+            version: None,
+            ascii: None,
+            wrap: None,
+            schedule: None,
+            execute: None,
+            underflow: None,
+            limits: None,
+            max_ips: None,
+            deterministic: false,
+            seed: None,
+            clock: None,
+            handprint: None,
+            timer: None,
+            env: None,
+            argv: None,
+            without: String::new(),
+            network: true,
+            input: None,
+            typed_arguments: Vec::new(),
+            output: None,
+            number_format: None,
+            diagnostics: None,
+            trace: None,
+            ip_names: Vec::new(),
+            hooks: Vec::new(),
+            finalizers: Vec::new(),
+            reporter: None,
+            literal_cache: false
+        }
+    }
+
+    pub fn with_code<T: ToString>(mut self, code: T) -> Self {
+        self.source = Some(Source::Code(code.to_string()));
+        self
+    }
+
+    pub fn with_file<T: ToString>(mut self, file: T) -> Self {
+        self.source = Some(Source::File(file.to_string()));
+        self
+    }
+
+    pub fn with_line_endings(mut self, line_endings: LineEndings) -> Self {
+        self.line_endings = line_endings;
+        self
+    }
+
+    pub fn with_version<T: ToString>(mut self, version: T) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    pub fn check_ascii(mut self, validation: Validation) -> Self {
+        self.ascii = Some(validation);
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = Some(wrap);
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    pub fn with_execute(mut self, execute: Execute) -> Self {
+        self.execute = Some(execute);
+        self
+    }
+
+    pub fn with_underflow(mut self, on_underflow: OnUnderflow) -> Self {
+        self.underflow = Some(on_underflow);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn with_max_ips(mut self, max_ips: usize, on_ip_cap: OnIpCap) -> Self {
+        self.max_ips = Some((max_ips, on_ip_cap));
+        self
+    }
+
+    // seed, clock, timer and env set explicitly take precedence
+    pub fn deterministic(mut self) -> Self {
+        self.deterministic = true;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_clock(mut self, time: NaiveDateTime) -> Self {
+        self.clock = Some(time);
+        self
+    }
+
+    pub fn with_handprint(mut self, handprint: i128) -> Self {
+        self.handprint = Some(handprint);
+        self
+    }
+
+    pub fn with_timer(mut self, timer: Timer<I>) -> Self {
+        self.timer = Some(timer);
+        self
+    }
+
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    pub fn with_argv(mut self, argv: Vec<String>) -> Self {
+        self.argv = Some(argv);
+        self
+    }
+
+    pub fn without_instructions(mut self, ops: &str) -> Self {
+        self.without.push_str(ops);
+        self
+    }
+
+    pub fn without_network(mut self) -> Self {
+        self.network = false;
+        self
+    }
+
+    pub fn with_arguments(mut self, args: Vec<String>) -> Self {
+        self.input = Some(IO::new().with_store(args));
+        self
+    }
+
+    pub fn with_typed_arguments(mut self, arguments: Vec<Argument>) -> Self {
+        self.typed_arguments = arguments;
+        self
+    }
+
+    pub fn with_input(mut self, input: IO) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    pub fn with_output(mut self, output: IO) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    pub fn with_number_format(mut self, number_format: NumberFormat) -> Self {
+        self.number_format = Some(number_format);
+        self
+    }
+
+    pub fn with_diagnostics(mut self, diagnostics: IO) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    pub fn with_trace(mut self, trace: Tracer) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    pub fn with_ip_name(mut self, id: usize, name: IpName) -> Self {
+        self.ip_names.push((id, name));
+        self
+    }
+
+    pub fn with_hook(mut self, hook: Hook<I>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    pub fn with_finalizer(mut self, finalizer: Finalizer) -> Self {
+        self.finalizers.push(finalizer);
+        self
+    }
+
+    pub fn with_reporter(mut self, reporter: Reporter) -> Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    pub fn with_literal_cache(mut self) -> Self {
+        self.literal_cache = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Funge<I>> {
+        let invalid = |option: &'static str, error: Error| Error::new(FungeError::Option(option, error.to_string()));
+        let mut funge = match self.source {
+            Some(Source::Code(code)) => Funge::new_with_line_endings(code, self.line_endings)
+                .map_err(|error| invalid("code", error))?,
+            Some(Source::File(file)) => Funge::from_file_with_line_endings(&file, self.line_endings)
+                .map_err(|error| invalid("file", Error::msg(format!("{}: {}", file, error))))?,
+            None => Err(invalid("code", Error::msg("no code or file given")))?
+        };
+        if let Some(version) = self.version {
+            funge = funge.with_version(version).map_err(|error| invalid("version", error))?;
+        }
+        // before the source is checked, that warns through them
+        if let Some(diagnostics) = self.diagnostics {
+            funge = funge.with_diagnostics(diagnostics);
+        }
+        if let Some(validation) = self.ascii {
+            funge = funge.check_ascii(validation).map_err(|error| invalid("ascii", error))?;
+        }
+        if let Some(wrap) = self.wrap {
+            funge = funge.with_wrap(wrap);
+        }
+        if let Some(schedule) = self.schedule {
+            funge = funge.with_schedule(schedule);
+        }
+        if let Some(execute) = self.execute {
+            funge = funge.with_execute(execute);
+        }
+        if let Some(on_underflow) = self.underflow {
+            funge = funge.with_underflow(on_underflow);
+        }
+        if let Some(limits) = self.limits {
+            if limits.max_ips == Some(0) {
+                Err(invalid("limits", Error::msg("at least one IP is needed")))?
+            }
+            funge = funge.with_limits(limits);
+        }
+        if let Some((max_ips, on_ip_cap)) = self.max_ips {
+            if max_ips == 0 {
+                Err(invalid("max_ips", Error::msg("at least one IP is needed")))?
+            }
+            funge = funge.with_max_ips(max_ips, on_ip_cap);
+        }
+        if self.deterministic {
+            funge = funge.deterministic();
+        }
+        if let Some(seed) = self.seed {
+            funge = funge.with_seed(seed);
+        }
+        if let Some(clock) = self.clock {
+            funge = funge.with_clock(clock);
+        }
+        if let Some(handprint) = self.handprint {
+            funge = funge.with_handprint(handprint);
+        }
+        if let Some(timer) = self.timer {
+            funge = funge.with_timer(timer);
+        }
+        if let Some(env) = self.env {
+            funge = funge.with_env(env);
+        }
+        if let Some(argv) = self.argv {
+            funge = funge.with_argv(argv);
+        }
+        funge = funge.without_instructions(&self.without);
+        if !self.network {
+            funge = funge.without_network();
+        }
+        if let Some(input) = self.input {
+            funge = funge.with_input(input);
+        }
+        if !self.typed_arguments.is_empty() {
+            funge = funge.with_typed_arguments(self.typed_arguments);
+        }
+        if let Some(output) = self.output {
+            funge = funge.with_output(output);
+        }
+        if let Some(number_format) = self.number_format {
+            funge.output = funge.output.with_number_format(number_format);
+        }
+        for (id, name) in self.ip_names {
+            funge = funge.with_ip_name(id, name);
+        }
+        for hook in self.hooks {
+            funge = funge.with_hook(hook);
+        }
+        for finalizer in self.finalizers {
+            funge = funge.with_finalizer(finalizer);
+        }
+        if let Some(reporter) = self.reporter {
+            funge = funge.with_reporter(reporter);
+        }
+        if let Some(trace) = self.trace {
+            funge = funge.with_trace(trace);
+        }
+        if self.literal_cache {
+            funge = funge.with_literal_cache();
+        }
+        Ok(funge)
+    }
+}
@@ -0,0 +1,95 @@
+use std::{env, fs, fs::File, path::Path};
+use std::io::{Read, Seek, SeekFrom};
+use anyhow::{Error, Result};
+use rusty_funge::{ExitStatus, Funge};
+
+
+// appended to a copy of the interpreter: program, version, their lengths as u64, 1 for the distinct exit status and
+// this marker
+const MAGIC: &[u8; 8] = b"RFBUNDLE";
+const TRAILER: usize = 25;
+
+
+fn trailer(bytes: &[u8]) -> Option<(usize, usize, ExitStatus)> {
+    let len = |b: &[u8]| u64::from_le_bytes(b.try_into().unwrap()) as usize;
+    let exit_status = if bytes.get(16) == Some(&1) { ExitStatus::Distinct } else { ExitStatus::Simple };
+    (bytes.len() == TRAILER && &bytes[17..] == MAGIC).then(|| (len(&bytes[..8]), len(&bytes[8..16]), exit_status))
+}
+
+
+// the program, version and exit status embedded in the running executable, if it is a bundle, an executable that
+// cannot be read is the plain interpreter, which should still start
+pub(crate) fn embedded() -> Result<Option<(String, Option<String>, ExitStatus)>> {
+    let Ok(mut file) = env::current_exe().and_then(File::open) else {
+        return Ok(None)
+    };
+    let Ok(metadata) = file.metadata() else {
+        return Ok(None)
+    };
+    let size = metadata.len() as usize;
+    if size < TRAILER {
+        return Ok(None)
+    }
+    let mut bytes = vec![0; TRAILER];
+    if file.seek(SeekFrom::End(-(TRAILER as i64))).and_then(|_| file.read_exact(&mut bytes)).is_err() {
+        return Ok(None)
+    }
+    let Some((code_len, version_len, exit_status)) = trailer(&bytes) else {
+        return Ok(None)
+    };
+    let Some(payload_len) = code_len.checked_add(version_len).filter(|len| *len <= size - TRAILER) else {
+        Err(Error::msg("Corrupt bundle"))?
+    };
+    let mut payload = vec![0; payload_len];
+    file.seek(SeekFrom::End(-((payload_len + TRAILER) as i64)))?;
+    file.read_exact(&mut payload)?;
+    let version = payload.split_off(code_len);
+    let code = payload.into_iter().map(char::from).collect();
+    Ok(Some((code, (!version.is_empty()).then(|| version.into_iter().map(char::from).collect()), exit_status)))
+}
+
+
+// a copy of this executable with the program appended
+pub(crate) fn bundle(program: &Path, output: &Path, befunge: Option<String>, exit_status: ExitStatus) -> Result<()> {
+    let mut code = fs::read(program).map_err(|error| Error::msg(format!("{}: {}", program.display(), error)))?;
+    // the bundle has another name than the interpreter, so it would not recognize the shebang line
+    if code.starts_with(b"#!") {
+        code.drain(..code.iter().position(|b| *b == b'\n').map_or(code.len(), |n| n + 1));
+    }
+    let funge = Funge::<isize>::new(code.iter().map(|b| char::from(*b)).collect::<String>())?;
+    let version = match befunge {
+        Some(befunge) => {
+            let version = format!("B{}", befunge);
+            funge.with_version(&version)?;
+            version
+        }
+        None => String::new()
+    };
+    let mut exe = fs::read(env::current_exe()?)?;
+    exe.extend(&code);
+    exe.extend(version.as_bytes());
+    exe.extend((code.len() as u64).to_le_bytes());
+    exe.extend((version.len() as u64).to_le_bytes());
+    exe.push((exit_status == ExitStatus::Distinct) as u8);
+    exe.extend(MAGIC);
+    fs::write(output, exe)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(output, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+
+// a bundle runs its program with its command line as input for & and ~
+pub(crate) fn run(code: String, version: Option<String>) -> Result<i32> {
+    let mut funge = Funge::<isize>::new(code)?;
+    if let Some(version) = version {
+        funge = funge.with_version(version)?;
+    }
+    funge.with_arguments(env::args().skip(1).collect())
+        .with_env(env::vars().collect())
+        .with_argv(env::args().collect())
+        .run_exit_code()
+}
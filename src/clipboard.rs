@@ -0,0 +1,58 @@
+use std::{env, fs, path::{Path, PathBuf}};
+use anyhow::{Error, Result};
+use rusty_funge::{Int, convert::join};
+
+
+// a stack or a block of funge-space copied in one debugger session, to paste it in another:
+// a first line "stack" or "space x y", then a line of cells per row, the top of the stack last
+pub(crate) enum Clip<I: Int> {
+    Stack(Vec<I>),
+    Space(isize, isize, Vec<Vec<I>>)
+}
+
+impl<I: Int> Clip<I> {
+    // in a directory of the user rather than the shared temporary directory, where another user could put a link
+    // to one of your files in place of the clip, without such a directory a file has to be given
+    pub(crate) fn default_file() -> Result<PathBuf> {
+        let dir = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+            .or_else(|| env::var_os("XDG_CACHE_HOME").map(PathBuf::from))
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+            .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+            .ok_or(Error::msg("There is no directory of the user for the clip, give a file."))?
+            .join("rusty_funge");
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder.create(&dir)?;
+        Ok(dir.join("clip"))
+    }
+
+    pub(crate) fn save(&self, file: &Path) -> Result<()> {
+        let text = match self {
+            Clip::Stack(stack) => format!("stack\n{}\n", join(stack, " ")),
+            Clip::Space(x, y, lines) => format!("space {} {}\n{}\n", x, y,
+                                                join(&lines.iter().map(|line| join(line, " ")).collect::<Vec<_>>(), "\n"))
+        };
+        fs::write(file, text)?;
+        Ok(())
+    }
+
+    pub(crate) fn load(file: &Path) -> Result<Self> {
+        let text = fs::read_to_string(file)?;
+        let mut lines = text.lines();
+        let error = || Error::msg(format!("{} is not a clip.", file.display()));
+        let cells = |line: &str| line.split_whitespace()
+            .map(|cell| cell.parse().map_err(|_| Error::msg(format!("Cannot read cell {} from the clip.", cell))))
+            .collect::<Result<Vec<I>>>();
+        let header = lines.next().ok_or_else(error)?.split_whitespace().collect::<Vec<_>>();
+        match header[..] {
+            ["stack"] => Ok(Clip::Stack(cells(lines.next().unwrap_or_default())?)),
+            ["space", x, y] => Ok(Clip::Space(x.parse()?, y.parse()?, lines.map(cells).collect::<Result<_>>()?)),
+            _ => Err(error())
+        }
+    }
+}
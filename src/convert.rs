@@ -0,0 +1,130 @@
+use std::str::FromStr;
+use anyhow::{Error, Result};
+use num::NumCast;
+use crate::FungeError;
+
+
+pub fn join<T: ToString>(v: &[T], s: &str) -> String {
+    v.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(s)
+}
+
+// fails when the value does not fit
+pub fn cast<I: NumCast, J: NumCast>(j: J) -> Result<I> {
+    I::from(j).ok_or(Error::new(FungeError::Casting))
+}
+
+pub fn cast_vec<I: NumCast, J: NumCast>(j: Vec<J>) -> Result<Vec<I>> {
+    j.into_iter().map(cast).collect()
+}
+
+pub fn from_char<I: NumCast>(c: char) -> Result<I> {
+    cast::<_, u32>(c.into())
+}
+
+// fails for values that are not a unicode scalar value
+pub fn to_char<I: NumCast>(i: I) -> Result<char> {
+    Ok(cast::<u32, _>(i)?.try_into()?)
+}
+
+// printable latin-1 characters, ¤ for anything else
+pub fn to_char_lossy<I: NumCast + Copy>(i: I) -> char {
+    Printable::new().char(i)
+}
+
+pub fn cells_to_string<I: NumCast + Copy>(cells: Vec<I>) -> String {
+    Printable::new().show_cells(cells)
+}
+
+
+// what stands in for a cell that is not printable: a character, or an escape like \x07
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Substitute {
+    Char(char),
+    Hex
+}
+
+impl FromStr for Substitute {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Substitute::Char(c)),
+            _ if s.eq_ignore_ascii_case("hex") => Ok(Substitute::Hex),
+            _ => Err(Error::msg(format!("Expected a single character or hex: {}", s)))
+        }
+    }
+}
+
+
+// the values shown as themselves, as inclusive ranges of code points
+#[derive(Clone, Debug, PartialEq)]
+pub struct Printable {
+    pub ranges: Vec<(u32, u32)>,
+    pub substitute: Substitute
+}
+
+impl Default for Printable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Printable {
+    pub fn new() -> Self {
+        Self { ranges: vec![(32, 126), (161, 255)], substitute: Substitute::Char('¤') }
+    }
+
+    pub fn with_ranges(mut self, ranges: Vec<(u32, u32)>) -> Self {
+        self.ranges = ranges;
+        self
+    }
+
+    pub fn with_substitute(mut self, substitute: Substitute) -> Self {
+        self.substitute = substitute;
+        self
+    }
+
+    // "32-126,161-255", a single number is a range of one
+    pub fn parse_ranges(text: &str) -> Result<Vec<(u32, u32)>> {
+        text.split(',').map(|range| {
+            let error = || Error::msg(format!("Cannot parse range: {}", range));
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            Ok((start.trim().parse().map_err(|_| error())?, end.trim().parse().map_err(|_| error())?))
+        }).collect()
+    }
+
+    fn printable<I: NumCast + Copy>(&self, i: I) -> Option<char> {
+        let n = cast::<u32, I>(i).ok()?;
+        self.ranges.iter().any(|(start, end)| (*start..=*end).contains(&n)).then(|| char::from_u32(n)).flatten()
+    }
+
+    pub fn is_printable<I: NumCast + Copy>(&self, i: I) -> bool {
+        self.printable(i).is_some()
+    }
+
+    // always one character, so it can be used in a grid, a hex substitute shows as ¤
+    pub fn char<I: NumCast + Copy>(&self, i: I) -> char {
+        match (self.printable(i), self.substitute) {
+            (Some(c), _) => c,
+            (None, Substitute::Char(c)) => c,
+            (None, Substitute::Hex) => '¤'
+        }
+    }
+
+    pub fn show<I: NumCast + Copy>(&self, i: I) -> String {
+        match (self.printable(i), self.substitute) {
+            (Some(c), _) => c.to_string(),
+            (None, Substitute::Char(c)) => c.to_string(),
+            (None, Substitute::Hex) => match (cast::<u8, I>(i), cast::<u32, I>(i), cast::<i128, I>(i)) {
+                (Ok(n), _, _) => format!("\\x{:02x}", n),
+                (_, Ok(n), _) => format!("\\u{{{:x}}}", n),
+                (_, _, n) => format!("\\{{{}}}", n.unwrap_or_default())
+            }
+        }
+    }
+
+    pub fn show_cells<I: NumCast + Copy>(&self, cells: Vec<I>) -> String {
+        cells.into_iter().map(|i| self.show(i)).collect()
+    }
+}
@@ -1,27 +1,31 @@
-use std::cmp::{min, max};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::{fs, path::PathBuf};
 use std::thread::{spawn, sleep};
 use std::time::{Duration, Instant};
 use anyhow::{Error, Result};
 use cursive::{Cursive, CursiveExt, Printer, Vec2};
 use cursive::view::View;
-use cursive::theme::{BorderStyle, ColorStyle, Palette, Theme};
+use cursive::theme::{BaseColor, BorderStyle, Color, ColorStyle, Effect, Palette, Theme};
 use cursive::event::{Event, EventResult, Key};
-use cursive::views::{Dialog, EditView};
-use rusty_funge::{Int, Funge, join, ord, IO, cast_int, chr, Rect, IP};
+use cursive::traits::Nameable;
+use cursive::views::{Dialog, EditView, LinearLayout, TextView};
+use crate::clipboard::Clip;
+use crate::terminal::Terminal;
+use strum_macros::EnumString;
+use rusty_funge::{Int, Funge, IO, InputRequest, Rect, IP, IpName, SharedFunge, convert::{from_char, join, to_char, Printable, Substitute}, ops};
 
 
 #[derive(Clone)]
 struct FungeDelta<I: Int> {
-    code: HashMap<Vec<isize>, I>,
+    code: Vec<(Vec<isize>, I)>,
     ips: Vec<IP<I>>,
-    output: usize,
-    input: Vec<String>
+    output: String,
+    input: Option<Vec<String>>
 }
 
 impl<I: Int> FungeDelta<I> {
-    fn new(code: HashMap<Vec<isize>, I>, ips: Vec<IP<I>>, output: usize, input: Vec<String>) -> Self {
+    fn new(code: Vec<(Vec<isize>, I)>, ips: Vec<IP<I>>, output: String, input: Option<Vec<String>>) -> Self {
         Self { code, ips, output, input }
     }
 }
@@ -45,27 +49,14 @@ impl<I: Int> FungeHist<I> {
 
     fn push(&mut self, old: &Funge<I>, new: &Result<Funge<I>>) {
         if let Ok(new) = new {
-            let mut code = HashMap::new();
-            for (y, (line_old, line_new)) in old.code.orig_code.iter().zip(new.code.orig_code.iter()).enumerate() {
-                if line_old != line_new {
-                    for x in 0..min(line_old.len(), line_new.len()) {
-                        if line_old[x] != line_new[x] {
-                            code.insert(vec![x as isize, y as isize], line_old[x]);
-                        }
-                    }
-                    for x in min(line_old.len(), line_new.len())..max(line_old.len(), line_new.len()) {
-                        code.insert(vec![x as isize, y as isize], line_old[x]);
-                    }
-                }
-            }
-            for (pos, op) in &new.code.new_code {
-                if *op != old.code[pos] {
-                    code.insert(pos.to_owned(), *op);
-                }
-            }
+            let code = new.last_writes().to_vec();
             let ips = old.ips.clone();
-            let output = new.output.len() - old.output.len();
-            let input = old.input.store.to_owned().into_iter().rev().take(old.input.len() - new.input.len()).rev().collect();
+            let output = new.last_output().to_string();
+            let input = if old.input.store != new.input.store {
+                Some(old.input.store.clone())
+            } else {
+                None
+            };
             self.history.push(FungeDelta::new(code, ips, output, input));
             if self.len() > self.maxlen {
                 self.history.remove(0);
@@ -76,19 +67,35 @@ impl<I: Int> FungeHist<I> {
 
     }
 
+    // a cell written between steps, undone together with the step before it
+    fn push_write(&mut self, position: Vec<isize>, old: I) {
+        if let Some(delta) = self.history.last_mut() {
+            delta.code.push((position, old));
+        }
+    }
+
+    // the cells written in the last n steps
+    fn recent_writes(&self, n: usize) -> impl Iterator<Item = &[isize]> {
+        self.history.iter().rev().take(n).flat_map(|delta| delta.code.iter().map(|(pos, _)| pos.as_slice()))
+    }
+
     fn pop(&mut self, funge: Result<Funge<I>>) -> Funge<I> {
         match funge {
             Ok(mut funge) => {
                 match self.history.pop() {
                     Some(delta) => {
-                        for (pos, op) in delta.code {
+                        // undone from the last write, so the value from before the step remains
+                        for (pos, op) in delta.code.into_iter().rev() {
                             funge.code.insert(pos, op);
                         }
                         funge.ips = delta.ips;
-                        for _ in 0..delta.output {
-                            funge.output.store.pop();
+                        if !delta.output.is_empty() {
+                            let output = funge.output.get();
+                            funge.output.store = vec![output.strip_suffix(&delta.output).unwrap_or(&output).to_string()];
+                        }
+                        if let Some(input) = delta.input {
+                            funge.input.store = input;
                         }
-                        funge.input.store.extend(delta.input);
                         funge.steps -= 1;
                         funge
                     }
@@ -101,29 +108,232 @@ impl<I: Int> FungeHist<I> {
 }
 
 
+// top or top,bottom, empty or 0 for all cells
+pub(crate) fn parse_stack_cells(text: &str) -> Result<Option<(usize, usize)>> {
+    let error = || Error::msg(format!("Cannot parse stack cells: {}", text));
+    let numbers = text.split(',').map(str::trim).filter(|n| !n.is_empty())
+        .map(|n| n.parse::<usize>().map_err(|_| error())).collect::<Result<Vec<_>>>()?;
+    match numbers[..] {
+        [] | [0] | [0, 0] => Ok(None),
+        [top] => Ok(Some((top, 0))),
+        [top, bottom] => Ok(Some((top, bottom))),
+        _ => Err(error())
+    }
+}
+
+// a stack with only the cells from its top and bottom, the top is on the right
+fn stack_line<I: Int>(stack: &[I], cells: Option<(usize, usize)>) -> String {
+    match cells {
+        Some((top, bottom)) if stack.len() > top + bottom => {
+            let mut parts = stack[..bottom].iter().map(|cell| cell.to_string()).collect::<Vec<String>>();
+            parts.push(format!("… {} more", stack.len() - top - bottom));
+            parts.extend(stack[stack.len() - top..].iter().map(|cell| cell.to_string()));
+            format!("[{}]", join(&parts, ", "))
+        }
+        _ => format!("[{}]", join(stack, ", "))
+    }
+}
+
+
+#[derive(Default)]
+struct Annotations {
+    file: Option<PathBuf>,
+    notes: BTreeMap<(isize, isize), String>
+}
+
+impl Annotations {
+    // one note per line: x y text
+    fn load(file: PathBuf) -> Result<Self> {
+        let mut notes = BTreeMap::new();
+        if file.exists() {
+            for line in fs::read_to_string(&file)?.lines().filter(|line| !line.trim().is_empty()) {
+                let mut parts = line.trim().splitn(3, ' ');
+                match (parts.next().map(str::parse), parts.next().map(str::parse), parts.next()) {
+                    (Some(Ok(x)), Some(Ok(y)), Some(text)) => { notes.insert((x, y), text.to_string()); }
+                    _ => Err(Error::msg(format!("Cannot parse annotation: {}", line)))?
+                }
+            }
+        }
+        Ok(Self { file: Some(file), notes })
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(file) = &self.file {
+            fs::write(file, self.notes.iter().map(|((x, y), text)| format!("{} {} {}\n", x, y, text)).collect::<String>())?;
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, position: (isize, isize), text: &str) -> Result<()> {
+        match text.trim() {
+            "" => self.notes.remove(&position),
+            text => self.notes.insert(position, text.to_string())
+        };
+        self.save()
+    }
+
+    fn get(&self, position: &[isize]) -> Option<&String> {
+        self.notes.get(&(position[0], position[1]))
+    }
+}
+
+
+const DEPTH_HISTORY: usize = 48;
+const LOOKAHEAD: usize = 8;
+const SCRATCH_STEPS: usize = 10000;
+const CHECKPOINT: usize = 1024;
+const MIN_WIDTH: usize = 20;
+const MIN_HEIGHT: usize = 10;
+const FRAME: Duration = Duration::from_millis(16);
+const FRAME_BUDGET: Duration = Duration::from_millis(8);
+const RATE_WINDOW: Duration = Duration::from_millis(500);
+const PROGRESS: Duration = Duration::from_millis(200);
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const NEAR: isize = 2;
+const RECENT: usize = 16;
+
+
+// the factor for the interval in auto speed, above 1 slows down and below 1 speeds up
+type SpeedPolicy<I> = fn(&FungeDebug<I>) -> f64;
+// the cells that differ, with their values in both
+type CellDiff<I> = Vec<(Vec<isize>, I, I)>;
+
+fn near<'a, I: Int>(funge: &Funge<I>, mut positions: impl Iterator<Item = &'a [isize]>) -> bool {
+    positions.any(|pos| funge.ips.iter()
+        .any(|ip| (ip.position()[0] - pos[0]).abs() <= NEAR && (ip.position()[1] - pos[1]).abs() <= NEAR))
+}
+
+// speed up more the longer no step wrote output or cells
+fn speed_up<I: Int>(debug: &FungeDebug<I>) -> f64 {
+    1.0 / min(1 + debug.quiet / 64, 16) as f64
+}
+
+// slow down near the op to break on, near cells written in the last steps and when there is output,
+// speed up more the longer nothing like that happened
+fn default_speed<I: Int>(debug: &FungeDebug<I>) -> f64 {
+    let Some(Ok(funge)) = debug.funge.as_ref() else {
+        return 1.0
+    };
+    let near_break = debug.stop_op.is_some_and(|op| funge.ips.iter().any(|ip| (-NEAR..=NEAR)
+        .any(|dx| (-NEAR..=NEAR).any(|dy| funge.code[&vec![ip.position()[0] + dx, ip.position()[1] + dy]] == op))));
+    if near_break || debug.quiet == 0 || near(funge, debug.history.recent_writes(RECENT)) {
+        4.0
+    } else {
+        speed_up(debug)
+    }
+}
+
+// slow down only when there is output or cells are written, wherever the IPs are
+fn quiet_speed<I: Int>(debug: &FungeDebug<I>) -> f64 {
+    if debug.quiet == 0 {
+        4.0
+    } else {
+        speed_up(debug)
+    }
+}
+
+
+// the policies for auto speed that can be chosen on the command line
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+pub(crate) enum Speed {
+    Near,
+    Quiet
+}
+
+impl Speed {
+    fn policy<I: Int>(self) -> SpeedPolicy<I> {
+        match self {
+            Speed::Near => default_speed,
+            Speed::Quiet => quiet_speed
+        }
+    }
+}
+
+
+
+// a state forked from another with its own history, to see what happens when a cell is different
+struct Timeline<I: Int> {
+    id: usize,
+    funge: Option<Result<Funge<I>>>,
+    history: FungeHist<I>,
+    depths: HashMap<usize, VecDeque<usize>>,
+    commands: Vec<(isize, usize, usize, String)>
+}
+
+
 struct FungeDebug<I: Int> {
     funge: Option<Result<Funge<I>>>,
     history: FungeHist<I>,
     interval: f64,
     running: bool,
-    stop_op: Option<I>
+    stop_op: Option<I>,
+    stop_ip: Option<String>,
+    depths: HashMap<usize, VecDeque<usize>>,
+    break_underflow: bool,
+    annotations: Annotations,
+    start: Funge<I>,
+    origin: String,
+    source: String,
+    reload_pending: bool,
+    show_diff: bool,
+    terminal: bool,
+    rate: Option<f64>,
+    // the cells shown from the top and the bottom of each stack, None shows them all
+    stack_cells: Option<(usize, usize)>,
+    // the step, where in the output and which command =, to mark the output of the commands
+    commands: Vec<(isize, usize, usize, String)>,
+    // steps since the last one that wrote output or cells
+    quiet: usize,
+    auto_speed: Option<SpeedPolicy<I>>,
+    // the policy ^w switches auto speed on with
+    speed_policy: SpeedPolicy<I>,
+    // the timeline in funge and history, and the others in the order they are switched to
+    timeline: usize,
+    timelines: VecDeque<Timeline<I>>,
+    show_timeline_diff: bool
 }
 
 impl<I: Int> FungeDebug<I> {
     fn new(funge: Funge<I>) -> Self {
         Self {
+            start: funge.clone(),
+            origin: String::new(),
+            source: String::new(),
+            reload_pending: false,
+            show_diff: false,
+            terminal: false,
+            rate: None,
+            stack_cells: Some((16, 4)),
+            commands: Vec::new(),
+            quiet: 0,
+            auto_speed: None,
+            speed_policy: default_speed,
+            timeline: 1,
+            timelines: VecDeque::new(),
+            show_timeline_diff: false,
             funge: Some(Ok(funge)),
             history: FungeHist::new(),
             interval: 0.05,
             running: false,
-            stop_op: None
+            stop_op: None,
+            stop_ip: None,
+            depths: HashMap::new(),
+            break_underflow: false,
+            annotations: Annotations::default()
         }
     }
 
     fn step_back(&mut self) {
         self.running = false;
         if let Some(new) = self.funge.take() {
-            self.funge = Some(Ok(self.history.pop(new)));
+            let funge = self.history.pop(new);
+            self.commands.retain(|(step, ..)| *step <= funge.steps);
+            self.funge = Some(Ok(funge));
+            for depths in self.depths.values_mut() {
+                depths.pop_back();
+            }
+            self.depths.retain(|_, depths| !depths.is_empty());
         }
     }
 
@@ -133,17 +343,257 @@ impl<I: Int> FungeDebug<I> {
                 let old = funge.clone();
                 let new = funge.step();
                 self.history.push(&old, &new);
+                if let Ok(new) = &new {
+                    self.record_depths(new);
+                    self.quiet = match new.last_output().is_empty() && new.last_writes().is_empty() {
+                        true => self.quiet + 1,
+                        false => 0
+                    };
+                    if let Some(command) = new.last_command() {
+                        let end = new.output.get().len();
+                        self.commands.push((new.steps, end - new.last_output().len(), end, command.to_string()));
+                    }
+                    if self.break_underflow && new.underflow_count() > old.underflow_count() {
+                        self.running = false;
+                    }
+                }
                 Some(new)
             }
             funge => funge
         }
     }
+
+    // skips steps without recording history, only a step that fails is kept so that it can be stepped back, the
+    // funge from before that step is replayed from a checkpoint, taken every so many steps and after each step
+    // that ran a command or read input, so that those are not done twice
+    fn fast_forward(&mut self, n: usize) {
+        let start = Instant::now();
+        let mut shown = start;
+        let mut checkpoint = None;
+        let mut since = 0;
+        for done in 1..=n {
+            match self.funge.take() {
+                Some(Ok(funge)) => {
+                    if checkpoint.is_none() || since == CHECKPOINT {
+                        checkpoint = Some(funge.clone());
+                        since = 0;
+                    }
+                    let input = funge.input.store.len();
+                    let new = funge.step();
+                    match (&new, checkpoint.take()) {
+                        (Err(_), Some(checkpoint)) => {
+                            let old = (0..since).try_fold(checkpoint.clone(), |funge, _| funge.step())
+                                .unwrap_or(checkpoint);
+                            self.history.push(&old, &new);
+                        }
+                        (Ok(new), _) if new.last_command().is_some() || new.input.store.len() != input => {}
+                        (_, kept) => {
+                            checkpoint = kept;
+                            since += 1;
+                        }
+                    }
+                    self.funge = Some(new);
+                }
+                funge => {
+                    self.funge = funge;
+                    break
+                }
+            }
+            if shown.elapsed() >= PROGRESS {
+                eprint!("\rskipping steps: {}/{}", done, n);
+                shown = Instant::now();
+            }
+        }
+        if shown != start {
+            eprintln!();
+        }
+        // a funge that failed stays, so that it can be stepped back
+        self.funge = match self.funge.take() {
+            Some(Ok(funge)) => {
+                self.record_depths(&funge);
+                Some(Ok(funge))
+            }
+            funge => funge
+        };
+    }
+
+    // reset to the start with the new source, or patch only the changed cells keeping stacks and IPs
+    fn reload(&mut self, source: String, reset: bool) -> Result<usize> {
+        self.running = false;
+        self.reload_pending = false;
+        let mut start = self.start.clone();
+        let reset_changed = start.patch_code(&self.origin, &source)?;
+        let changed = match self.funge.as_mut() {
+            Some(Ok(funge)) if !reset => funge.patch_code(&self.source, &source)?,
+            _ => {
+                self.funge = Some(Ok(start.clone()));
+                self.history = FungeHist::new();
+                self.depths.clear();
+                self.commands.clear();
+                reset_changed
+            }
+        };
+        self.start = start;
+        self.origin = source.clone();
+        self.source = source;
+        Ok(changed)
+    }
+
+    fn effective_interval(&self) -> f64 {
+        match self.auto_speed {
+            Some(policy) => self.interval * policy(self),
+            None => self.interval
+        }
+    }
+
+    // the output with the output of commands run by = between markers
+    fn marked_output(&self, funge: &Funge<I>) -> String {
+        let output = funge.output.get();
+        let mut marked = String::new();
+        let mut last = 0;
+        for (_, start, end, command) in self.commands.iter().filter(|(_, _, end, _)| *end <= output.len()) {
+            marked.push_str(&output[last..*start]);
+            marked.push_str(&format!("«= {}»{}«/=»", command, &output[*start..*end]));
+            last = *end;
+        }
+        marked.push_str(&output[last..]);
+        marked
+    }
+
+    // the current timeline continues as a copy, with a cell written if given, the original is kept to switch to
+    fn fork(&mut self, cell: Option<(isize, isize, I)>) -> Result<()> {
+        let Some(Ok(funge)) = self.funge.as_ref() else {
+            Err(Error::msg("A funge that failed cannot be forked."))?
+        };
+        let mut fork = funge.clone();
+        let written = match cell {
+            Some((x, y, op)) => {
+                let old = fork.code[&vec![x, y]];
+                fork.insert_block(&[vec![op]], x, y)?;
+                Some((vec![x, y], old))
+            }
+            None => None
+        };
+        self.running = false;
+        self.timelines.push_back(Timeline {
+            id: self.timeline,
+            funge: self.funge.replace(Ok(fork)),
+            history: self.history.clone(),
+            depths: self.depths.clone(),
+            commands: self.commands.clone()
+        });
+        // only in the history of the fork, stepping back past the fork there undoes the write
+        if let Some((position, old)) = written {
+            self.history.push_write(position, old);
+        }
+        self.timeline = self.timelines.iter().map(|timeline| timeline.id).max().unwrap_or(0) + 1;
+        Ok(())
+    }
+
+    fn switch_timeline(&mut self) {
+        if let Some(next) = self.timelines.pop_front() {
+            self.running = false;
+            self.timelines.push_back(Timeline {
+                id: self.timeline,
+                funge: std::mem::replace(&mut self.funge, next.funge),
+                history: std::mem::replace(&mut self.history, next.history),
+                depths: std::mem::replace(&mut self.depths, next.depths),
+                commands: std::mem::replace(&mut self.commands, next.commands)
+            });
+            self.timeline = next.id;
+        }
+    }
+
+    // the cells that differ from the timeline that is next
+    fn timeline_diff(&self) -> Option<(usize, CellDiff<I>)> {
+        match (self.funge.as_ref(), self.timelines.front()) {
+            (Some(Ok(funge)), Some(Timeline { id, funge: Some(Ok(other)), .. })) => Some((*id, funge.code.diff(&other.code))),
+            _ => None
+        }
+    }
+
+    // self-modifications compared to the program as loaded
+    fn diff(&self) -> Vec<(Vec<isize>, I, I)> {
+        match self.funge.as_ref() {
+            Some(Ok(funge)) => funge.code.diff(&self.start.code),
+            _ => Vec::new()
+        }
+    }
+
+    fn export_diff(&self, file: &str) -> Result<usize> {
+        match self.funge.as_ref() {
+            Some(Ok(funge)) => {
+                fs::write(file, funge.code.diff_listing(&self.start.code))?;
+                Ok(self.diff().len())
+            }
+            _ => Err(Error::msg("There is no funge to compare."))
+        }
+    }
+
+    fn is_running(&mut self) -> bool {
+        let running = if !self.running {
+            false
+        } else {
+            match self.funge.as_ref() {
+                Some(Ok(f)) => {
+                    if let Some(op) = self.stop_op {
+                        let mut running = true;
+                        for ip in &f.ips {
+                            let watched = match &self.stop_ip {
+                                Some(stop_ip) => ip.id.to_string() == *stop_ip
+                                    || f.ip_name(ip.id).is_some_and(|name| name.name == *stop_ip),
+                                None => true
+                            };
+                            if watched && f.code[&ip.position().to_vec()] == op {
+                                self.stop_op = None;
+                                running = false;
+                                break
+                            }
+                        }
+                        running
+                    } else {
+                        true
+                    }
+                }
+                _ => false
+            }
+        };
+        if !running {
+            self.running = false
+        }
+        running
+    }
+
+    fn record_depths(&mut self, funge: &Funge<I>) {
+        for ip in &funge.ips {
+            let depths = self.depths.entry(ip.id).or_default();
+            if depths.len() == DEPTH_HISTORY {
+                depths.pop_front();
+            }
+            depths.push_back(ip.stack_depth());
+        }
+    }
+
+    fn sparkline(&self, id: usize) -> String {
+        match self.depths.get(&id) {
+            Some(depths) => {
+                let max = depths.iter().max().copied().unwrap_or_default().max(1);
+                depths.iter().map(|depth| SPARKS[depth * (SPARKS.len() - 1) / max]).collect()
+            }
+            None => String::new()
+        }
+    }
 }
 
 
-fn input_dialog() -> Result<String> {
+fn read_source(file: &PathBuf) -> Result<String> {
+    Ok(fs::read(file)?.into_iter().map(char::from).collect())
+}
+
+
+fn input_dialog(request: &InputRequest) -> Result<String> {
     let mut app = Cursive::new();
-    app.add_layer(Dialog::new().title("Funge is asking for input").content(EditView::new()));
+    app.add_layer(Dialog::new().title(request.to_string()).content(EditView::new()));
     app.add_global_callback(Key::Enter, |app| app.quit());
     app.set_theme(Theme { shadow: false, borders: BorderStyle::None, palette: Palette::default() });
     app.run();
@@ -159,86 +609,172 @@ fn input_dialog() -> Result<String> {
 
 
 pub(crate) struct FungeView<I: Int> {
-    funge: Arc<Mutex<FungeDebug<I>>>
+    funge: SharedFunge<FungeDebug<I>>,
+    source_file: Option<PathBuf>,
+    printable: Printable
 }
 
 impl<I: Int> FungeView<I> {
     pub (crate) fn new(funge: Funge<I>, input: Vec<String>) -> Result<Self> {
-        Ok(FungeView { funge: Arc::new(Mutex::new(FungeDebug::new(
+        let number_format = funge.output.number_format();
+        Ok(FungeView { funge: SharedFunge::new(FungeDebug::new(
             funge.with_input(IO::new()
                 .with_store(input)
-                .with_input(|store| {
+                .with_prompt(|store, request| {
                     Ok(match store.pop() {
-                        None => input_dialog()?,
+                        None => input_dialog(request)?,
                         Some(s) => s
                     })
                 })).with_output(IO::new()
+                .with_number_format(number_format)
+                .with_output(|store, s| {
+                    store.push(s);
+                    Ok(())
+                })).with_diagnostics(IO::new()
                 .with_output(|store, s| {
-                    Ok(store.push(s))
-                })))))
+                    store.push(s);
+                    Ok(())
+                })))), source_file: None, printable: Printable::new()
         })
     }
 
+    pub(crate) fn with_stack_cells(self, cells: Option<(usize, usize)>) -> Result<Self> {
+        self.funge.update(|funge| funge.stack_cells = cells)?;
+        Ok(self)
+    }
+
+    // start with auto speed on
+    pub(crate) fn with_auto_speed(self, speed: Speed) -> Result<Self> {
+        self.funge.update(|funge| {
+            funge.speed_policy = speed.policy();
+            funge.auto_speed = Some(funge.speed_policy);
+        })?;
+        Ok(self)
+    }
+
+    pub(crate) fn with_printable(mut self, printable: Printable) -> Self {
+        self.printable = printable;
+        self
+    }
+
+    pub(crate) fn with_source(mut self, file: PathBuf) -> Result<Self> {
+        let source = read_source(&file)?;
+        self.funge.update(|funge| {
+            funge.origin = source.clone();
+            funge.source = source;
+        })?;
+        self.source_file = Some(file);
+        Ok(self)
+    }
+
+    fn reload_dialog(funge: SharedFunge<FungeDebug<I>>, file: &PathBuf) -> Dialog {
+        let source = match read_source(file) {
+            Ok(source) => source,
+            Err(error) => return Dialog::info(format!("Could not read {}: {}", file.display(), error))
+        };
+        if funge.observe(|funge| funge.source == source).unwrap_or(true) {
+            return Dialog::info("The source did not change.")
+        }
+        let _ = funge.update(|funge| funge.reload_pending = true);
+        let (reset, patch, ignore) = (funge.clone(), funge.clone(), funge);
+        let reset_source = source.clone();
+        Dialog::text(format!("{} changed on disk. Reset and run it from the start, or only patch the changed \
+                              cells and keep the stacks and IPs?", file.display()))
+            .title("Reload")
+            .button("Reset", move |app| {
+                let _ = reset.update(|funge| funge.reload(reset_source.clone(), true));
+                app.pop_layer();
+            })
+            .button("Patch", move |app| {
+                let _ = patch.update(|funge| funge.reload(source.clone(), false));
+                app.pop_layer();
+            })
+            .button("Ignore", move |app| {
+                let _ = ignore.update(|funge| funge.reload_pending = false);
+                app.pop_layer();
+            })
+    }
+
+    #[cfg(feature = "watch")]
+    fn watch(&self, sink: cursive::CbSink) -> Option<notify::RecommendedWatcher> {
+        use notify::{Watcher, RecursiveMode};
+        let file = self.source_file.clone()?;
+        let funge = self.funge.clone();
+        let name = file.file_name()?.to_owned();
+        let path = file.clone();
+        // watch the directory, editors often replace the file instead of writing to it
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                let pending = funge.observe(|funge| funge.reload_pending).unwrap_or(true);
+                if !pending && event.kind.is_modify() | event.kind.is_create()
+                    && event.paths.iter().any(|p| p.file_name() == Some(&name)) {
+                    let (funge, path) = (funge.clone(), path.clone());
+                    let _ = sink.send(Box::new(move |app| {
+                        let dialog = Self::reload_dialog(funge, &path);
+                        if dialog.buttons_len() > 1 {
+                            app.add_layer(dialog);
+                        }
+                    }));
+                }
+            }
+        }).ok()?;
+        let dir = match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from(".")
+        };
+        watcher.watch(&dir, RecursiveMode::NonRecursive).ok()?;
+        Some(watcher)
+    }
+
+    pub(crate) fn with_annotations(self, file: PathBuf) -> Result<Self> {
+        let annotations = Annotations::load(file)?;
+        self.funge.update(|funge| funge.annotations = annotations)?;
+        Ok(self)
+    }
+
     fn step_back(&mut self) {
-        if let Ok(mut funge) = self.funge.lock() {
+        if let Ok(mut funge) = self.funge.write() {
             funge.step_back()
         }
     }
 
     fn step(&mut self) {
-        if let Ok(mut funge) = self.funge.lock() {
+        if let Ok(mut funge) = self.funge.write() {
             funge.step();
         }
     }
 
     pub fn step_n(&mut self, n: usize) {
-        if let Ok(mut funge) = self.funge.lock() {
-            for _ in 0..n {
-                funge.step();
-            }
+        if let Ok(mut funge) = self.funge.write() {
+            funge.fast_forward(n);
         }
     }
 
     fn new_mutex(&self) -> Self {
-        Self { funge: Arc::clone(&self.funge) }
+        Self { funge: self.funge.clone(), source_file: self.source_file.clone(), printable: self.printable.clone() }
     }
 
-    fn is_running(&self) -> bool {
-        match self.funge.lock() {
+    // steps that are due, but only as many as fit in the budget, so that the lock is not held for long
+    fn run_batch(&self, due: usize, budget: Duration) -> (usize, bool) {
+        let instant = Instant::now();
+        match self.funge.write() {
             Ok(mut funge) => {
-                let running = if !funge.running {
-                    false
-                } else {
-                    match funge.funge.as_ref() {
-                        Some(Ok(f)) => {
-                            if let Some(op) = funge.stop_op {
-                                let mut running = true;
-                                for pos in f.ips_pos() {
-                                    if f.code[&pos] == op {
-                                        funge.stop_op = None;
-                                        running = false;
-                                        break
-                                    }
-                                }
-                                running
-                            } else {
-                                true
-                            }
-                        }
-                        _ => false
+                let mut steps = 0;
+                while steps < due && instant.elapsed() < budget {
+                    funge.step();
+                    steps += 1;
+                    if !funge.is_running() {
+                        return (steps, false)
                     }
-                };
-                if !running {
-                    funge.running = false
                 }
-                running
+                (steps, true)
             }
-            _ => false
+            _ => (0, false)
         }
     }
 
     fn toggle_run(&self) {
-        let running = { self.funge.lock().unwrap().running };
+        let running = { self.funge.read().unwrap().running };
         match running {
             true => self.pause(),
             false => self.run()
@@ -246,27 +782,37 @@ impl<I: Int> FungeView<I> {
     }
 
     fn pause(&self) {
-        self.funge.lock().unwrap().running = false;
+        self.funge.write().unwrap().running = false;
     }
 
+    // each frame runs the steps that became due since the last one, within a budget that leaves the UI responsive
     fn run(&self) {
-        let mut funge = self.new_mutex();
-        { funge.funge.lock().unwrap().running = true; }
+        let funge = self.new_mutex();
+        { funge.funge.write().unwrap().running = true; }
         spawn(move || {
+            let mut due = 1.0;
+            let mut last = Instant::now();
+            let (mut window, mut counted) = (Instant::now(), 0);
             loop {
-                let instant = Instant::now();
-                funge.step();
-                let duration = Duration::from_micros(match funge.funge.lock() {
-                    Ok(f) => (f.interval * 1e6) as u64,
-                    Err(_) => 100000
-                });
-                if !funge.is_running() {
+                let frame = Instant::now();
+                let interval = funge.funge.read().map(|f| f.effective_interval()).unwrap_or(0.1);
+                due += last.elapsed().as_secs_f64() / interval;
+                last = Instant::now();
+                let (steps, running) = funge.run_batch(due as usize, FRAME_BUDGET);
+                if !running {
+                    let _ = funge.funge.update(|funge| funge.rate = None);
                     break
                 }
-                let elapsed = instant.elapsed();
-                if duration > elapsed {
-                    sleep(duration - elapsed)
+                // do not catch up on steps that did not fit in the budget
+                due = if steps < due as usize { due.fract() } else { due - steps as f64 };
+                counted += steps;
+                if window.elapsed() >= RATE_WINDOW {
+                    let rate = counted as f64 / window.elapsed().as_secs_f64();
+                    let _ = funge.funge.update(|funge| funge.rate = Some(rate));
+                    (window, counted) = (Instant::now(), 0);
                 }
+                let next = Duration::from_secs_f64(((1.0 - due) * interval).max(0.0));
+                sleep(next.max(FRAME.saturating_sub(frame.elapsed())));
             }
         });
     }
@@ -274,9 +820,11 @@ impl<I: Int> FungeView<I> {
     pub(crate) fn debug(self, interval: Option<f64>) {
         let mut app = Cursive::new();
         if let Some(interval) = interval {
-            { self.funge.lock().unwrap().interval = interval; }
+            { self.funge.write().unwrap().interval = interval; }
             self.toggle_run();
         }
+        #[cfg(feature = "watch")]
+        let _watcher = self.watch(app.cb_sink().clone());
         app.add_layer(self);
         app.add_global_callback(Key::Esc, |app| app.quit());
         app.set_autorefresh(true);
@@ -284,17 +832,209 @@ impl<I: Int> FungeView<I> {
         app.run();
     }
 
-    fn wrap(string: String, width: usize) -> Vec<String> {
-        let mut lines = Vec::new();
-        let mut _a: &str = "";
-        for mut line in string.lines() {
-            while line.len() > width {
-                (_a, line) = line.split_at(width);
-                lines.push(_a.to_string());
+    fn explain(&self) -> String {
+        if let Ok(funge) = self.funge.read() {
+            if let Some(Ok(funge)) = funge.funge.as_ref() {
+                if let Some(pos) = funge.ips_pos().first() {
+                    return match to_char(funge.code[pos]).ok().and_then(ops::explain) {
+                        Some(info) => info.to_string(),
+                        None => format!("{} is not an instruction", funge.code[pos])
+                    }
+                }
+            }
+        }
+        String::from("Nothing to explain")
+    }
+
+    fn name_dialog(&self) -> Dialog {
+        let funge = self.funge.clone();
+        Dialog::new().title("Name IP: id name[:color]").content(EditView::new().on_submit(move |app, text| {
+            if let Some((id, name)) = text.trim().split_once(' ') {
+                if let (Ok(id), Some(name)) = (id.parse(), IpName::parse(name)) {
+                    let _ = funge.update(|funge| if let Some(Ok(funge)) = funge.funge.as_mut() {
+                        funge.name_ip(id, name)
+                    });
+                }
             }
-            if line.len() > 0 {
-                lines.push(line.to_string());
+            app.pop_layer();
+        }))
+    }
+
+    fn annotate_dialog(&self) -> Dialog {
+        let funge = self.funge.clone();
+        let position = self.funge.observe(|funge| match funge.funge.as_ref() {
+            Some(Ok(funge)) => funge.ips.first().map(|ip| (ip.position()[0], ip.position()[1])),
+            _ => None
+        }).ok().flatten().unwrap_or_default();
+        let note = self.funge.observe(|funge| funge.annotations.notes.get(&position).cloned()).ok().flatten();
+        Dialog::new().title(format!("Annotate ({}, {}), empty to remove", position.0, position.1))
+            .content(EditView::new().content(note.unwrap_or_default()).on_submit(move |app, text| {
+                let result = funge.update(|funge| funge.annotations.set(position, text));
+                app.pop_layer();
+                if let Ok(Err(error)) = result {
+                    app.add_layer(Dialog::info(format!("Could not save annotations: {}", error)));
+                }
+            }))
+    }
+
+    fn export_dialog(&self) -> Dialog {
+        let funge = self.funge.clone();
+        let file = match &self.source_file {
+            Some(file) => format!("{}.diff", file.display()),
+            None => String::from("funge.diff")
+        };
+        Dialog::new().title("Export changes to").content(EditView::new().content(file).on_submit(move |app, text| {
+            let result = funge.observe(|funge| funge.export_diff(text.trim()));
+            app.pop_layer();
+            match result {
+                Ok(Ok(n)) => app.add_layer(Dialog::info(format!("Exported {} changed cells.", n))),
+                Ok(Err(error)) => app.add_layer(Dialog::info(format!("Could not export changes: {}", error))),
+                _ => {}
+            }
+        }))
+    }
+
+    fn stack_dialog(&self) -> Dialog {
+        let funge = self.funge.clone();
+        let cells = match self.funge.observe(|funge| funge.stack_cells).ok().flatten() {
+            Some((top, bottom)) => format!("{},{}", top, bottom),
+            None => String::new()
+        };
+        Dialog::new().title("Stack cells shown from the top,bottom (empty for all)")
+            .content(EditView::new().content(cells).on_submit(move |app, text| {
+                app.pop_layer();
+                match parse_stack_cells(text) {
+                    Ok(cells) => { let _ = funge.update(|funge| funge.stack_cells = cells); }
+                    Err(error) => app.add_layer(Dialog::info(error.to_string()))
+                }
+            }))
+    }
+
+    // ops typed here run on a copy of the stacks of the first IP, the program itself is not touched
+    fn scratch_dialog(&self) -> Dialog {
+        let funge = self.funge.clone();
+        let log = TextView::new("").with_name("scratch log");
+        let edit = EditView::new().on_submit(move |app, text| {
+            let line = funge.observe(|debug| match debug.funge.as_ref() {
+                Some(Ok(funge)) => match funge.scratch(0, text, SCRATCH_STEPS) {
+                    Ok((stack, output)) if output.is_empty() => format!("{} → {}\n", text, stack_line(&stack, debug.stack_cells)),
+                    Ok((stack, output)) => format!("{} → {}, output: {:?}\n", text, stack_line(&stack, debug.stack_cells), output),
+                    Err(error) => format!("{} → {}\n", text, error)
+                }
+                _ => String::from("No IP to copy the stack from.\n")
+            }).unwrap_or_default();
+            app.call_on_name("scratch log", |log: &mut TextView| log.append(line));
+            app.call_on_name("scratch code", |edit: &mut EditView| edit.set_content(""));
+        }).with_name("scratch code");
+        Dialog::new().title("Scratchpad: run ops on a copy of the stack")
+            .content(LinearLayout::vertical().child(log).child(edit))
+            .dismiss_button("Close")
+    }
+
+    // copies the stack of the first IP, or a block given as x,y,width,height, to a file another session can paste
+    fn copy_dialog(&self) -> Dialog {
+        let funge = self.funge.clone();
+        Dialog::new().title("Copy stack or x,y,width,height to [file]")
+            .content(EditView::new().content("stack").on_submit(move |app, text| {
+                let mut words = text.split_whitespace();
+                let what = words.next().unwrap_or("stack").to_string();
+                let file = words.next().map(PathBuf::from);
+                let result = funge.observe(|debug| -> Result<String> {
+                    let file = match file {
+                        Some(file) => file,
+                        None => Clip::<I>::default_file()?
+                    };
+                    let Some(Ok(funge)) = debug.funge.as_ref() else {
+                        Err(Error::msg("There is no funge to copy from."))?
+                    };
+                    let clip = match what.as_str() {
+                        "stack" => Clip::Stack(funge.ips.first().ok_or(Error::msg("There is no IP."))?.stack()),
+                        rect => match rect.split(',').map(|i| i.trim().parse()).collect::<Result<Vec<isize>, _>>()?[..] {
+                            [x, y, width, height] => Clip::Space(x, y, funge.code.region(&Rect::new(x, x + width, y, y + height))),
+                            _ => Err(Error::msg("Give stack or x,y,width,height."))?
+                        }
+                    };
+                    clip.save(&file)?;
+                    Ok(format!("Copied {} to {}.", what, file.display()))
+                });
+                app.pop_layer();
+                match result {
+                    Ok(Ok(message)) => app.add_layer(Dialog::info(message)),
+                    Ok(Err(error)) => app.add_layer(Dialog::info(format!("Could not copy: {}", error))),
+                    _ => {}
+                }
+            }))
+    }
+
+    // a stack is pushed on that of the first IP, a block of funge-space goes where it was copied from
+    fn paste_dialog(&self) -> Dialog {
+        let funge = self.funge.clone();
+        Dialog::new().title("Paste from")
+            .content(EditView::new().content(Clip::<I>::default_file().map(|file| file.display().to_string()).unwrap_or_default()).on_submit(move |app, text| {
+                let result = Clip::load(&PathBuf::from(text.trim())).and_then(|clip| {
+                    funge.update(|debug| -> Result<String> {
+                        let Some(Ok(funge)) = debug.funge.as_mut() else {
+                            Err(Error::msg("There is no funge to paste in."))?
+                        };
+                        match clip {
+                            Clip::Stack(stack) => {
+                                let n = stack.len();
+                                funge.ips.first_mut().ok_or(Error::msg("There is no IP."))?.push_cells(stack);
+                                Ok(format!("Pushed {} cells.", n))
+                            }
+                            Clip::Space(x, y, lines) => {
+                                funge.insert_block(&lines, x, y)?;
+                                Ok(format!("Pasted {} lines at ({}, {}).", lines.len(), x, y))
+                            }
+                        }
+                    })?
+                });
+                app.pop_layer();
+                match result {
+                    Ok(message) => app.add_layer(Dialog::info(message)),
+                    Err(error) => app.add_layer(Dialog::info(format!("Could not paste: {}", error)))
+                }
+            }))
+    }
+
+    fn fork_dialog(&self) -> Dialog {
+        let funge = self.funge.clone();
+        Dialog::new().title("Fork, writing x,y,char in the fork (empty for none)").content(EditView::new().on_submit(move |app, text| {
+            let cell = match text.trim().splitn(3, ',').collect::<Vec<_>>()[..] {
+                [""] => Ok(None),
+                [x, y, c] if c.chars().count() == 1 => match (x.trim().parse(), y.trim().parse(), from_char(c.chars().next().unwrap_or(' '))) {
+                    (Ok(x), Ok(y), Ok(op)) => Ok(Some((x, y, op))),
+                    _ => Err(Error::msg("Give x,y,char."))
+                }
+                _ => Err(Error::msg("Give x,y,char."))
+            };
+            let result = cell.and_then(|cell| funge.update(|funge| funge.fork(cell))?);
+            app.pop_layer();
+            if let Err(error) = result {
+                app.add_layer(Dialog::info(format!("Could not fork: {}", error)));
             }
+        }))
+    }
+
+    fn break_dialog(&self) -> Dialog {
+        let funge = self.funge.clone();
+        Dialog::new().title("Only break on IP (id or name, empty for all)").content(EditView::new().on_submit(move |app, text| {
+            let text = text.trim();
+            let _ = funge.update(|funge| funge.stop_ip = Some(text.to_string()).filter(|text| !text.is_empty()));
+            app.pop_layer();
+        }))
+    }
+
+    fn cell_char(&self, funge: &Funge<I>, position: &[isize]) -> String {
+        self.printable.char(funge.code[&position.to_vec()]).to_string()
+    }
+
+    fn wrap(string: String, width: usize) -> Vec<String> {
+        let width = max(width, 1);
+        let mut lines = Vec::new();
+        for line in string.lines() {
+            let chars: Vec<char> = line.chars().collect();
+            lines.extend(chars.chunks(width).map(|chunk| chunk.iter().collect::<String>()));
         }
         lines
     }
@@ -302,42 +1042,83 @@ impl<I: Int> FungeView<I> {
 
 impl<I: Int> View for FungeView<I> {
     fn draw(&self, printer: &Printer) {
-        if let Ok(funge_mutex) = self.funge.lock().as_ref()  {
+        if let Ok(funge_mutex) = self.funge.read().as_deref()  {
             let hist_len = funge_mutex.history.len();
             let running = funge_mutex.running;
             match funge_mutex.funge.as_ref() {
+                Some(Ok(_)) if printer.size.x < MIN_WIDTH || printer.size.y < MIN_HEIGHT => {
+                    printer.print((0, 0), "window too small");
+                    printer.print((0, 1), "esc: quit");
+                }
                 Some(Ok(funge)) => {
-                    let cheight = (printer.size.y / 2) as isize;
+                    let cheight = min(printer.size.y / 2, printer.size.y.saturating_sub(6)) as isize;
                     let cwidth = printer.size.x as isize;
                     let fheight = funge.extent.height();
                     let fwidth = funge.extent.width();
                     let (top, bottom) = if cheight >= fheight {
                         (funge.extent.top, funge.extent.bottom)
                     } else {
-                        let y = funge.ips_pos().iter().map(|i| i[1]).sum::<isize>() / (funge.ips.len() as isize);
+                        let y = funge.ips_pos().iter().map(|i| i[1]).sum::<isize>() / max(funge.ips.len(), 1) as isize;
                         let top = max(y - &cheight / 2, funge.extent.top);
                         (top, top + cheight)
                     };
                     let (left, right) = if cwidth >= fwidth {
                         (funge.extent.left, funge.extent.right)
                     } else {
-                        let x = funge.ips_pos().iter().map(|i| i[0]).sum::<isize>() / (funge.ips.len() as isize);
+                        let x = funge.ips_pos().iter().map(|i| i[0]).sum::<isize>() / max(funge.ips.len(), 1) as isize;
                         let left = max(x - &cwidth / 2, funge.extent.left);
                         (left, left + cwidth)
                     };
-                    for (n, line) in funge.code.get_string(Rect::new(left, right, top, bottom)).iter().enumerate() {
-                        printer.print((0, n), line);
+                    for (n, line) in funge.code.region(&Rect::new(left, right, top, bottom)).into_iter().enumerate() {
+                        printer.print((0, n), &line.into_iter().map(|i| self.printable.char(i)).collect::<String>());
                     }
-                    for pos in funge.ips_pos() {
+                    if funge_mutex.show_diff {
+                        for (pos, _, _) in funge_mutex.diff() {
+                            if (left <= pos[0]) & (pos[0] < right) & (top <= pos[1]) & (pos[1] < bottom) {
+                                let c = self.cell_char(funge, &pos);
+                                printer.with_color(ColorStyle::back(Color::Dark(BaseColor::Yellow)), |printer| {
+                                    printer.print(((pos[0] - left) as usize, (pos[1] - top) as usize), &c);
+                                })
+                            }
+                        }
+                    }
+                    let timeline_diff = funge_mutex.show_timeline_diff.then(|| funge_mutex.timeline_diff()).flatten();
+                    if let Some((_, diff)) = &timeline_diff {
+                        for (pos, _, _) in diff {
+                            if (left <= pos[0]) & (pos[0] < right) & (top <= pos[1]) & (pos[1] < bottom) {
+                                let c = self.cell_char(funge, pos);
+                                printer.with_color(ColorStyle::back(Color::Dark(BaseColor::Magenta)), |printer| {
+                                    printer.print(((pos[0] - left) as usize, (pos[1] - top) as usize), &c);
+                                })
+                            }
+                        }
+                    }
+                    for (x, y) in funge_mutex.annotations.notes.keys() {
+                        if (left <= *x) & (*x < right) & (top <= *y) & (*y < bottom) {
+                            let c = self.cell_char(funge, &[*x, *y]);
+                            printer.with_effect(Effect::Underline, |printer| {
+                                printer.print(((x - left) as usize, (y - top) as usize), &c);
+                            })
+                        }
+                    }
+                    if !running {
+                        for (pos, _) in funge.lookahead(LOOKAHEAD).values().flatten() {
+                            if (left <= pos[0]) & (pos[0] < right) & (top <= pos[1]) & (pos[1] < bottom) {
+                                let c = self.cell_char(funge, pos);
+                                printer.with_color(ColorStyle::secondary(), |printer| {
+                                    printer.print(((pos[0] - left) as usize, (pos[1] - top) as usize), &c);
+                                })
+                            }
+                        }
+                    }
+                    for ip in &funge.ips {
+                        let pos = ip.position();
                         if (left <= pos[0]) & (pos[0] < right) & (top <= pos[1]) & (pos[1] < bottom) {
-                            let c = match cast_int::<u8, _>(funge.code[&pos]) {
-                                Ok(n @ 32..=126) | Ok(n @ 161..=255) => n,
-                                _ => 164
-                            };
-                            let c = chr(c).expect("c can only be valid u8 for char");
-                            printer.with_color(ColorStyle::highlight(),
+                            let c = self.cell_char(funge, pos);
+                            let color = funge.ip_name(ip.id).and_then(|name| name.color.as_deref()).and_then(Color::parse);
+                            printer.with_color(color.map(ColorStyle::back).unwrap_or(ColorStyle::highlight()),
                                                |printer| {
-                                                   printer.print(((pos[0] - left) as usize, (pos[1] - top) as usize), &c.to_string());
+                                                   printer.print(((pos[0] - left) as usize, (pos[1] - top) as usize), &c);
                                                }
                             )
                         }
@@ -345,36 +1126,91 @@ impl<I: Int> View for FungeView<I> {
 
                     let mut n = (bottom - top) as usize;
                     let offset: Vec<Vec<isize>> = funge.ips.iter().map(|ip| ip.offset.clone()).collect();
-                    printer.print((0, n + 1), &format!("top-left: {}, {}, ip pos: {:?}, offset: {:?}",
-                                                       top, left, funge.ips_pos(), offset));
-                    let cwidth = cwidth as usize;
-                    let mut stack = Self::wrap(funge.get_stack_string(), cwidth);
-                    let mut output = Self::wrap(funge.output.get(), cwidth);
-                    if printer.size.y >= n + 9 {
-                        stack = stack.into_iter().rev().take(printer.size.y / 5).rev().collect();
-                        if printer.size.y >= stack.len() + n + 9 {
-                            output = output.into_iter().rev().take(printer.size.y - stack.len() - n - 9).rev().collect();
-                        } else {
-                            output = Vec::new();
+                    let ips = funge.ips.iter().map(|ip| {
+                        let cell = funge.code[&ip.position().to_vec()];
+                        match self.printable.substitute {
+                            Substitute::Hex if !self.printable.is_printable(cell) =>
+                                format!("{} {:?} {}", funge.ip_label(ip.id), ip.position(), self.printable.show(cell)),
+                            _ => format!("{} {:?}", funge.ip_label(ip.id), ip.position())
                         }
-                    } else {
-                        stack = Vec::new();
-                        output = Vec::new();
+                    }).collect::<Vec<String>>();
+                    printer.print((0, n + 1), &format!("top-left: {}, {}, ips: {}, offset: {:?}",
+                                                       top, left, join(&ips, ", "), offset));
+                    if let Ok(prediction) = funge.predict(0) {
+                        printer.print((0, n + 2), &format!("next: {}", prediction));
                     }
+                    let cwidth = cwidth as usize;
+                    let depths = funge.ips.iter()
+                        .map(|ip| {
+                            let stats = funge.ip_stats().get(&ip.id).cloned().unwrap_or_default();
+                            format!("{}: {} {}, max {}, {} instructions", funge.ip_label(ip.id), funge_mutex.sparkline(ip.id),
+                                    ip.stack_depth(), stats.max_stack, stats.instructions)
+                        })
+                        .collect::<Vec<String>>();
+                    let notes = funge.ips.iter()
+                        .filter_map(|ip| funge_mutex.annotations.get(ip.position())
+                            .map(|note| format!("note at {:?}: {}", ip.position(), note)))
+                        .collect::<Vec<String>>();
+                    let mut stack = Self::wrap(join(&notes, "\n"), cwidth);
+                    stack.extend(Self::wrap(join(&depths, "\n"), cwidth));
+                    let stacks = funge.ips.iter()
+                        .flat_map(|ip| ip.stacks().into_iter().map(|stack| stack_line(stack, funge_mutex.stack_cells)))
+                        .collect::<Vec<String>>();
+                    stack.extend(Self::wrap(join(&stacks, "\n"), cwidth));
+                    let mut output = match funge_mutex.terminal {
+                        true => Terminal::lines(&funge.output.get(), cwidth, printer.size.y),
+                        false => Self::wrap(funge_mutex.marked_output(funge), cwidth)
+                    };
+                    let mut warnings = Self::wrap(funge.diagnostics.as_ref().map(|d| d.get()).unwrap_or_default(), cwidth);
+                    warnings = warnings.into_iter().rev().take(3).rev().collect();
+                    // when space runs out the warnings go first, then the output and then the stacks, the code,
+                    // status and keys always stay
+                    let mut free = printer.size.y.saturating_sub(n + 6);
+                    let show_stack = free > 0;
+                    stack = stack.into_iter().rev().take(min(printer.size.y / 5, free.saturating_sub(1))).rev().collect();
+                    free = free.saturating_sub(1 + stack.len());
+                    let needed = if warnings.is_empty() { 0 } else { 2 + warnings.len() };
+                    let show_output = free >= 2;
+                    output = output.into_iter().rev().take(max(free.saturating_sub(2 + needed), min(free.saturating_sub(2), 1)))
+                        .rev().collect();
+                    free = free.saturating_sub(2 + output.len());
+                    warnings = warnings.into_iter().rev().take(free.saturating_sub(2)).rev().collect();
 
-                    printer.print((0, n + 3), &format!("stacks:"));
-                    n += 4;
+                    if show_stack {
+                        printer.print((0, n + 3), "stacks:");
+                        n += 1;
+                    }
+                    n += 3;
                     for line in stack {
-                        printer.print((0, n), &*line);
+                        printer.print((0, n), &line);
                         n += 1;
                     }
-                    printer.print((0, n + 1), "output:");
-                    n += 2;
+                    if show_output {
+                        printer.print((0, n + 1), "output:");
+                        n += 2;
+                    }
                     for line in output {
-                        printer.print((0, n), &*line);
+                        printer.print((0, n), &line);
                         n += 1;
                     }
-                    printer.print((0, n + 1), &format!("steps: {}", funge.steps));
+                    if !warnings.is_empty() {
+                        printer.print((0, n + 1), "warnings:");
+                        n += 2;
+                        for line in warnings {
+                            printer.print((0, n), &line);
+                            n += 1;
+                        }
+                    }
+                    let timeline = match (&timeline_diff, funge_mutex.timelines.len()) {
+                        (_, 0) => String::new(),
+                        (Some((other, diff)), n) => format!(", timeline {} of {}, {} cells differ from timeline {}",
+                                                            funge_mutex.timeline, n + 1, diff.len(), other),
+                        (None, n) => format!(", timeline {} of {}", funge_mutex.timeline, n + 1)
+                    };
+                    printer.print((0, n + 1), &format!("steps: {}, ips: {}/{}, {} {:?}, underflows: {}, {}{}", funge.steps,
+                                                       funge.ips.len(), funge.rules().max_ips(),
+                                                       funge.rules().version(), funge.rules().wrap(),
+                                                       funge.underflow_count(), funge.space_stats(), timeline));
 
                     let mut text = vec!["esc: quit"];
                     if hist_len > 0 {
@@ -386,14 +1222,40 @@ impl<I: Int> View for FungeView<I> {
                         text.push("space: run")
                     }
                     text.push("enter: step");
-                    let interval = format!("interval: {} up/down arrow", funge_mutex.interval);
+                    text.push("?: explain");
+                    text.push("^n: name ip");
+                    text.push("^b: break on ip");
+                    text.push("^a: annotate");
+                    text.push("^r: reload");
+                    text.push(if funge_mutex.show_diff { "^d: hide changes" } else { "^d: show changes" });
+                    text.push("^e: export changes");
+                    text.push("^s: stack cells");
+                    text.push("^p: scratchpad");
+                    text.push("^f: fork");
+                    if !funge_mutex.timelines.is_empty() {
+                        text.push("^g: switch timeline");
+                        text.push(if funge_mutex.show_timeline_diff { "^x: hide timeline diff" } else { "^x: diff timelines" });
+                    }
+                    text.push("^y: copy");
+                    text.push("^v: paste");
+                    text.push(if funge_mutex.terminal { "^t: raw output" } else { "^t: output as on a terminal" });
+                    text.push(if funge_mutex.break_underflow { "^u: ignore underflow" } else { "^u: break on underflow" });
+                    text.push(if funge_mutex.auto_speed.is_some() { "^w: fixed speed" } else { "^w: auto speed" });
+                    let auto = match funge_mutex.auto_speed {
+                        Some(_) => format!(" auto ({:.3})", funge_mutex.effective_interval()),
+                        None => String::new()
+                    };
+                    let interval = match funge_mutex.rate.filter(|_| running) {
+                        Some(rate) => format!("interval: {}{} ({:.0} steps/s) up/down arrow", funge_mutex.interval, auto, rate),
+                        None => format!("interval: {}{} up/down arrow", funge_mutex.interval, auto)
+                    };
                     text.push(&*interval);
-                    printer.print((0, printer.size.y - 1), &*join(&text, ", "));
+                    printer.print((0, printer.size.y.saturating_sub(1)), &join(&text, ", "));
                 }
                 Some(Err(e)) => {
                     printer.print((0, 0), "Error occured:");
-                    printer.print((0, 1), &*format!("{}", e));
-                    printer.print((0, 2), &*format!("running: {}", running));
+                    printer.print((0, 1), &format!("{}", e));
+                    printer.print((0, 2), &format!("running: {}", running));
                     printer.print((0, 3), "esc: quit, backspace: back");
                 }
                 None => {}
@@ -421,7 +1283,7 @@ impl<I: Int> View for FungeView<I> {
                 EventResult::Consumed(None)
             }
             Event::Key(Key::Up) => {
-                let lock = self.funge.lock();
+                let lock = self.funge.write();
                 let mut funge = lock.unwrap();
                 let interval = funge.interval / 2.0;
                 if interval < 0.001 {
@@ -432,12 +1294,93 @@ impl<I: Int> View for FungeView<I> {
                 EventResult::Consumed(None)
             }
             Event::Key(Key::Down) => {
-                self.funge.lock().unwrap().interval *= 2.0;
+                self.funge.write().unwrap().interval *= 2.0;
+                EventResult::Consumed(None)
+            }
+            Event::Char('?') => {
+                let text = self.explain();
+                EventResult::with_cb(move |app| app.add_layer(Dialog::info(text.clone())))
+            }
+            Event::CtrlChar('n') => {
+                let dialog = self.name_dialog();
+                EventResult::with_cb_once(move |app| app.add_layer(dialog))
+            }
+            Event::CtrlChar('r') => {
+                match &self.source_file {
+                    Some(file) => {
+                        let dialog = Self::reload_dialog(self.funge.clone(), file);
+                        EventResult::with_cb_once(move |app| app.add_layer(dialog))
+                    }
+                    None => EventResult::Consumed(None)
+                }
+            }
+            Event::CtrlChar('d') => {
+                let mut funge = self.funge.write().unwrap();
+                funge.show_diff = !funge.show_diff;
+                EventResult::Consumed(None)
+            }
+            Event::CtrlChar('t') => {
+                let mut funge = self.funge.write().unwrap();
+                funge.terminal = !funge.terminal;
+                EventResult::Consumed(None)
+            }
+            Event::CtrlChar('e') => {
+                let dialog = self.export_dialog();
+                EventResult::with_cb_once(move |app| app.add_layer(dialog))
+            }
+            Event::CtrlChar('a') => {
+                let dialog = self.annotate_dialog();
+                EventResult::with_cb_once(move |app| app.add_layer(dialog))
+            }
+            Event::CtrlChar('s') => {
+                let dialog = self.stack_dialog();
+                EventResult::with_cb_once(move |app| app.add_layer(dialog))
+            }
+            Event::CtrlChar('p') => {
+                let dialog = self.scratch_dialog();
+                EventResult::with_cb_once(move |app| app.add_layer(dialog))
+            }
+            Event::CtrlChar('f') => {
+                let dialog = self.fork_dialog();
+                EventResult::with_cb_once(move |app| app.add_layer(dialog))
+            }
+            Event::CtrlChar('g') => {
+                self.funge.write().unwrap().switch_timeline();
+                EventResult::Consumed(None)
+            }
+            Event::CtrlChar('x') => {
+                let mut funge = self.funge.write().unwrap();
+                funge.show_timeline_diff = !funge.show_timeline_diff;
+                EventResult::Consumed(None)
+            }
+            Event::CtrlChar('y') => {
+                let dialog = self.copy_dialog();
+                EventResult::with_cb_once(move |app| app.add_layer(dialog))
+            }
+            Event::CtrlChar('v') => {
+                let dialog = self.paste_dialog();
+                EventResult::with_cb_once(move |app| app.add_layer(dialog))
+            }
+            Event::CtrlChar('b') => {
+                let dialog = self.break_dialog();
+                EventResult::with_cb_once(move |app| app.add_layer(dialog))
+            }
+            Event::CtrlChar('w') => {
+                let mut funge = self.funge.write().unwrap();
+                funge.auto_speed = match funge.auto_speed {
+                    Some(_) => None,
+                    None => Some(funge.speed_policy)
+                };
+                EventResult::Consumed(None)
+            }
+            Event::CtrlChar('u') => {
+                let mut funge = self.funge.write().unwrap();
+                funge.break_underflow = !funge.break_underflow;
                 EventResult::Consumed(None)
             }
             Event::Char(c) => {
-                if let Ok(op) = ord(c) {
-                    self.funge.lock().unwrap().stop_op = Some(op);
+                if let Ok(op) = from_char(c) {
+                    self.funge.write().unwrap().stop_op = Some(op);
                     self.run();
                 }
                 EventResult::Consumed(None)
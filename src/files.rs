@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use anyhow::{Error, Result};
+
+
+// a file opened by FILE, with the vector of its i/o buffer in funge-space
+pub(crate) struct OpenFile {
+    pub(crate) name: String,
+    pub(crate) buffer: Vec<isize>,
+    file: Arc<File>
+}
+
+impl OpenFile {
+    pub(crate) fn read(&self, n: usize) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        (&*self.file).take(n as u64).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    // up to and including the next newline
+    pub(crate) fn read_line(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut byte = [0];
+        while (&*self.file).read(&mut byte)? > 0 {
+            bytes.push(byte[0]);
+            if byte[0] == b'\n' {
+                break
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub(crate) fn write(&self, bytes: &[u8]) -> Result<()> {
+        Ok((&*self.file).write_all(bytes)?)
+    }
+
+    pub(crate) fn seek(&self, position: SeekFrom) -> Result<u64> {
+        Ok((&*self.file).seek(position)?)
+    }
+
+    pub(crate) fn tell(&self) -> Result<u64> {
+        Ok((&*self.file).stream_position()?)
+    }
+}
+
+
+// the open files by handle, clones of the funge share them
+#[derive(Clone, Default)]
+pub(crate) struct Files {
+    table: HashMap<usize, Arc<OpenFile>>,
+    last: usize
+}
+
+impl Files {
+    // modes like fopen: r, w, a, r+, w+, a+
+    pub(crate) fn open(&mut self, name: String, mode: usize, buffer: Vec<isize>) -> Result<usize> {
+        let mut options = OpenOptions::new();
+        match mode {
+            0 => options.read(true),
+            1 => options.write(true).create(true).truncate(true),
+            2 => options.append(true).create(true),
+            3 => options.read(true).write(true),
+            4 => options.read(true).write(true).create(true).truncate(true),
+            5 => options.read(true).append(true).create(true),
+            _ => Err(Error::msg(format!("unknown mode {}", mode)))?
+        };
+        let file = Arc::new(options.open(&name)?);
+        self.last += 1;
+        self.table.insert(self.last, Arc::new(OpenFile { name, buffer, file }));
+        Ok(self.last)
+    }
+
+    pub(crate) fn get(&self, handle: usize) -> Result<Arc<OpenFile>> {
+        self.table.get(&handle).cloned().ok_or_else(|| Error::msg(format!("no file {}", handle)))
+    }
+
+    pub(crate) fn close(&mut self, handle: usize) -> Result<()> {
+        self.table.remove(&handle).map(|_| ()).ok_or_else(|| Error::msg(format!("no file {}", handle)))
+    }
+}
@@ -0,0 +1,985 @@
+use anyhow::Result;
+use std::any::{Any, TypeId};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs};
+use std::f64::consts::PI;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use num::{BigInt, Integer, Signed, Zero};
+use rand::Rng;
+use crate::{Effect, Funge, Int, IP, convert::{cast, from_char, to_char}, sockets::MAX_TRANSFER};
+
+
+// what a letter does while a fingerprint is loaded
+pub type Semantic<I> = fn(&mut IP<I>, &mut Funge<I>) -> Result<()>;
+
+
+pub struct Fingerprint<I: Int> {
+    pub id: i128,
+    pub name: &'static str,
+    pub ops: Vec<(char, Semantic<I>)>
+}
+
+impl<I: Int> Fingerprint<I> {
+    pub fn new(name: &'static str, ops: Vec<(char, Semantic<I>)>) -> Self {
+        Self { id: id(name), name, ops }
+    }
+
+    // these do not load without the network
+    pub fn network(&self) -> bool {
+        matches!(self.name, "SOCK" | "SCKE")
+    }
+}
+
+
+// the id ( and ) compute from the name, like 0x4e554c4c for NULL
+pub fn id(name: &str) -> i128 {
+    name.bytes().fold(0, |id, b| id * 256 + b as i128)
+}
+
+// the built-in fingerprints
+pub fn registry<I: Int>() -> Vec<Fingerprint<I>> {
+    vec![null(), roma(), modu(), mode(), orth(), refc(), strn(), fixp(), Fingerprint::new("FPSP", float::<I, false>()),
+         Fingerprint::new("FPDP", float::<I, true>()), long(), boolean(), hrti(), evar(), subr(), term(), sock(), scke(), file(), dirf()]
+}
+
+// the registry by id, built once for each type of cell the first time ( or ) needs it
+static REGISTRIES: OnceLock<Mutex<HashMap<TypeId, &'static (dyn Any + Send + Sync)>>> = OnceLock::new();
+
+pub fn get<I: Int>(id: i128) -> Option<&'static Fingerprint<I>> {
+    let mut registries = REGISTRIES.get_or_init(Mutex::default).lock().unwrap_or_else(PoisonError::into_inner);
+    let registry = *registries.entry(TypeId::of::<I>()).or_insert_with(|| {
+        let by_id = registry::<I>().into_iter().map(|fingerprint| (fingerprint.id, fingerprint)).collect::<HashMap<_, _>>();
+        Box::leak(Box::new(by_id))
+    });
+    registry.downcast_ref::<HashMap<i128, Fingerprint<I>>>().expect("the registry is kept by the type of its cells")
+        .get(&id)
+}
+
+
+fn reflect<I: Int>(ip: &mut IP<I>, _: &mut Funge<I>) -> Result<()> {
+    ip.reflect();
+    Ok(())
+}
+
+// every letter reflects, masking what was loaded before
+fn null<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("NULL", ('A'..='Z').map(|op| (op, reflect as Semantic<I>)).collect())
+}
+
+fn push<I: Int>(ip: &mut IP<I>, n: i32) -> Result<()> {
+    ip.stack.push(cast(n)?);
+    Ok(())
+}
+
+// roman numerals push their value
+fn roma<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("ROMA", vec![
+        ('C', |ip, _| push(ip, 100)),
+        ('D', |ip, _| push(ip, 500)),
+        ('I', |ip, _| push(ip, 1)),
+        ('L', |ip, _| push(ip, 50)),
+        ('M', |ip, _| push(ip, 1000)),
+        ('V', |ip, _| push(ip, 5)),
+        ('X', |ip, _| push(ip, 10))
+    ])
+}
+
+// pops b then a, pushes 0 when b is 0 or the remainder overflows (the smallest cell and -1, where it is 0 anyway) and
+// otherwise what the function makes of a and b
+fn modulo<I: Int>(ip: &mut IP<I>, f: fn(I, I) -> I) -> Result<()> {
+    let b = ip.stack.pop();
+    let a = ip.stack.pop();
+    ip.stack.push(if a.checked_rem(&b).is_none() { I::zero() } else { f(a, b) });
+    Ok(())
+}
+
+// M takes the sign of the divisor, U is always positive (Sam Holden's) and R the sign of the dividend (C)
+fn modu<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("MODU", vec![
+        ('M', |ip, _| modulo(ip, |a, b| a.mod_floor(&b))),
+        ('U', |ip, _| modulo(ip, |a, b| {
+            let r = a % b;
+            if r < I::zero() { I::zero() - r } else { r }
+        })),
+        ('R', |ip, _| modulo(ip, |a, b| a % b))
+    ])
+}
+
+// toggles hovermode (H), invertmode (I), queuemode (Q) and switchmode (S)
+fn mode<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("MODE", vec![
+        ('H', |ip, _| {
+            ip.hover = !ip.hover;
+            Ok(())
+        }),
+        ('I', |ip, _| {
+            ip.stack.invert = !ip.stack.invert;
+            Ok(())
+        }),
+        ('Q', |ip, _| {
+            ip.stack.queue = !ip.stack.queue;
+            Ok(())
+        }),
+        ('S', |ip, _| {
+            ip.switch = !ip.switch;
+            Ok(())
+        })
+    ])
+}
+
+fn bitwise<I: Int>(ip: &mut IP<I>, f: fn(i128, i128) -> i128) -> Result<()> {
+    let b: i128 = cast(ip.stack.pop())?;
+    let a: i128 = cast(ip.stack.pop())?;
+    ip.stack.push(cast(f(a, b))?);
+    Ok(())
+}
+
+// orthogonal easement: bitwise A, O and E, G and P with x on top, V and W set the delta, X and Y the position,
+// S prints a string and Z skips the next instruction when it pops 0
+fn orth<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("ORTH", vec![
+        ('A', |ip, _| bitwise(ip, |a, b| a & b)),
+        ('O', |ip, _| bitwise(ip, |a, b| a | b)),
+        ('E', |ip, _| bitwise(ip, |a, b| a ^ b)),
+        ('G', |ip, funge| {
+            let x: isize = cast(ip.stack.pop())?;
+            let y: isize = cast(ip.stack.pop())?;
+            ip.stack.push(funge.code[&vec![x + ip.offset[0], y + ip.offset[1]]]);
+            Ok(())
+        }),
+        ('P', |ip, funge| {
+            let x: isize = cast(ip.stack.pop())?;
+            let y: isize = cast(ip.stack.pop())?;
+            let v = ip.stack.pop();
+            funge.insert(v, vec![x + ip.offset[0], y + ip.offset[1]]);
+            Ok(())
+        }),
+        ('V', |ip, _| {
+            ip.delta[0] = cast(ip.stack.pop())?;
+            Ok(())
+        }),
+        ('W', |ip, _| {
+            ip.delta[1] = cast(ip.stack.pop())?;
+            Ok(())
+        }),
+        ('X', |ip, _| {
+            ip.position[0] = cast(ip.stack.pop())?;
+            Ok(())
+        }),
+        ('Y', |ip, _| {
+            ip.position[1] = cast(ip.stack.pop())?;
+            Ok(())
+        }),
+        ('S', |ip, funge| {
+            let s = ip.read_string()?;
+            funge.write(s)
+        }),
+        ('Z', |ip, funge| {
+            if ip.stack.pop().is_zero() {
+                ip.movep(funge);
+            }
+            Ok(())
+        })
+    ])
+}
+
+// R turns a vector into a scalar reference, the same vector always gets the same one, D turns it back
+fn refc<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("REFC", vec![
+        ('R', |ip, funge| {
+            let y = ip.stack.pop();
+            let x = ip.stack.pop();
+            let vector = vec![x, y];
+            let reference = match funge.references.iter().position(|reference| *reference == vector) {
+                Some(reference) => reference,
+                None => {
+                    funge.references.push(vector);
+                    funge.references.len() - 1
+                }
+            };
+            ip.stack.push(cast(reference)?);
+            Ok(())
+        }),
+        ('D', |ip, funge| {
+            match cast::<usize, I>(ip.stack.pop()).ok().and_then(|reference| funge.references.get(reference)) {
+                Some(vector) => for cell in vector {
+                    ip.stack.push(*cell);
+                }
+                None => ip.reflect()
+            }
+            Ok(())
+        })
+    ])
+}
+
+fn pop_usize<I: Int>(ip: &mut IP<I>) -> usize {
+    cast(ip.stack.pop()).unwrap_or(0)
+}
+
+fn vector<I: Int>(ip: &mut IP<I>) -> Result<Vec<isize>> {
+    let y: isize = cast(ip.stack.pop())?;
+    let x: isize = cast(ip.stack.pop())?;
+    Ok(vec![x + ip.offset[0], y + ip.offset[1]])
+}
+
+// strings are 0gnirts on the stack, counts and positions are in characters
+fn strn<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("STRN", vec![
+        ('A', |ip, _| {
+            let top = ip.read_string()?;
+            let bottom = ip.read_string()?;
+            ip.push_string(&(top + &bottom))
+        }),
+        ('C', |ip, _| {
+            let top = ip.read_string()?;
+            let bottom = ip.read_string()?;
+            ip.stack.push(match top.cmp(&bottom) {
+                Ordering::Less => I::zero() - I::one(),
+                Ordering::Equal => I::zero(),
+                Ordering::Greater => I::one()
+            });
+            Ok(())
+        }),
+        ('D', |ip, funge| {
+            let string = ip.read_string()?;
+            funge.write(string)
+        }),
+        ('F', |ip, _| {
+            let top = ip.read_string()?;
+            let bottom = ip.read_string()?;
+            ip.push_string(top.find(&bottom).map(|index| &top[index..]).unwrap_or(""))
+        }),
+        ('G', |ip, funge| {
+            let mut position = vector(ip)?;
+            let mut string = String::new();
+            // stop at the edge of funge-space, where only spaces follow
+            while funge.code.get(&position).is_some_and(|cell| !cell.is_zero()) && position[0] < funge.extent.right {
+                string.push(to_char(funge.code[&position])?);
+                position[0] += 1;
+            }
+            ip.push_string(&string)
+        }),
+        ('I', |ip, funge| {
+            let request = ip.input_request('I', funge.steps);
+            match funge.read(request) {
+                Ok(line) => ip.push_string(line.trim_end_matches(['\r', '\n'])),
+                Err(_) => {
+                    ip.reflect();
+                    Ok(())
+                }
+            }
+        }),
+        ('L', |ip, _| {
+            let n = pop_usize(ip);
+            let string = ip.read_string()?;
+            ip.push_string(&string.chars().take(n).collect::<String>())
+        }),
+        ('M', |ip, _| {
+            let n = pop_usize(ip);
+            let start = pop_usize(ip);
+            let string = ip.read_string()?;
+            ip.push_string(&string.chars().skip(start).take(n).collect::<String>())
+        }),
+        ('N', |ip, _| {
+            let string = ip.read_string()?;
+            ip.push_string(&string)?;
+            ip.stack.push(cast(string.chars().count())?);
+            Ok(())
+        }),
+        ('P', |ip, funge| {
+            let position = vector(ip)?;
+            let string = ip.read_string()?;
+            for (i, c) in string.chars().chain(['\0']).enumerate() {
+                funge.insert(from_char(c)?, vec![position[0] + i as isize, position[1]]);
+            }
+            Ok(())
+        }),
+        ('R', |ip, _| {
+            let n = pop_usize(ip);
+            let string = ip.read_string()?;
+            let skip = string.chars().count().saturating_sub(n);
+            ip.push_string(&string.chars().skip(skip).collect::<String>())
+        }),
+        ('S', |ip, _| {
+            let n = ip.stack.pop();
+            ip.push_string(&n.to_string())
+        }),
+        ('V', |ip, _| {
+            let string = ip.read_string()?;
+            let digits: String = string.trim_start().chars().enumerate()
+                .take_while(|(i, c)| c.is_ascii_digit() || (*i == 0 && *c == '-'))
+                .map(|(_, c)| c).collect();
+            ip.stack.push(digits.parse().unwrap_or(I::zero()));
+            Ok(())
+        })
+    ])
+}
+
+// truncates the result, reflects when it is not a number or does not fit in a cell
+fn fixed<I: Int>(ip: &mut IP<I>, f: fn(f64) -> f64) -> Result<()> {
+    let n: f64 = cast(ip.stack.pop())?;
+    let result = f(n);
+    match cast(result.trunc()) {
+        Ok(value) if result.is_finite() => ip.stack.push(value),
+        _ => ip.reflect()
+    }
+    Ok(())
+}
+
+// fixed point: angles are degrees and, like sines and cosines, times 10000
+fn fixp<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("FIXP", vec![
+        ('A', |ip, _| bitwise(ip, |a, b| a & b)),
+        ('O', |ip, _| bitwise(ip, |a, b| a | b)),
+        ('X', |ip, _| bitwise(ip, |a, b| a ^ b)),
+        ('B', |ip, _| fixed(ip, |n| (n / 10000.0).acos().to_degrees() * 10000.0)),
+        ('C', |ip, _| fixed(ip, |n| (n / 10000.0).to_radians().cos() * 10000.0)),
+        ('I', |ip, _| fixed(ip, |n| (n / 10000.0).to_radians().sin() * 10000.0)),
+        ('J', |ip, _| fixed(ip, |n| (n / 10000.0).asin().to_degrees() * 10000.0)),
+        ('T', |ip, _| fixed(ip, |n| (n / 10000.0).to_radians().tan() * 10000.0)),
+        ('U', |ip, _| fixed(ip, |n| (n / 10000.0).atan().to_degrees() * 10000.0)),
+        ('P', |ip, _| fixed(ip, |n| n * PI)),
+        ('Q', |ip, _| fixed(ip, f64::sqrt)),
+        ('N', |ip, _| fixed(ip, |n| -n)),
+        ('S', |ip, _| fixed(ip, |n| if n == 0.0 { 0.0 } else { n.signum() })),
+        ('V', |ip, _| fixed(ip, f64::abs)),
+        ('R', |ip, _| {
+            let b: f64 = cast(ip.stack.pop())?;
+            let a: f64 = cast(ip.stack.pop())?;
+            let result = a.powf(b);
+            match cast(result.trunc()) {
+                Ok(value) if result.is_finite() => ip.stack.push(value),
+                _ => ip.reflect()
+            }
+            Ok(())
+        }),
+        ('D', |ip, funge| {
+            let n: i128 = cast(ip.stack.pop())?;
+            let r = if n == 0 { 0 } else { funge.rng.gen_range(0..n.abs()) * n.signum() };
+            ip.stack.push(cast(r)?);
+            Ok(())
+        })
+    ])
+}
+
+// the bits of a float as 32 bit halves in cells, a double takes two with the low half on top, so cells need at
+// least 32 bits and larger cells hold a half either sign extended or as unsigned
+fn pop_float<I: Int, const DOUBLE: bool>(ip: &mut IP<I>) -> Option<f64> {
+    let half = |cell: I| -> Option<u32> {
+        let n: i64 = cast(cell).ok()?;
+        i32::try_from(n).map(|n| n as u32).ok().or(u32::try_from(n).ok())
+    };
+    if DOUBLE {
+        let (low, high) = (ip.stack.pop(), ip.stack.pop());
+        Some(f64::from_bits((half(high)? as u64) << 32 | half(low)? as u64))
+    } else {
+        Some(f32::from_bits(half(ip.stack.pop())?) as f64)
+    }
+}
+
+// reflects when the cells are too small
+fn push_float<I: Int, const DOUBLE: bool>(ip: &mut IP<I>, value: f64) -> Result<()> {
+    let halves = if DOUBLE {
+        let bits = value.to_bits();
+        vec![(bits >> 32) as u32, bits as u32]
+    } else {
+        vec![(value as f32).to_bits()]
+    };
+    match halves.into_iter().map(|half| cast(half as i32)).collect::<Result<Vec<I>>>() {
+        Ok(cells) => ip.stack.extend(cells),
+        Err(_) => ip.reflect()
+    }
+    Ok(())
+}
+
+fn unary<I: Int, const DOUBLE: bool>(ip: &mut IP<I>, f: fn(f64) -> f64) -> Result<()> {
+    match pop_float::<I, DOUBLE>(ip) {
+        Some(a) => push_float::<I, DOUBLE>(ip, f(a)),
+        None => {
+            ip.reflect();
+            Ok(())
+        }
+    }
+}
+
+fn binary<I: Int, const DOUBLE: bool>(ip: &mut IP<I>, f: fn(f64, f64) -> f64) -> Result<()> {
+    let b = pop_float::<I, DOUBLE>(ip);
+    match (pop_float::<I, DOUBLE>(ip), b) {
+        (Some(a), Some(b)) => push_float::<I, DOUBLE>(ip, f(a, b)),
+        _ => {
+            ip.reflect();
+            Ok(())
+        }
+    }
+}
+
+// FPSP and FPDP, single and double precision
+fn float<I: Int, const DOUBLE: bool>() -> Vec<(char, Semantic<I>)> {
+    vec![
+        ('A', |ip, _| binary::<I, DOUBLE>(ip, |a, b| a + b)),
+        ('S', |ip, _| binary::<I, DOUBLE>(ip, |a, b| a - b)),
+        ('M', |ip, _| binary::<I, DOUBLE>(ip, |a, b| a * b)),
+        ('D', |ip, _| binary::<I, DOUBLE>(ip, |a, b| a / b)),
+        ('Y', |ip, _| binary::<I, DOUBLE>(ip, f64::powf)),
+        ('B', |ip, _| unary::<I, DOUBLE>(ip, f64::sin)),
+        ('C', |ip, _| unary::<I, DOUBLE>(ip, f64::cos)),
+        ('T', |ip, _| unary::<I, DOUBLE>(ip, f64::tan)),
+        ('E', |ip, _| unary::<I, DOUBLE>(ip, f64::asin)),
+        ('H', |ip, _| unary::<I, DOUBLE>(ip, f64::acos)),
+        ('G', |ip, _| unary::<I, DOUBLE>(ip, f64::atan)),
+        ('K', |ip, _| unary::<I, DOUBLE>(ip, f64::ln)),
+        ('L', |ip, _| unary::<I, DOUBLE>(ip, f64::log10)),
+        ('X', |ip, _| unary::<I, DOUBLE>(ip, f64::exp)),
+        ('Q', |ip, _| unary::<I, DOUBLE>(ip, f64::sqrt)),
+        ('V', |ip, _| unary::<I, DOUBLE>(ip, f64::abs)),
+        ('N', |ip, _| unary::<I, DOUBLE>(ip, |a| -a)),
+        ('F', |ip, _| {
+            let n: f64 = cast(ip.stack.pop())?;
+            push_float::<I, DOUBLE>(ip, n)
+        }),
+        ('I', |ip, _| {
+            match pop_float::<I, DOUBLE>(ip).and_then(|a| cast(a.trunc()).ok()) {
+                Some(n) => ip.stack.push(n),
+                None => ip.reflect()
+            }
+            Ok(())
+        }),
+        ('P', |ip, funge| {
+            match pop_float::<I, DOUBLE>(ip) {
+                Some(a) if DOUBLE => funge.write(format!("{} ", a))?,
+                Some(a) => funge.write(format!("{} ", a as f32))?,
+                None => ip.reflect()
+            }
+            Ok(())
+        }),
+        ('R', |ip, _| {
+            match ip.read_string()?.trim().parse() {
+                Ok(a) => push_float::<I, DOUBLE>(ip, a)?,
+                Err(_) => ip.reflect()
+            }
+            Ok(())
+        })
+    ]
+}
+
+// a long is two cells, the high one first and the low one, taken as unsigned, on top
+fn width<I: Int>() -> usize {
+    8 * std::mem::size_of::<I>()
+}
+
+fn pop_long<I: Int>(ip: &mut IP<I>) -> Result<BigInt> {
+    let low = BigInt::from(cast::<i128, I>(ip.stack.pop())?);
+    let high = BigInt::from(cast::<i128, I>(ip.stack.pop())?);
+    Ok((high << width::<I>()) + low.mod_floor(&(BigInt::from(1) << width::<I>())))
+}
+
+// wraps around like a cell of twice the width would
+fn push_long<I: Int>(ip: &mut IP<I>, n: BigInt) -> Result<()> {
+    let cell = BigInt::from(1) << width::<I>();
+    let long = &cell * &cell;
+    let half: BigInt = &long / 2;
+    let n: BigInt = (n + &half).mod_floor(&long) - half;
+    let mut low = n.mod_floor(&cell);
+    let high = (&n - &low) >> width::<I>();
+    if low >= &cell / 2 {
+        low -= &cell;
+    }
+    for half in [high, low] {
+        ip.stack.push(cast(i128::try_from(half)?)?);
+    }
+    Ok(())
+}
+
+fn long_binary<I: Int>(ip: &mut IP<I>, f: fn(BigInt, BigInt) -> BigInt) -> Result<()> {
+    let b = pop_long(ip)?;
+    let a = pop_long(ip)?;
+    push_long(ip, f(a, b))
+}
+
+fn long_unary<I: Int>(ip: &mut IP<I>, f: fn(BigInt) -> BigInt) -> Result<()> {
+    let a = pop_long(ip)?;
+    push_long(ip, f(a))
+}
+
+fn shift<I: Int>(ip: &mut IP<I>, left: bool) -> Result<()> {
+    let n: usize = cast(ip.stack.pop()).unwrap_or(0).min(2 * width::<I>());
+    let a = pop_long(ip)?;
+    push_long(ip, if left { a << n } else { a >> n })
+}
+
+// integers of two cells, division and modulo by 0 give 0
+fn long<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("LONG", vec![
+        ('A', |ip, _| long_binary(ip, |a, b| a + b)),
+        ('S', |ip, _| long_binary(ip, |a, b| a - b)),
+        ('M', |ip, _| long_binary(ip, |a, b| a * b)),
+        ('D', |ip, _| long_binary(ip, |a, b| if b.is_zero() { b } else { a / b })),
+        ('O', |ip, _| long_binary(ip, |a, b| if b.is_zero() { b } else { a % b })),
+        ('N', |ip, _| long_unary(ip, |a| -a)),
+        ('B', |ip, _| long_unary(ip, |a| a.abs())),
+        ('L', |ip, _| shift(ip, true)),
+        ('R', |ip, _| shift(ip, false)),
+        ('E', |ip, _| {
+            let n = BigInt::from(cast::<i128, I>(ip.stack.pop())?);
+            push_long(ip, n)
+        }),
+        ('P', |ip, funge| {
+            let a = pop_long(ip)?;
+            funge.write(format!("{} ", a))
+        }),
+        ('Z', |ip, _| {
+            let string = ip.read_string()?;
+            match string.trim().parse() {
+                Ok(a) => push_long(ip, a)?,
+                Err(_) => ip.reflect()
+            }
+            Ok(())
+        })
+    ])
+}
+
+fn boolean<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("BOOL", vec![
+        ('A', |ip, _| bitwise(ip, |a, b| a & b)),
+        ('O', |ip, _| bitwise(ip, |a, b| a | b)),
+        ('X', |ip, _| bitwise(ip, |a, b| a ^ b)),
+        ('N', |ip, _| {
+            let a: i128 = cast(ip.stack.pop())?;
+            ip.stack.push(cast(!a)?);
+            Ok(())
+        })
+    ])
+}
+
+// high resolution timer in microseconds, from the funge's timer
+fn hrti<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("HRTI", vec![
+        ('G', |ip, _| {
+            ip.stack.push(I::one());
+            Ok(())
+        }),
+        ('M', |ip, funge| {
+            ip.mark = Some((funge.timer)(funge));
+            Ok(())
+        }),
+        ('T', |ip, funge| {
+            match ip.mark {
+                Some(mark) => ip.stack.push(cast((funge.timer)(funge).saturating_sub(mark).as_micros())?),
+                None => ip.reflect()
+            }
+            Ok(())
+        }),
+        ('E', |ip, _| {
+            ip.mark = None;
+            Ok(())
+        }),
+        ('S', |ip, funge| {
+            ip.stack.push(cast((funge.timer)(funge).subsec_micros())?);
+            Ok(())
+        })
+    ])
+}
+
+// the funge's environment, as given with with_env, P changes only that copy
+fn evar<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("EVAR", vec![
+        ('G', |ip, funge| {
+            let name = ip.read_string()?;
+            let value = funge.env.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_owned());
+            ip.push_string(&value.unwrap_or_default())
+        }),
+        ('N', |ip, funge| {
+            ip.stack.push(cast(funge.env.len())?);
+            Ok(())
+        }),
+        ('P', |ip, funge| {
+            let string = ip.read_string()?;
+            match string.split_once('=') {
+                Some((name, value)) => match funge.env.iter_mut().find(|(key, _)| key == name) {
+                    Some(variable) => variable.1 = value.to_string(),
+                    None => funge.env.push((name.to_string(), value.to_string()))
+                }
+                None => ip.reflect()
+            }
+            Ok(())
+        }),
+        ('V', |ip, funge| {
+            match cast::<usize, I>(ip.stack.pop()).ok().and_then(|n| funge.env.get(n)) {
+                Some((name, value)) => {
+                    let variable = format!("{}={}", name, value);
+                    ip.push_string(&variable)?
+                }
+                None => ip.reflect()
+            }
+            Ok(())
+        })
+    ])
+}
+
+// an address as absolute position, in relative mode it is relative to the storage offset
+fn address<I: Int>(ip: &mut IP<I>) -> Result<Vec<isize>> {
+    let y: isize = cast(ip.stack.pop())?;
+    let x: isize = cast(ip.stack.pop())?;
+    Ok(match ip.relative {
+        true => vec![x + ip.offset[0], y + ip.offset[1]],
+        false => vec![x, y]
+    })
+}
+
+// the top n cells, in the order they were pushed
+fn pop_cells<I: Int>(ip: &mut IP<I>, n: usize) -> Vec<I> {
+    let mut cells: Vec<I> = (0..n).map(|_| ip.stack.pop()).collect();
+    cells.reverse();
+    cells
+}
+
+// C puts the return address and delta below its n arguments, R restores them and keeps its n return values
+fn subr<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("SUBR", vec![
+        ('A', |ip, _| {
+            ip.relative = false;
+            Ok(())
+        }),
+        ('C', |ip, funge| {
+            let n = pop_usize(ip);
+            let target = address(ip)?;
+            let cells = pop_cells(ip, n);
+            let (x, y) = match ip.relative {
+                true => (ip.position[0] - ip.offset[0], ip.position[1] - ip.offset[1]),
+                false => (ip.position[0], ip.position[1])
+            };
+            for i in [x, y, ip.delta[0], ip.delta[1]] {
+                ip.stack.push(cast(i)?);
+            }
+            cells.into_iter().for_each(|cell| ip.stack.push(cell));
+            ip.jump(funge, target, vec![1, 0]);
+            Ok(())
+        }),
+        ('J', |ip, funge| {
+            let target = address(ip)?;
+            ip.jump(funge, target, vec![1, 0]);
+            Ok(())
+        }),
+        ('O', |ip, _| {
+            ip.relative = true;
+            Ok(())
+        }),
+        ('R', |ip, _| {
+            let n = pop_usize(ip);
+            let cells = pop_cells(ip, n);
+            let dy: isize = cast(ip.stack.pop())?;
+            let dx: isize = cast(ip.stack.pop())?;
+            ip.position = address(ip)?;
+            ip.delta = vec![dx, dy];
+            cells.into_iter().for_each(|cell| ip.stack.push(cell));
+            Ok(())
+        })
+    ])
+}
+
+// ANSI escape sequences
+fn csi<I: Int>(funge: &mut Funge<I>, sequence: &str) -> Result<()> {
+    funge.write(format!("\x1b[{}", sequence))
+}
+
+fn lines<I: Int>(ip: &mut IP<I>, funge: &mut Funge<I>, down: bool) -> Result<()> {
+    let n: isize = cast(ip.stack.pop())?;
+    match (n < 0) ^ down {
+        _ if n == 0 => Ok(()),
+        true => csi(funge, &format!("{}B", n.abs())),
+        false => csi(funge, &format!("{}A", n.abs()))
+    }
+}
+
+fn color<I: Int>(ip: &mut IP<I>, funge: &mut Funge<I>, base: u8) -> Result<()> {
+    match cast::<u8, I>(ip.stack.pop()) {
+        Ok(n @ 0..=7) => csi(funge, &format!("{}m", base + n)),
+        _ => csi(funge, "0m")
+    }
+}
+
+// terminal control as escape sequences in the output, F and B set the colors 0 - 7, others reset them
+fn term<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("TERM", vec![
+        ('B', |ip, funge| color(ip, funge, 40)),
+        ('C', |_, funge| csi(funge, "2J\x1b[H")),
+        ('D', |ip, funge| lines(ip, funge, true)),
+        ('F', |ip, funge| color(ip, funge, 30)),
+        ('G', |ip, funge| {
+            let y: isize = cast(ip.stack.pop())?;
+            let x: isize = cast(ip.stack.pop())?;
+            csi(funge, &format!("{};{}H", y.max(0) + 1, x.max(0) + 1))
+        }),
+        ('H', |_, funge| csi(funge, "H")),
+        ('L', |_, funge| csi(funge, "K")),
+        ('S', |_, funge| csi(funge, "J")),
+        ('U', |ip, funge| lines(ip, funge, false))
+    ])
+}
+
+// a dry run only notes the effect, failing operations reflect
+fn network<I: Int>(ip: &mut IP<I>, funge: &mut Funge<I>, op: char, f: Semantic<I>) -> Result<()> {
+    if funge.dry_run {
+        funge.effects.push(Effect::Network(op));
+        ip.reflect();
+    } else if let Err(error) = f(ip, funge) {
+        ip.reflect();
+        funge.warn(format!("network instruction {} failed: {}, reflected", op, error))?;
+    }
+    Ok(())
+}
+
+// ct prt addr, only AF_INET (2) is supported, addresses are as I pushes them
+fn socket_address<I: Int>(ip: &mut IP<I>) -> Result<SocketAddrV4> {
+    let address: u32 = cast(ip.stack.pop())?;
+    let port: u16 = cast(ip.stack.pop())?;
+    match cast::<i128, I>(ip.stack.pop())? {
+        2 => Ok(SocketAddrV4::new(Ipv4Addr::from(address), port)),
+        family => Err(anyhow::Error::msg(format!("unsupported address family {}", family)))
+    }
+}
+
+// TCP and UDP over IPv4, handles are shared by all IPs
+fn sock<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("SOCK", vec![
+        ('A', |ip, funge| network(ip, funge, 'A', |ip, funge| {
+            let (handle, peer) = funge.sockets.accept(pop_usize(ip))?;
+            let (port, address) = match peer {
+                SocketAddr::V4(peer) => (peer.port(), u32::from(*peer.ip())),
+                SocketAddr::V6(peer) => (peer.port(), 0)
+            };
+            ip.stack.push(cast(port)?);
+            ip.stack.push(cast(address)?);
+            ip.stack.push(cast(handle)?);
+            Ok(())
+        })),
+        ('B', |ip, funge| network(ip, funge, 'B', |ip, funge| {
+            let handle = pop_usize(ip);
+            let address = socket_address(ip)?;
+            funge.sockets.bind(handle, address)
+        })),
+        ('C', |ip, funge| network(ip, funge, 'C', |ip, funge| {
+            let handle = pop_usize(ip);
+            let address = socket_address(ip)?;
+            funge.sockets.connect(handle, address)
+        })),
+        ('I', |ip, _| {
+            match ip.read_string()?.parse::<Ipv4Addr>() {
+                Ok(address) => ip.stack.push(cast(u32::from(address))?),
+                Err(_) => ip.reflect()
+            }
+            Ok(())
+        }),
+        ('K', |ip, funge| network(ip, funge, 'K', |ip, funge| funge.sockets.kill(pop_usize(ip)))),
+        ('L', |ip, funge| network(ip, funge, 'L', |ip, funge| {
+            let handle = pop_usize(ip);
+            ip.stack.pop();
+            funge.sockets.listen(handle)
+        })),
+        ('O', |ip, funge| network(ip, funge, 'O', |ip, funge| {
+            let handle = pop_usize(ip);
+            let option = pop_usize(ip);
+            let value = !ip.stack.pop().is_zero();
+            funge.sockets.set_option(handle, option, value)
+        })),
+        ('R', |ip, funge| network(ip, funge, 'R', |ip, funge| {
+            let handle = pop_usize(ip);
+            let length = pop_usize(ip);
+            let position = vector(ip)?;
+            let bytes = funge.sockets.receive(handle, length)?;
+            for (x, byte) in bytes.iter().enumerate() {
+                funge.insert(cast(*byte)?, vec![position[0] + x as isize, position[1]]);
+            }
+            ip.stack.push(cast(bytes.len())?);
+            Ok(())
+        })),
+        ('S', |ip, funge| network(ip, funge, 'S', |ip, funge| {
+            ip.stack.pop();
+            let udp = match (cast::<i128, I>(ip.stack.pop())?, cast::<i128, I>(ip.stack.pop())?) {
+                (1, 2) => true,
+                (2, 2) => false,
+                (kind, family) => Err(anyhow::Error::msg(format!("unsupported socket type {} of family {}", kind, family)))?
+            };
+            ip.stack.push(cast(funge.sockets.create(udp))?);
+            Ok(())
+        })),
+        ('W', |ip, funge| network(ip, funge, 'W', |ip, funge| {
+            let handle = pop_usize(ip);
+            let length = pop_usize(ip).min(MAX_TRANSFER);
+            let position = vector(ip)?;
+            funge.sockets.connected(handle)?;
+            let bytes: Vec<u8> = (0..length as isize)
+                .map(|x| funge.code[&vec![position[0] + x, position[1]]].to_i128().unwrap_or_default() as u8).collect();
+            let sent = funge.sockets.send(handle, &bytes)?;
+            ip.stack.push(cast(sent)?);
+            Ok(())
+        }))
+    ])
+}
+
+// SOCK extensions: H looks up the IPv4 address of a host, P pushes 1 when a socket has data to read
+fn scke<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("SCKE", vec![
+        ('H', |ip, funge| network(ip, funge, 'H', |ip, _| {
+            let host = ip.read_string()?;
+            let address = (host.as_str(), 0).to_socket_addrs()?.find_map(|address| match address {
+                SocketAddr::V4(address) => Some(u32::from(*address.ip())),
+                SocketAddr::V6(_) => None
+            }).ok_or_else(|| anyhow::Error::msg(format!("no IPv4 address for {}", host)))?;
+            ip.stack.push(cast(address)?);
+            Ok(())
+        })),
+        ('P', |ip, funge| network(ip, funge, 'P', |ip, funge| {
+            let waiting = funge.sockets.peek(pop_usize(ip))?;
+            ip.stack.push(if waiting { I::one() } else { I::zero() });
+            Ok(())
+        }))
+    ])
+}
+
+// files are sandboxed like i and o, a dry run only notes the effect
+fn access<I: Int>(funge: &mut Funge<I>, name: &str, write: bool) -> Result<()> {
+    match write {
+        true if !funge.rules.can_write_files() => Err(anyhow::Error::msg("writing files is disabled"))?,
+        false if !funge.rules.can_read_files() => Err(anyhow::Error::msg("reading files is disabled"))?,
+        true if funge.dry_run => funge.effects.push(Effect::WriteFile(name.to_string())),
+        false if funge.dry_run => funge.effects.push(Effect::ReadFile(name.to_string())),
+        _ => return Ok(())
+    }
+    Err(anyhow::Error::msg("files are not accessed in a dry run"))
+}
+
+// R and W push only the handle, so a count they cannot move in one step reflects instead of being cut short
+fn transfer<I: Int>(ip: &mut IP<I>) -> Result<usize> {
+    match pop_usize(ip) {
+        n if n > MAX_TRANSFER => Err(anyhow::Error::msg(format!("cannot move more than {} bytes at once", MAX_TRANSFER))),
+        n => Ok(n)
+    }
+}
+
+// failing operations reflect
+fn file_op<I: Int>(ip: &mut IP<I>, funge: &mut Funge<I>, op: char, f: Semantic<I>) -> Result<()> {
+    if let Err(error) = f(ip, funge) {
+        ip.reflect();
+        funge.warn(format!("file instruction {} failed: {}, reflected", op, error))?;
+    }
+    Ok(())
+}
+
+// file handles with an i/o buffer in funge-space, shared by all IPs
+fn file<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("FILE", vec![
+        ('C', |ip, funge| file_op(ip, funge, 'C', |ip, funge| funge.files.close(pop_usize(ip)))),
+        ('D', |ip, funge| file_op(ip, funge, 'D', |ip, funge| {
+            let name = ip.read_string()?;
+            access(funge, &name, true)?;
+            Ok(std::fs::remove_file(funge.path(&name))?)
+        })),
+        ('G', |ip, funge| file_op(ip, funge, 'G', |ip, funge| {
+            let handle = pop_usize(ip);
+            let file = funge.files.get(handle)?;
+            access(funge, &file.name, false)?;
+            let bytes = file.read_line()?;
+            ip.stack.push(cast(handle)?);
+            ip.push_string(&bytes.iter().map(|byte| char::from(*byte)).collect::<String>())?;
+            ip.stack.push(cast(bytes.len())?);
+            Ok(())
+        })),
+        ('L', |ip, funge| file_op(ip, funge, 'L', |ip, funge| {
+            let handle = pop_usize(ip);
+            let position = funge.files.get(handle)?.tell()?;
+            ip.stack.push(cast(handle)?);
+            ip.stack.push(cast(position)?);
+            Ok(())
+        })),
+        ('O', |ip, funge| file_op(ip, funge, 'O', |ip, funge| {
+            let name = ip.read_string()?;
+            let mode = pop_usize(ip);
+            let buffer = vector(ip)?;
+            if mode == 0 || mode >= 3 {
+                access(funge, &name, false)?;
+            }
+            if mode > 0 {
+                access(funge, &name, true)?;
+            }
+            let path = funge.path(&name).to_string_lossy().into_owned();
+            let handle = funge.files.open(path, mode, buffer)?;
+            ip.stack.push(cast(handle)?);
+            Ok(())
+        })),
+        ('P', |ip, funge| file_op(ip, funge, 'P', |ip, funge| {
+            let string = ip.read_string()?;
+            let handle = pop_usize(ip);
+            let file = funge.files.get(handle)?;
+            access(funge, &file.name, true)?;
+            file.write(&string.chars().map(|c| c as u8).collect::<Vec<u8>>())?;
+            ip.stack.push(cast(handle)?);
+            Ok(())
+        })),
+        ('R', |ip, funge| file_op(ip, funge, 'R', |ip, funge| {
+            let n = transfer(ip)?;
+            let handle = pop_usize(ip);
+            let file = funge.files.get(handle)?;
+            access(funge, &file.name, false)?;
+            for (x, byte) in file.read(n)?.into_iter().enumerate() {
+                funge.insert(cast(byte)?, vec![file.buffer[0] + x as isize, file.buffer[1]]);
+            }
+            ip.stack.push(cast(handle)?);
+            Ok(())
+        })),
+        ('S', |ip, funge| file_op(ip, funge, 'S', |ip, funge| {
+            let n: i64 = cast(ip.stack.pop())?;
+            let from = ip.stack.pop();
+            let handle = pop_usize(ip);
+            let file = funge.files.get(handle)?;
+            access(funge, &file.name, false)?;
+            file.seek(match cast::<i128, I>(from)? {
+                0 => SeekFrom::Start(n.max(0) as u64),
+                1 => SeekFrom::Current(n),
+                2 => SeekFrom::End(n),
+                from => Err(anyhow::Error::msg(format!("unknown origin {}", from)))?
+            })?;
+            ip.stack.push(cast(handle)?);
+            Ok(())
+        })),
+        ('W', |ip, funge| file_op(ip, funge, 'W', |ip, funge| {
+            let n = transfer(ip)?;
+            let handle = pop_usize(ip);
+            let file = funge.files.get(handle)?;
+            access(funge, &file.name, true)?;
+            let bytes: Vec<u8> = (0..n as isize)
+                .map(|x| funge.code[&vec![file.buffer[0] + x, file.buffer[1]]].to_i128().unwrap_or_default() as u8).collect();
+            file.write(&bytes)?;
+            ip.stack.push(cast(handle)?);
+            Ok(())
+        }))
+    ])
+}
+
+// C changes the working directory of the funge, not that of the process
+fn dirf<I: Int>() -> Fingerprint<I> {
+    Fingerprint::new("DIRF", vec![
+        ('C', |ip, funge| file_op(ip, funge, 'C', |ip, funge| {
+            let name = ip.read_string()?;
+            access(funge, &name, false)?;
+            let path = std::fs::canonicalize(funge.path(&name))?;
+            if !path.is_dir() {
+                Err(anyhow::Error::msg(format!("{} is not a directory", name)))?
+            }
+            funge.working_dir = path;
+            Ok(())
+        })),
+        ('M', |ip, funge| file_op(ip, funge, 'M', |ip, funge| {
+            let name = ip.read_string()?;
+            access(funge, &name, true)?;
+            Ok(std::fs::create_dir(funge.path(&name))?)
+        })),
+        ('R', |ip, funge| file_op(ip, funge, 'R', |ip, funge| {
+            let name = ip.read_string()?;
+            access(funge, &name, true)?;
+            Ok(std::fs::remove_dir(funge.path(&name))?)
+        }))
+    ])
+}
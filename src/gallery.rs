@@ -0,0 +1,32 @@
+pub struct Example {
+    pub name: &'static str,
+    pub description: &'static str,
+    source: &'static str
+}
+
+impl Example {
+    // the source without its #! line
+    pub fn code(&self) -> String {
+        self.source.lines().filter(|line| !line.starts_with("#!")).collect::<Vec<&str>>().join("\n")
+    }
+}
+
+
+pub const EXAMPLES: &[Example] = &[
+    Example { name: "hello_world", description: "prints Hello World!", source: include_str!("../examples/hello_world.bf") },
+    Example { name: "quine", description: "prints its own source", source: include_str!("../examples/quine1.bf") },
+    Example { name: "sieve", description: "sieve of Eratosthenes, prints the primes below 80",
+              source: include_str!("../examples/sieve.bf") },
+    Example { name: "mandelbrot", description: "draws the Mandelbrot set in ascii, slowly",
+              source: include_str!("../examples/mandelbrot.bf") },
+    Example { name: "99", description: "99 bottles of beer", source: include_str!("../examples/99.bf") },
+    Example { name: "factorial", description: "factorial of the number given as input",
+              source: include_str!("../examples/factorial0.bf") },
+    Example { name: "pi", description: "prints digits of pi", source: include_str!("../examples/pi.bf") },
+    Example { name: "dna", description: "prints a random DNA sequence", source: include_str!("../examples/dna.bf") },
+];
+
+
+pub fn get(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|example| example.name == name)
+}
@@ -1,39 +1,146 @@
-use std::collections::HashMap;
-use std::{env, fs, fmt, fmt::{Debug, Display, Formatter}, io};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::{env, fs, fmt, fmt::{Debug, Display, Formatter}, io, path::PathBuf};
 use std::ops::{Add, Index, IndexMut, Sub};
-use std::{hash::Hash, path::Path, str::FromStr, io::stdin};
+use std::{hash::Hash, str::FromStr, io::{stdin, BufRead}};
 use std::cmp::{max, min};
 use std::process::Command;
 use std::io::Write;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, atomic::{AtomicBool, Ordering}};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use anyhow::{Error, Result};
-use chrono::{offset::Local, {Datelike, Timelike}};
-use rand::Rng;
-use num::{Integer, NumCast};
+use chrono::{offset::Local, {Datelike, NaiveDate, NaiveDateTime, Timelike}};
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+use num::{CheckedDiv, Integer, NumCast, traits::{CheckedRem, WrappingAdd, WrappingMul, WrappingSub}};
 use strum_macros::EnumString;
 use regex::Regex;
+use trace::{Tracer, TraceRecord};
+use fingerprint::Semantic;
+use sockets::Sockets;
+use files::Files;
+pub use builder::FungeBuilder;
+use convert::{cast, cast_vec, cells_to_string, from_char, to_char};
+
+#[cfg(feature = "bench")]
+pub mod bench;
+mod builder;
+pub mod convert;
+mod files;
+pub mod fingerprint;
+pub mod gallery;
+pub mod ops;
+pub mod prelude;
+pub mod slice;
+mod sockets;
+pub mod trace;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const MAX_NESTING: usize = 16;
+const MAX_IPS: usize = 10000;
+// the stats of at most this many stopped IPs are kept by id, older ones are added up in SpaceStats::stopped
+const MAX_STOPPED_IP_STATS: usize = 64;
+// how often the reporter is called while running, the time is only looked at every PROGRESS_STEPS steps
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+const PROGRESS_STEPS: isize = 1024;
+
+
+// the version as y reports it: major * 1000000 + minor * 1000 + patch, a pre-release or build suffix is ignored
+pub fn version_number(version: &str) -> Result<i128> {
+    let error = || Error::msg(format!("Version {} is not major.minor.patch with minor and patch below 1000.", version));
+    let numbers = version.split(['-', '+']).next().unwrap_or_default().split('.')
+        .map(|part| part.parse::<i128>().map_err(|_| error())).collect::<Result<Vec<_>>>()?;
+    match numbers[..] {
+        [major, minor, patch] if minor < 1000 && patch < 1000 => Ok(major * 1000000 + minor * 1000 + patch),
+        _ => Err(error())
+    }
+}
+
+// the handprint y reports, the name packed in bytes from the lowest, four ASCII characters fit a 32 bit cell
+pub fn handprint(name: &str) -> i128 {
+    name.bytes().rev().fold(0, |handprint, b| handprint * 256 + b as i128)
+}
+
 
+// runs code with each input as a line for & and ~, sandboxed: limits that stop runaway programs, no = and no access
+// to files (i, o, FILE, DIRF) or the network, returns the exit code and everything written
+pub fn run_str(code: &str, input: &[&str]) -> Result<(i32, String)> {
+    let mut funge = Funge::<isize>::new(code)?
+        .without_instructions("=io")
+        .without_network()
+        .with_limits(Limits {
+            max_steps: Some(10_000_000),
+            max_time: Some(Duration::from_secs(10)),
+            max_cells: Some(1_000_000),
+            max_output: Some(1_000_000),
+            ..Limits::new()
+        })
+        .with_input(IO::new()
+            .with_store(input.iter().map(|line| line.to_string()).collect())
+            .with_input(|store| store.pop().ok_or(Error::new(FungeError::Input))))
+        .with_output(IO::new().with_output(|_, _| Ok(())));
+    let mut output = String::new();
+    loop {
+        let steps = funge.steps;
+        funge = match funge.step_ips() {
+            Ok(funge) => funge,
+            Err(error) => return Funge::<isize>::outcome(&error, steps, Vec::new()).into_result().map(|code| (code, output))
+        };
+        output.push_str(funge.last_output());
+        if funge.ips.is_empty() {
+            return Ok((0, output))
+        }
+    }
+}
 
-pub trait Int: Integer + NumCast + FromStr + Hash + Clone + Copy + Sync + Send + Display + 'static {}
-impl<I: Integer + NumCast + FromStr + Hash + Clone + Copy + Sync + Send + Display + 'static> Int for I {}
+pub trait Int: Integer + NumCast + CheckedDiv + CheckedRem + WrappingAdd + WrappingSub + WrappingMul + FromStr + Hash + Clone
+    + Copy + Sync + Send + Display + 'static {}
+impl<I: Integer + NumCast + CheckedDiv + CheckedRem + WrappingAdd + WrappingSub + WrappingMul + FromStr + Hash + Clone + Copy
+    + Sync + Send + Display + 'static> Int for I {}
 
 
 #[derive(Debug, thiserror::Error)]
 enum FungeError {
     #[error("Could not convert from primitive.")]
     Casting,
-    #[error("No file name.")]
-    FileName,
-    #[error("Cannot convert String.")]
-    String,
     #[error("Invalid input.")]
     Input,
     #[error("Unrecognized version: {0}")]
     Version(String),
     #[error("Funge exited with return code {0}.")]
-    Quit(i32)
+    Quit(i32),
+    #[error("Shared funge lock was poisoned.")]
+    Lock,
+    #[error("Stack underflow at ({0}, {1}).")]
+    Underflow(isize, isize),
+    #[error("Source is not printable ASCII at: {0}")]
+    NonAscii(String),
+    #[error("More than {0} IPs.")]
+    TooManyIps(usize),
+    #[error("Step limit of {0} reached.")]
+    StepLimit(usize),
+    #[error("Time limit of {0:?} reached.")]
+    Timeout(Duration),
+    #[error("More than {0} cells in funge-space.")]
+    CellLimit(usize),
+    #[error("More than {0} characters of output.")]
+    OutputLimit(usize),
+    #[error("Stack deeper than {0}.")]
+    StackLimit(usize),
+    #[error("Cannot parse limits: {0}")]
+    Limits(String),
+    #[error("Cancelled.")]
+    Cancelled,
+    #[error("The literal cache diverged from the reference at step {0}:\n{1}")]
+    Diverged(isize, String),
+    #[error("Invalid {0}: {1}")]
+    Option(&'static str, String),
+    #[error("Argument {0} does not fit: {1}.")]
+    Argument(String, String),
+    // a run that did not halt, as an error again, so that the reason can still be found
+    #[error("{1}")]
+    Stopped(StopReason, String)
 }
 
 
@@ -43,7 +150,7 @@ fn split_string(string: String) -> Result<Vec<String>> {
     let mut res = Vec::new();
     let r = Regex::new(r"[^\\](\s)")?;
     loop {
-        if let Some(m) = r.find(&string) {
+        if let Some(m) = r.find(string) {
             (_mat, string) = string.split_at(m.end());
             res.push(_mat.trim().replace(r"\ ", " ").to_string());
         } else {
@@ -54,121 +161,278 @@ fn split_string(string: String) -> Result<Vec<String>> {
     Ok(res)
 }
 
-
-
-pub fn join<T: ToString>(v: &Vec<T>, s: &str) -> String {
-    let mut string = String::new();
-    if v.len() > 1 {
-        for i in 0..v.len() - 1 {
-            string.push_str(&v[i].to_string());
-            string.push_str(&s);
+// the first number in s, with a minus sign right before it, and the rest of s;
+// digits that would overflow the cell are left in the rest
+fn parse_number<I: Int>(s: &str) -> Option<(I, &str)> {
+    let start = s.find(|c: char| c.is_ascii_digit())?;
+    let sign = if s[..start].ends_with('-') { -1 } else { 1 };
+    let mut value = 0i128;
+    let mut end = start;
+    for (i, c) in s[start..].char_indices() {
+        let Some(digit) = c.to_digit(10) else { break };
+        match value.checked_mul(10).and_then(|value| value.checked_add(sign * digit as i128)) {
+            Some(next) if <I as NumCast>::from(next).is_some() => value = next,
+            _ => break
         }
+        end = start + i + 1;
     }
-    if v.len() > 0 {
-        string.push_str(&v[v.len() - 1].to_string());
-    }
-    string
+    Some((cast(value).ok()?, &s[end..]))
 }
 
-pub fn cast_int<I: NumCast, J: NumCast>(j: J) -> Result<I> {
-    Ok(I::from(j).ok_or(Error::new(FungeError::Casting))?)
-}
 
-fn cast_vec_int<I: NumCast, J: NumCast>(j: Vec<J>) -> Result<Vec<I>> {
-    let mut i = Vec::<I>::new();
-    for n in j {
-        i.push(cast_int(n)?);
-    }
-    Ok(i)
+
+#[doc(hidden)]
+#[deprecated(note = "use convert::join")]
+pub fn join<T: ToString>(v: &[T], s: &str) -> String {
+    convert::join(v, s)
 }
 
-fn vec_to_string<I: NumCast>(vec: Vec<I>) -> String {
-    join(&vec.into_iter().map(|i| {
-        chr(match cast_int::<u8, I>(i) {
-            Ok(n @ 32..=126) | Ok(n @ 161..=255) => n,
-            _ => 164
-        }).expect("These values should work.")
-    }).collect::<Vec<char>>(), "")
+#[doc(hidden)]
+#[deprecated(note = "use convert::cast")]
+pub fn cast_int<I: NumCast, J: NumCast>(j: J) -> Result<I> {
+    cast(j)
 }
 
+#[doc(hidden)]
+#[deprecated(note = "use convert::from_char")]
 pub fn ord<I: NumCast>(c: char) -> Result<I> {
-    Ok(cast_int::<_, u32>(c.try_into()?)?)
+    from_char(c)
 }
 
+#[doc(hidden)]
+#[deprecated(note = "use convert::to_char")]
 pub fn chr<I: NumCast>(i: I) -> Result<char> {
-    Ok(cast_int::<u32, _>(i)?.try_into()?)
+    to_char(i)
 }
 
-fn add<I: Add + Copy>(a: &Vec<I>, b: &Vec<I>) -> Vec<I> where
+fn add<I: Add + Copy>(a: &[I], b: &[I]) -> Vec<I> where
     Vec<I>: FromIterator<<I as Add>::Output> {
     a.iter().zip(b.iter()).map(|(&a, &b)| a + b).collect()
 }
 
-fn sub<I: Sub + Copy>(a: &Vec<I>, b: &Vec<I>) -> Vec<I> where
+fn sub<I: Sub + Copy>(a: &[I], b: &[I]) -> Vec<I> where
     Vec<I>: FromIterator<<I as Sub>::Output> {
     a.iter().zip(b.iter()).map(|(&a, &b)| a - b).collect()
 }
 
 
+// which IP asks for input with which instruction, so a front-end can say what it is waiting for
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct InputRequest {
+    pub ip: usize,
+    pub position: Vec<isize>,
+    pub op: char,
+    pub steps: isize
+}
+
+impl Display for InputRequest {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let what = match self.op {
+            '&' => "a number",
+            '~' => "a character",
+            _ => "input"
+        };
+        write!(f, "IP {} at ({}) requests {} via {}", self.ip, convert::join(&self.position, ","), what, self.op)
+    }
+}
+
+// a value given up front, a number for & or a string for ~
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Argument {
+    Int(i128),
+    Str(String)
+}
+
+impl Display for Argument {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Argument::Int(n) => write!(f, "{}", n),
+            Argument::Str(s) => write!(f, "{:?}", s)
+        }
+    }
+}
+
+// closures, so that what they read from or write to can be kept in them, the store is that of the IO
+pub type Prompt = Arc<dyn Fn(&mut Vec<String>, &InputRequest) -> Result<String> + Send + Sync>;
+type Input = Arc<dyn Fn(&mut Vec<String>) -> Result<String> + Send + Sync>;
+type Output = Arc<dyn Fn(&mut Vec<String>, String) -> Result<()> + Send + Sync>;
+
+
 #[derive(Clone)]
 pub struct IO {
     pub store: Vec<String>,
-    input: fn(&mut Vec<String>) -> Result<String>,
-    output: fn(&mut Vec<String>, String) -> Result<()>,
+    input: Input,
+    prompt: Option<Prompt>,
+    output: Output,
+    buffered: bool,
+    buffer: String,
+    number_format: NumberFormat
+}
+
+impl Default for IO {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IO {
     pub fn new() -> Self {
         Self {
             store: Vec::new(),
-            input: |store| {
+            input: Arc::new(|store| {
                 Ok(match store.pop() {
                     None => {
                         let mut s = String::new();
-                        stdin().read_line(&mut s)?;
+                        if stdin().read_line(&mut s)? == 0 {
+                            Err(Error::new(FungeError::Input))?
+                        }
                         s
                     }
                     Some(s) => s
                 })
-            },
-            output: |_, s| {
+            }),
+            prompt: None,
+            output: Arc::new(|_, s| {
                 print!("{}", s);
                 io::stdout().flush().unwrap_or(());
                 Ok(())
-            }
+            }),
+            buffered: false,
+            buffer: String::new(),
+            number_format: NumberFormat::Spec
         }
     }
 
+    pub fn binary() -> Self {
+        Self::new()
+            .with_input(|store| {
+                Ok(match store.pop() {
+                    None => {
+                        let mut bytes = Vec::new();
+                        if stdin().lock().read_until(b'\n', &mut bytes)? == 0 {
+                            Err(Error::new(FungeError::Input))?
+                        }
+                        bytes.into_iter().map(char::from).collect()
+                    }
+                    Some(s) => s
+                })
+            })
+            .with_output(|_, s| {
+                let bytes = s.chars().flat_map(|c| match u8::try_from(c) {
+                    Ok(byte) => vec![byte],
+                    Err(_) => c.to_string().into_bytes()
+                }).collect::<Vec<u8>>();
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(&bytes)?;
+                stdout.flush()?;
+                Ok(())
+            })
+    }
+
+    pub fn stderr() -> Self {
+        Self::new().with_output(|_, s| {
+            eprint!("{}", s);
+            Ok(())
+        })
+    }
+
     pub fn with_store(mut self, mut store: Vec<String>) -> Self {
         store.reverse();
         self.store = store;
         self
     }
 
-    pub fn with_input(mut self, fun: fn(&mut Vec<String>) -> Result<String>) -> Self {
-        self.input = fun;
+    pub fn with_input(mut self, fun: impl Fn(&mut Vec<String>) -> Result<String> + Send + Sync + 'static) -> Self {
+        self.input = Arc::new(fun);
+        self.prompt = None;
+        self
+    }
+
+    // like with_input, but also told which IP and instruction are asking
+    pub fn with_prompt(mut self, fun: impl Fn(&mut Vec<String>, &InputRequest) -> Result<String> + Send + Sync + 'static)
+        -> Self {
+        self.prompt = Some(Arc::new(fun));
+        self
+    }
+
+    pub fn with_output(mut self, fun: impl Fn(&mut Vec<String>, String) -> Result<()> + Send + Sync + 'static) -> Self {
+        self.output = Arc::new(fun);
+        self
+    }
+
+    pub fn with_buffering(mut self, buffered: bool) -> Self {
+        self.buffered = buffered;
         self
     }
 
-    pub fn with_output(mut self, fun: fn(&mut Vec<String>, String) -> Result<()>) -> Self {
-        self.output = fun;
+    pub fn is_buffered(&self) -> bool {
+        self.buffered
+    }
+
+    pub fn with_number_format(mut self, number_format: NumberFormat) -> Self {
+        self.number_format = number_format;
         self
     }
 
+    pub fn number_format(&self) -> NumberFormat {
+        self.number_format
+    }
+
+    // Display on integers never uses locale grouping, also not for 128 bit cells
+    pub(crate) fn format_number<I: Int>(&self, n: I) -> String {
+        match self.number_format {
+            NumberFormat::Spec => format!("{} ", n),
+            NumberFormat::Bare => n.to_string(),
+            NumberFormat::Newline => format!("{}\n", n)
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.store.len()
     }
 
-    fn pop(&mut self) -> Result<String> {
-        (self.input)(&mut self.store)
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    fn pop(&mut self, request: &InputRequest) -> Result<String> {
+        match &self.prompt {
+            Some(prompt) => prompt(&mut self.store, request),
+            None => (self.input)(&mut self.store)
+        }
+    }
+
+    fn unread(&mut self, s: String) {
+        if !s.is_empty() {
+            self.store.push(s);
+        }
     }
 
     fn push(&mut self, s: String) -> Result<()> {
-        (self.output)(&mut self.store, s)
+        if self.buffered {
+            self.buffer.push_str(&s);
+            if s.contains('\n') {
+                self.flush()?;
+            }
+            Ok(())
+        } else {
+            (self.output)(&mut self.store, s)
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            Ok(())
+        } else {
+            let s = std::mem::take(&mut self.buffer);
+            (self.output)(&mut self.store, s)
+        }
     }
 
     pub fn get(&self) -> String {
-        join(&self.store, "")
+        convert::join(&self.store, "")
     }
 }
 
@@ -196,10 +460,31 @@ impl<I: Int> Stack<I> {
         self.stack.push(cell)
     }
 
+    fn pop_bottom(&mut self) -> I {
+        match self.stack.is_empty() {
+            true => I::zero(),
+            false => self.stack.remove(0)
+        }
+    }
+
+    fn push_bottom(&mut self, cell: I) {
+        self.stack.insert(0, cell)
+    }
+
     fn extend(&mut self, cells: Vec<I>) {
         self.stack.extend(cells);
     }
 
+    // like pushing each to the bottom, so the last ends up lowest
+    fn extend_bottom(&mut self, cells: Vec<I>) {
+        self.stack.splice(0..0, cells.into_iter().rev());
+    }
+
+    // counted from the bottom
+    fn get(&self, index: usize) -> Option<I> {
+        self.stack.get(index).copied()
+    }
+
     fn len(&self) -> usize {
         self.stack.len()
     }
@@ -207,26 +492,23 @@ impl<I: Int> Stack<I> {
 
 impl<I: Int> Display for Stack<I> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "[{}]", join(&self.stack, ", "))
+        write!(f, "[{}]", convert::join(&self.stack, ", "))
     }
 }
 
-impl<I: Int> Index<usize> for Stack<I> {
-    type Output = I;
-
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.stack[index]
-    }
-}
 
 #[derive(Clone)]
 struct StackStack<I: Int> {
-    stackstack: Vec<Stack<I>>
+    stackstack: Vec<Stack<I>>,
+    underflows: usize,
+    // MODE: push to and pop from the bottom of the TOSS
+    invert: bool,
+    queue: bool
 }
 
 impl<I: Int> StackStack<I> {
     fn new() -> Self {
-        Self { stackstack: vec![Stack::new()] }
+        Self { stackstack: vec![Stack::new()], underflows: 0, invert: false, queue: false }
     }
 
     fn check_stack(&mut self) {
@@ -238,19 +520,31 @@ impl<I: Int> StackStack<I> {
     fn pop(&mut self) -> I {
         self.check_stack();
         let x = self.len_stack();
-        self.stackstack[x - 1].pop()
+        if self.stackstack[x - 1].len() == 0 {
+            self.underflows += 1;
+        }
+        match self.queue {
+            true => self.stackstack[x - 1].pop_bottom(),
+            false => self.stackstack[x - 1].pop()
+        }
     }
 
     fn push(&mut self, cell: I) {
         self.check_stack();
         let x = self.len_stack();
-        self.stackstack[x - 1].push(cell);
+        match self.invert {
+            true => self.stackstack[x - 1].push_bottom(cell),
+            false => self.stackstack[x - 1].push(cell)
+        }
     }
 
     fn extend(&mut self, cells: Vec<I>) {
         self.check_stack();
         let x = self.len_stack();
-        self.stackstack[x - 1].extend(cells);
+        match self.invert {
+            true => self.stackstack[x - 1].extend_bottom(cells),
+            false => self.stackstack[x - 1].extend(cells)
+        }
     }
 
     fn pop_stack(&mut self) -> Stack<I> {
@@ -266,6 +560,13 @@ impl<I: Int> StackStack<I> {
 
     fn len_stack(&self) -> usize { self.stackstack.len() }
 
+    fn top(&self) -> Vec<I> {
+        match self.stackstack.last() {
+            Some(stack) => stack.stack.clone(),
+            None => Vec::new()
+        }
+    }
+
     fn len(&self) -> usize {
         if self.len_stack() == 0 {
             0
@@ -303,7 +604,7 @@ impl<I: Int> IndexMut<usize> for StackStack<I> {
 
 impl<I: Int> Display for StackStack<I> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{}", join(&self.stackstack, "\n"))
+        write!(f, "{}", convert::join(&self.stackstack, "\n"))
     }
 }
 
@@ -316,7 +617,14 @@ pub struct IP<I: Int> {
     pub offset: Vec<isize>,
     string: bool,
     stack: StackStack<I>,
-    fingerprint_ops: HashMap<I, ()>
+    fingerprint_ops: HashMap<I, Vec<Semantic<I>>>,
+    // MODE: direction changes add to the delta, and [ ] { } ( ) turn into their counterpart once executed
+    hover: bool,
+    switch: bool,
+    // HRTI: the time of the last M
+    mark: Option<Duration>,
+    // SUBR: addresses are relative to the storage offset
+    relative: bool
 }
 
 
@@ -329,23 +637,61 @@ impl<I: Int> IP<I> {
             offset: vec![0, 0],
             string: false,
             stack: StackStack::new(),
-            fingerprint_ops: HashMap::new()
+            fingerprint_ops: HashMap::new(),
+            hover: false,
+            switch: false,
+            mark: None,
+            relative: false
         };
-        if let Ok(32 | 59) = cast_int(new.op(funge)) {
+        if let Ok(32 | 59) = cast(new.op(funge)) {
             new = new.advance(funge, false)?;
         };
         Ok(new)
     }
 
+    pub fn position(&self) -> &[isize] {
+        &self.position
+    }
+
+    pub fn delta(&self) -> &[isize] {
+        &self.delta
+    }
+
+    pub fn is_string_mode(&self) -> bool {
+        self.string
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn stack(&self) -> Vec<I> {
+        self.stack.top()
+    }
+
+    // every stack of the stack stack, the top one last
+    pub fn stacks(&self) -> Vec<&[I]> {
+        self.stack.stackstack.iter().map(|stack| stack.stack.as_slice()).collect()
+    }
+
+    // pushes the cells on the top stack the way instructions do, the last one ends up on top unless MODE inverted it
+    pub fn push_cells(&mut self, cells: Vec<I>) {
+        self.stack.extend(cells);
+    }
+
     fn split(&self, id: usize) -> Self {
         Self {
-            id: id,
+            id,
             position: self.position.to_owned(),
             delta: self.delta.to_owned(),
             offset: self.offset.to_owned(),
             string: self.string,
             stack: self.stack.to_owned(),
-            fingerprint_ops: self.fingerprint_ops.to_owned()
+            fingerprint_ops: self.fingerprint_ops.to_owned(),
+            hover: self.hover,
+            switch: self.switch,
+            mark: self.mark,
+            relative: self.relative
         }
     }
 
@@ -358,10 +704,28 @@ impl<I: Int> IP<I> {
     }
 
     fn next_op(&self, funge: &Funge<I>) -> Result<I> {
-        let next_pos = self.next_valid_pos(&funge, false)?;
+        let next_pos = self.next_valid_pos(funge, false)?;
         Ok(funge.code[&next_pos])
     }
 
+    fn input_request(&self, op: char, steps: isize) -> InputRequest {
+        InputRequest { ip: self.id, position: self.position.to_owned(), op, steps }
+    }
+
+    fn go(&mut self, delta: Vec<isize>) {
+        self.delta = match self.hover {
+            true => add(&self.delta, &delta),
+            false => delta
+        };
+    }
+
+    // continue at target with delta: step back from it, so the IP lands on it when it advances
+    fn jump(&mut self, funge: &Funge<I>, target: Vec<isize>, delta: Vec<isize>) {
+        self.delta = delta.iter().map(|i| -i).collect();
+        self.position = self.wrapped_pos(funge, target);
+        self.delta = delta;
+    }
+
     fn reflect(&mut self) {
         self.delta = self.delta.iter().map(|i| -i).collect();
     }
@@ -383,18 +747,23 @@ impl<I: Int> IP<I> {
         self.position = self.next_pos(funge, self.position.to_owned());
     }
 
-    fn check_pos(&self, pos: &Vec<isize>, funge: &Funge<I>) -> bool {
+    fn check_pos(&self, pos: &[isize], funge: &Funge<I>) -> bool {
         (funge.extent.left <= pos[0]) & (pos[0] < funge.extent.right) &
             (funge.extent.top <= pos[1]) & (pos[1] < funge.extent.bottom)
     }
 
+    // an IP that left funge-space without wrapping will only find spaces
+    fn lost(&self, funge: &Funge<I>, pos: &[isize]) -> bool {
+        (funge.rules.wrap == Wrap::Unbounded) && !self.check_pos(pos, funge)
+    }
+
     fn next_valid_pos(&self, funge: &Funge<I>, skip: bool) -> Result<Vec<isize>> {
         let mut pos = self.position.to_owned();
-        let space: I = cast_int(32)?;
-        let semicolon: I = cast_int(59)?;
+        let space: I = cast(32)?;
+        let semicolon: I = cast(59)?;
         if self.string {
             if self.op_at(funge, &pos) == space {
-                while self.op_at(funge, &pos) == space {
+                while (self.op_at(funge, &pos) == space) & !self.lost(funge, &pos) {
                     pos = self.next_pos(funge, pos);
                 }
             } else {
@@ -407,12 +776,12 @@ impl<I: Int> IP<I> {
             loop {
                 if self.op_at(funge, &pos) == semicolon {
                     pos = self.next_pos(funge, pos);
-                    while self.op_at(funge, &pos) != semicolon {
+                    while (self.op_at(funge, &pos) != semicolon) & !self.lost(funge, &pos) {
                         pos = self.next_pos(funge, pos);
                     }
                     pos = self.next_pos(funge, pos);
                 }
-                while self.op_at(funge, &pos) == space {
+                while (self.op_at(funge, &pos) == space) & !self.lost(funge, &pos) {
                     pos = self.next_pos(funge, pos);
                 }
                 if self.op_at(funge, &pos) != semicolon {
@@ -423,7 +792,30 @@ impl<I: Int> IP<I> {
         Ok(pos)
     }
 
-    fn next_pos(&self, funge: &Funge<I>, mut pos: Vec<isize>) -> Vec<isize> {
+    fn next_pos(&self, funge: &Funge<I>, pos: Vec<isize>) -> Vec<isize> {
+        match funge.rules.wrap {
+            Wrap::Lahey => self.next_pos_lahey(funge, pos),
+            Wrap::Torus => {
+                let (width, height) = funge.torus_size();
+                let pos = add(&pos, &self.delta);
+                vec![pos[0].rem_euclid(width), pos[1].rem_euclid(height)]
+            }
+            Wrap::Unbounded => add(&pos, &self.delta)
+        }
+    }
+
+    // the next cell in Lahey-space, on the other side of the code when at the edge
+    fn wrapped_pos(&self, funge: &Funge<I>, mut pos: Vec<isize>) -> Vec<isize> {
+        if (funge.rules.wrap != Wrap::Lahey) | !self.check_pos(&pos, funge) | self.check_pos(&add(&pos, &self.delta), funge) {
+            return self.next_pos(funge, pos)
+        }
+        while self.check_pos(&sub(&pos, &self.delta), funge) {
+            pos = sub(&pos, &self.delta);
+        }
+        pos
+    }
+
+    fn next_pos_lahey(&self, funge: &Funge<I>, mut pos: Vec<isize>) -> Vec<isize> {
         if self.check_pos(&pos, funge) {  // always do one step outside before wrapping
             add(&pos, &self.delta)
         } else {
@@ -447,158 +839,228 @@ impl<I: Int> IP<I> {
             if f == I::zero() {
                 return Ok(string)
             } else {
-                string.push_str(&chr(f)?.to_string())
+                string.push(to_char(f)?)
+            }
+        }
+    }
+
+    // the other way around: 0 first and then the characters from last to first
+    fn push_string(&mut self, string: &str) -> Result<()> {
+        self.stack.push(I::zero());
+        for c in string.chars().rev() {
+            self.stack.push(from_char(c)?);
+        }
+        Ok(())
+    }
+
+    fn read_fingerprint(&mut self) -> Result<i128> {
+        let mut id = 0i128;
+        for _ in 0..cast(self.stack.pop())? {
+            id = id.wrapping_mul(256).wrapping_add(cast(self.stack.pop())?);
+        }
+        Ok(id)
+    }
+
+    // each letter keeps a stack of semantics, ( pushes those of the fingerprint and ) pops them again
+    fn load_fingerprint(&mut self, funge: &Funge<I>) -> Result<()> {
+        let id = self.read_fingerprint()?;
+        match fingerprint::get::<I>(id).filter(|fingerprint| funge.rules.network || !fingerprint.network()) {
+            Some(fingerprint) => {
+                for (op, semantic) in &fingerprint.ops {
+                    self.fingerprint_ops.entry(from_char(*op)?).or_default().push(*semantic);
+                }
+                self.stack.push(cast(id).unwrap_or(I::zero()));
+                self.stack.push(I::one());
             }
+            None => self.reflect()
         }
+        Ok(())
     }
 
-    fn read_fingerprint(&mut self) -> Result<()> {
-        for _ in 0..cast_int(self.stack.pop())? {
-            self.stack.pop();
+    fn unload_fingerprint(&mut self) -> Result<()> {
+        let id = self.read_fingerprint()?;
+        match fingerprint::get::<I>(id) {
+            Some(fingerprint) => for (op, _) in &fingerprint.ops {
+                let op = from_char(*op)?;
+                if let Some(semantics) = self.fingerprint_ops.get_mut(&op) {
+                    semantics.pop();
+                    if semantics.is_empty() {
+                        self.fingerprint_ops.remove(&op);
+                    }
+                }
+            }
+            None => self.reflect()
         }
         Ok(())
     }
 
     fn get_info(&mut self, funge: &Funge<I>) -> Result<usize> {
-        let time = Local::now();
-        let vars = env::vars();
+        let time = funge.clock.unwrap_or_else(|| Local::now().naive_local());
         let mut l = Vec::new();
         let size = self.stack.len();
         for stack in &self.stack.stackstack {
-            l.push(cast_int(stack.len())?);
+            l.push(cast(stack.len())?);
         }
-        let mut f = 0;
-        for (i, c) in "wprusty".chars().enumerate() {
-            f += (256 as isize).pow(i as u32) * ord::<isize>(c)?;
-        }
-        let mut flags = 16;  // unbuffered IO
-        for (i, op) in [116, 105, 111, 61].iter().enumerate() {  // tio=
-            if funge.rules.instruction_set.contains(op) {
+        let mut flags = if funge.output.is_buffered() { 0 } else { 16 };  // unbuffered IO
+        for (i, enabled) in [funge.rules.can_split(), funge.rules.can_read_files(), funge.rules.can_write_files(),
+                             funge.rules.can_execute()].into_iter().enumerate() {
+            if enabled {
                 flags += 2isize.pow(i as u32);
             }
         }
 
         let mut r = Vec::new();
-        for (key, value) in vars {
-            let j: Vec<I> = key.chars().map(|i| ord(i).expect("")).collect();
-            r.extend(j);
-            r.push(ord('=')?);
-            let j: Vec<I> = value.chars().map(|i| ord(i).expect("")).collect();
-            r.extend(j);
+        for (key, value) in &funge.env {
+            r.extend(key.chars().map(from_char).collect::<Result<Vec<I>>>()?);
+            r.push(from_char('=')?);
+            r.extend(value.chars().map(from_char).collect::<Result<Vec<I>>>()?);
             r.push(I::zero());
         }
         r.push(I::zero());
-        r.push(I::zero());
         r.reverse();
         self.stack.extend(r);  // 20
 
+        // the path of the program first, then its arguments
         let mut r = Vec::new();
-        let args: Vec<String> = env::args().collect();
-        if args.len() > 1 {
-            for i in 1..args.len() {
-                let j: Vec<I> = args[i].chars().map(|i| ord(i).expect("")).collect();
-                r.extend(j);
-                r.push(I::zero());
-            }
+        for arg in &funge.argv {
+            r.extend(arg.chars().map(from_char).collect::<Result<Vec<I>>>()?);
+            r.push(I::zero());
         }
-        let file = &args[0];
-        let path = Path::new(&file);
-        let j: Vec<I> = path.file_name().ok_or(Error::new(FungeError::FileName))?
-            .to_str().ok_or(Error::new(FungeError::String))?
-            .chars().map(|i| ord(i).expect("")).collect();
-        r.extend(j);
-        r.push(I::zero());
         r.push(I::zero());
         r.push(I::zero());
         r.reverse();
         self.stack.extend(r);  // 19
 
         self.stack.extend(l);  // 18
-        self.stack.push(cast_int(self.stack.len_stack())?);  // 17
-        self.stack.push(cast_int(time.hour() * 256 * 256 + time.minute() * 256 + time.second())?);  // 16
-        self.stack.push(cast_int((time.year() - 1900) * 256 * 256 + (time.month() as i32) * 256 + (time.day() as i32))?);  // 15
-        self.stack.extend(cast_vec_int(vec![funge.extent.width() - 1, funge.extent.height() - 1])?);  // 14
-        self.stack.extend(cast_vec_int(vec![funge.extent.left, funge.extent.top])?);  // 13
-        self.stack.extend(cast_vec_int(self.offset.to_owned())?);  // 12
-        self.stack.extend(cast_vec_int(self.delta.to_owned())?);  // 11
-        self.stack.extend(cast_vec_int(self.position.to_owned())?);  // 10
+        self.stack.push(cast(self.stack.len_stack())?);  // 17
+        self.stack.push(cast(time.hour() * 256 * 256 + time.minute() * 256 + time.second())?);  // 16
+        self.stack.push(cast((time.year() - 1900) * 256 * 256 + (time.month() as i32) * 256 + (time.day() as i32))?);  // 15
+        self.stack.extend(cast_vec(vec![funge.extent.width() - 1, funge.extent.height() - 1])?);  // 14
+        self.stack.extend(cast_vec(vec![funge.extent.left, funge.extent.top])?);  // 13
+        self.stack.extend(cast_vec(self.offset.to_owned())?);  // 12
+        self.stack.extend(cast_vec(self.delta.to_owned())?);  // 11
+        self.stack.extend(cast_vec(self.position.to_owned())?);  // 10
         self.stack.push(I::zero());  // 9
-        self.stack.push(cast_int(*&self.id)?);  // 8
-        self.stack.push(cast_int(2)?);  // 7
-        self.stack.push(cast_int(ord::<I>(std::path::MAIN_SEPARATOR)?)?);  // 6
+        self.stack.push(cast(self.id)?);  // 8
+        self.stack.push(cast(2)?);  // 7
+        self.stack.push(cast(from_char::<I>(std::path::MAIN_SEPARATOR)?)?);  // 6
         self.stack.push(I::one());  // 5
-        self.stack.push(cast_int(VERSION.replace(".", "").parse::<isize>()?)?);  // 4
-        self.stack.push(cast_int(f)?);  // 3
-        self.stack.push(cast_int(std::mem::size_of::<I>())?);  // 2
-        self.stack.push(cast_int(flags)?);  // 1
+        self.stack.push(cast(version_number(VERSION)?)?);  // 4
+        self.stack.push(cast(funge.handprint)?);  // 3
+        self.stack.push(cast(std::mem::size_of::<I>())?);  // 2
+        self.stack.push(cast(flags)?);  // 1
 
         Ok(self.stack.len() - size)
     }
 
-    fn not_implemented(&mut self, funge: &Funge<I>) -> Result<()> {
+    fn not_implemented(&mut self, funge: &mut Funge<I>, op: I) -> Result<()> {
+        let message = format!("unknown instruction {} at ({}, {})", to_char(op).unwrap_or('¤'), self.position[0], self.position[1]);
         match funge.rules.on_error {
             OnError::Ignore => {
-                Ok(())
+                funge.warn(format!("{}, ignored", message))
             }
             OnError::Reflect => {
                 self.reflect();
-                Ok(())
+                funge.warn(format!("{}, reflected", message))
             }
             OnError::Quit => Err(Error::new(FungeError::Quit(0)))
         }
     }
 
-    fn step(self, funge: Funge<I>, n_ips: usize) -> Result<(Funge<I>, Vec<Self>)> {
-        let op = self.op(&funge);
-        let (funge, mut ips, skip) = self.exe(funge, op, n_ips)?;
-        ips = ips.into_iter().map(|ip| ip.advance(&funge, skip)).collect::<Result<Vec<IP<I>>>>()?;
-        Ok((funge, ips))
+    fn step(self, funge: &mut Funge<I>) -> Result<Vec<Self>> {
+        let mut op = self.op(funge);
+        for hook in &funge.hooks {
+            match hook(funge, &self, op) {
+                HookAction::Proceed => {}
+                HookAction::Skip => {
+                    let ip = self.advance(funge, false)?;
+                    return Ok(vec![ip])
+                }
+                HookAction::Replace(new) => op = new
+            }
+        }
+        let record = funge.trace.as_ref().map(|_| TraceRecord {
+            step: funge.steps,
+            ip: self.id,
+            position: (self.position[0], self.position[1]),
+            op: op.to_i128().unwrap_or_default(),
+            stack: self.stack.top().iter().map(|i| i.to_i128().unwrap_or_default()).collect(),
+            writes: Vec::new(),
+            output: String::new(),
+            name: funge.ip_name(self.id).map(|name| name.name.to_owned()),
+            command: None
+        });
+        let (id, position, underflows) = (self.id, self.position.to_owned(), self.stack.underflows);
+        let switch = match op.to_u8() {
+            Some(b'[') if self.switch && !self.string => Some(b']'),
+            Some(b']') if self.switch && !self.string => Some(b'['),
+            Some(b'{') if self.switch && !self.string => Some(b'}'),
+            Some(b'}') if self.switch && !self.string => Some(b'{'),
+            Some(b'(') if self.switch && !self.string => Some(b')'),
+            Some(b')') if self.switch && !self.string => Some(b'('),
+            _ => None
+        };
+        let (mut ips, skip) = self.exe(funge, op)?;
+        if let Some(counterpart) = switch {
+            funge.insert(cast(counterpart)?, position.to_owned());
+        }
+        funge.ip_stats.entry(id).or_default().instructions += 1;
+        for ip in &ips {
+            let stats = funge.ip_stats.entry(ip.id).or_default();
+            stats.max_stack = max(stats.max_stack, ip.stack_depth());
+            if let Some(max_stack) = funge.limits.max_stack.filter(|max_stack| ip.stack_depth() > *max_stack) {
+                Err(Error::new(FungeError::StackLimit(max_stack)))?
+            }
+        }
+        let underflows = ips.iter().map(|ip| ip.stack.underflows).max().unwrap_or(underflows) - underflows;
+        if underflows > 0 {
+            funge.underflow(position, op, underflows)?;
+        }
+        if let (Some(record), Some(trace)) = (record, funge.trace.as_mut()) {
+            trace.record(record)?;
+        }
+        ips = ips.into_iter().map(|ip| ip.advance(funge, skip)).collect::<Result<Vec<IP<I>>>>()?;
+        Ok(ips)
     }
 
-    fn exe(mut self, mut funge: Funge<I>, op: I, n_ips: usize) -> Result<(Funge<I>, Vec<Self>, bool)> {
+    fn exe(mut self, funge: &mut Funge<I>, op: I) -> Result<(Vec<Self>, bool)> {
         let mut new_ips = Vec::new();
         if self.string {
             match op.to_u8() {
                 Some(34) => { self.string = false }  // "
                 _ => { self.stack.push(op) }
             }
-        } else if self.fingerprint_ops.contains_key(&op) {
-            // self.fingerprint_ops[self.op(funge)]?
+        } else if self.lost(funge, &self.position) {
+            // drifting through empty space
+        } else if let Some(semantic) = self.fingerprint_ops.get(&op).and_then(|semantics| semantics.last()).copied() {
+            semantic(&mut self, funge)?;
         } else if let Some(n @ 0..=255) = op.to_u8() {
             if funge.rules.instruction_set.contains(&n) {
                 match n {
-                    43 => { // +
+                    43 => { // +, wraps around when the cell overflows
                         let b = self.stack.pop();
                         let a = self.stack.pop();
-                        self.stack.push(a + b);
+                        self.stack.push(a.wrapping_add(&b));
                     }
-                    45 => { // -
+                    45 => { // -, wraps around when the cell overflows
                         let b = self.stack.pop();
                         let a = self.stack.pop();
-                        self.stack.push(a - b);
+                        self.stack.push(a.wrapping_sub(&b));
                     }
-                    42 => { // *
+                    42 => { // *, wraps around when the cell overflows
                         let b = self.stack.pop();
                         let a = self.stack.pop();
-                        self.stack.push(a * b);
+                        self.stack.push(a.wrapping_mul(&b));
                     }
-                    47 => { // /
+                    47 => { // /, 0 when dividing by 0 or the smallest cell by -1
                         let b = self.stack.pop();
                         let a = self.stack.pop();
-                        if b == I::zero() {
-                            self.stack.push(I::zero());
-                        } else {
-                            self.stack.push(a / b);
-                        }
+                        self.stack.push(a.checked_div(&b).unwrap_or(I::zero()));
                     }
                     37 => { // %
                         let b = self.stack.pop();
                         let a = self.stack.pop();
-                        if b == I::zero() {
-                            self.stack.push(I::zero());
-                        } else {
-                            self.stack.push(a % b);
-                        }
+                        self.stack.push(a.checked_rem(&b).unwrap_or(I::zero()));
                     }
                     33 => { // !
                         let a = self.stack.pop();
@@ -617,13 +1079,12 @@ impl<I: Int> IP<I> {
                             self.stack.push(I::zero());
                         }
                     }
-                    62 => self.delta = vec![1, 0], // >
-                    60 => self.delta = vec![-1, 0], // <
-                    94 => self.delta = vec![0, -1], // ^
-                    118 => self.delta = vec![0, 1], // v
+                    62 => self.go(vec![1, 0]), // >
+                    60 => self.go(vec![-1, 0]), // <
+                    94 => self.go(vec![0, -1]), // ^
+                    118 => self.go(vec![0, 1]), // v
                     63 => { // ?
-                        let mut rng = rand::thread_rng();
-                        self.delta = match rng.gen_range(0..4) {
+                        self.delta = match funge.rng.gen_range(0..4) {
                             0 => { vec![-1, 0] }
                             1 => { vec![1, 0] }
                             2 => { vec![0, -1] }
@@ -632,16 +1093,16 @@ impl<I: Int> IP<I> {
                     }
                     95 => { // _
                         if self.stack.pop() == I::zero() {
-                            self.delta = vec![1, 0]
+                            self.go(vec![1, 0])
                         } else {
-                            self.delta = vec![-1, 0]
+                            self.go(vec![-1, 0])
                         }
                     }
                     124 => { // |
                         if self.stack.pop() == I::zero() {
-                            self.delta = vec![0, 1];
+                            self.go(vec![0, 1]);
                         } else {
-                            self.delta = vec![0, -1];
+                            self.go(vec![0, -1]);
                         }
                     }
                     34 => self.string = true, // "
@@ -657,59 +1118,77 @@ impl<I: Int> IP<I> {
                         self.stack.push(b);
                     }
                     36 => { self.stack.pop(); } // $
-                    46 => funge.output.push(format!("{} ", self.stack.pop()))?, // .
-                    44 => funge.output.push(format!("{}", chr(self.stack.pop())?))?, // ,
+                    46 => funge.write(funge.output.format_number(self.stack.pop()))?, // .
+                    44 => funge.write(to_char(self.stack.pop())?.to_string())?, // ,
+                    35 if funge.rules.jump_wraps => { // #
+                        self.position = self.wrapped_pos(funge, self.position.to_owned());
+                        return Ok((vec![self], true))
+                    }
                     35 => { // #
-                        self.movep(&funge);
-                        return Ok((funge, vec![self], true))
+                        self.movep(funge);
+                        return Ok((vec![self], true))
                     }
                     112 => { // p
-                        let y: isize = cast_int(self.stack.pop())?;
-                        let x: isize = cast_int(self.stack.pop())?;
+                        let y: isize = cast(self.stack.pop())?;
+                        let x: isize = cast(self.stack.pop())?;
                         let v = self.stack.pop();
                         funge.insert(v, vec![x + self.offset[0], y + self.offset[1]]);
                     }
                     103 => { // g
-                        let y: isize = cast_int(self.stack.pop())?;
-                        let x: isize = cast_int(self.stack.pop())?;
-                        self.stack.push(*&funge.code[&vec![x + self.offset[0], y + self.offset[1]]]);
+                        let y: isize = cast(self.stack.pop())?;
+                        let x: isize = cast(self.stack.pop())?;
+                        self.stack.push(funge.code[&vec![x + self.offset[0], y + self.offset[1]]]);
                     }
                     38 => { // &
-                        match funge.input.pop() {
-                            Ok(s) => {  // TODO: take until input would cause cell overflow
-                                let i: Vec<char> = s.chars()
-                                    .skip_while(|i| !i.is_digit(10))
-                                    .take_while(|i| i.is_digit(10)).collect();
-                                match join(&i, "").parse() {
-                                    Ok(n) => self.stack.push(n),
-                                    _ => self.reflect()
+                        funge.output.flush()?;
+                        // lines with only whitespace, like the end of the line a previous & read from, are skipped
+                        let mut input = funge.read(self.input_request('&', funge.steps));
+                        while matches!(&input, Ok(s) if s.trim().is_empty()) {
+                            input = funge.read(self.input_request('&', funge.steps));
+                        }
+                        match input {
+                            Ok(s) => match parse_number(&s) {
+                                Some((n, rest)) => {
+                                    self.stack.push(n);
+                                    funge.input.unread(rest.to_string());
+                                }
+                                None => {
+                                    self.reflect();
+                                    funge.warn(format!("no number in input {:?}, reflected", s))?;
                                 }
                             }
+                            Err(error) if matches!(error.downcast_ref(), Some(FungeError::Argument(..))) => Err(error)?,
                             Err(_) => self.reflect()
                         }
                     }
                     126 => { // ~
-                        match funge.input.pop() {
-                            Ok(s) => self.stack.push(ord(s.chars().nth(0).ok_or(Error::new(FungeError::Input))?)?),
+                        funge.output.flush()?;
+                        match funge.read(self.input_request('~', funge.steps)) {
+                            Ok(s) => {
+                                let mut chars = s.chars();
+                                self.stack.push(from_char(chars.next().ok_or(Error::new(FungeError::Input))?)?);
+                                funge.input.unread(chars.collect());
+                            }
+                            Err(error) if matches!(error.downcast_ref(), Some(FungeError::Argument(..))) => Err(error)?,
                             Err(_) => self.reflect()
                         }
                     }
-                    64 => return Ok((funge, Vec::new(), false)), // @
+                    64 => return Ok((Vec::new(), false)), // @
                     32 => { // space
-                        self = self.advance(&funge, false)?;
-                        let n_op = self.op(&funge);
-                        return Ok(self.exe(funge, n_op, n_ips)?);
+                        self = self.advance(funge, false)?;
+                        let n_op = self.op(funge);
+                        return self.exe(funge, n_op);
                     }
                     // 98 from here
                     91 => self.turn_left(), // [
                     93 => self.turn_right(), // ]
                     39 => { // '
-                        self.movep(&funge);
-                        self.stack.push(self.op(&funge));
-                        return Ok((funge, vec![self], true))
+                        self.movep(funge);
+                        self.stack.push(self.op(funge));
+                        return Ok((vec![self], true))
                     }
                     123 => { // {
-                        let n: isize = cast_int(self.stack.pop())?;
+                        let n: isize = cast(self.stack.pop())?;
                         let cells = if n > 0 {
                             let mut cells = Vec::new();
                             for _ in 0..n {
@@ -724,19 +1203,19 @@ impl<I: Int> IP<I> {
                             Vec::new()
                         };
                         for coordinate in &self.offset {
-                            self.stack.push(cast_int(*coordinate)?);
+                            self.stack.push(cast(*coordinate)?);
                         }
                         self.stack.push_stack(Stack::new());
                         for cell in cells {
                             self.stack.push(cell);
                         }
-                        self.offset = self.next_pos(&funge, self.position.to_owned());
+                        self.offset = self.next_pos(funge, self.position.to_owned());
                     }
                     125 => { // }
                         if self.stack.len_stack() <= 1 {
                             self.reflect()
                         } else {
-                            let n: isize = cast_int(self.stack.pop())?;
+                            let n: isize = cast(self.stack.pop())?;
                             let cells = if n > 0 {
                                 let mut cells = Vec::new();
                                 for _ in 0..n {
@@ -748,8 +1227,8 @@ impl<I: Int> IP<I> {
                                 Vec::new()
                             };
                             self.stack.pop_stack();
-                            let y = cast_int(self.stack.pop())?;
-                            let x = cast_int(self.stack.pop())?;
+                            let y = cast(self.stack.pop())?;
+                            let x = cast(self.stack.pop())?;
                             self.offset = vec![x, y];
                             if n > 0 {
                                 for cell in cells {
@@ -763,115 +1242,130 @@ impl<I: Int> IP<I> {
                         }
                     }
                     61 => { // =
-                        let mut command = split_string(self.read_string()?)?;
-                        if command.len() > 0 {
-                            match Command::new(command.remove(0)).args(command).output() {
-                                Ok(output) => {
-                                    funge.output.push(join(&output.stdout.into_iter().map(|i| chr(i)).collect::<Result<Vec<char>>>()?, ""))?;
-                                    self.stack.push(match output.status.code() {
-                                        Some(i) => cast_int(i)?,
-                                        None => I::zero()
-                                    });
-                                }
-                                Err(_) => self.stack.push(I::one())
-                            }
+                        let string = self.read_string()?;
+                        let mut command = split_string(string.to_owned())?;
+                        if funge.dry_run {
+                            funge.effects.push(Effect::Execute(string));
+                            self.stack.push(I::zero());
                         } else {
-                            self.stack.push(I::one());
+                            funge.last_command = Some(string.to_owned());
+                            if let Some(trace) = funge.trace.as_mut() {
+                                trace.command = Some(string.to_owned());
+                            }
+                            let return_code = if funge.rules.execute == Execute::Funge {
+                                match funge.execute_funge(command) {
+                                    Ok(return_code) => cast(return_code)?,
+                                    Err(error) => match StopReason::from(&error) {
+                                        // the nested funge runs under the limits of this one, so reaching them stops both
+                                        StopReason::StepLimit | StopReason::Timeout | StopReason::Cancelled | StopReason::Limit(_) =>
+                                            Err(error)?,
+                                        _ => {
+                                            funge.warn(format!("could not run {}: {}", string, error))?;
+                                            I::one()
+                                        }
+                                    }
+                                }
+                            } else if !command.is_empty() {
+                                match Command::new(command.remove(0)).args(command).current_dir(&funge.working_dir).output() {
+                                    Ok(output) => {
+                                        funge.write(convert::join(&output.stdout.into_iter().map(to_char).collect::<Result<Vec<char>>>()?, ""))?;
+                                        match output.status.code() {
+                                            Some(i) => cast(i)?,
+                                            None => I::zero()
+                                        }
+                                    }
+                                    Err(_) => I::one()
+                                }
+                            } else {
+                                I::one()
+                            };
+                            self.stack.push(return_code);
                         }
                     }
-                    40 => { // ( no fingerprints are implemented
-                        self.read_fingerprint()?;
-                        // self.fingerprint_ops[] = self.Reverse;
-                        self.reflect();
-                    }
-                    41 => { // )
-                        self.read_fingerprint()?;
-                        // self.fingerprint_ops.pop()
-                        self.reflect();
-                    }
+                    40 => self.load_fingerprint(funge)?, // (
+                    41 => self.unload_fingerprint()?, // )
                     105 => { // i
                         let file = self.read_string()?;
                         let flags = self.stack.pop();
-                        let y0 = cast_int(self.stack.pop())?;
-                        let x0 = cast_int(self.stack.pop())?;
-                        match read_file(&file) {
-                            Ok(text) => {
+                        let y0 = cast(self.stack.pop())?;
+                        let x0 = cast(self.stack.pop())?;
+                        let bytes = if funge.dry_run {
+                            funge.effects.push(Effect::ReadFile(file.to_owned()));
+                            Err(Error::msg("Files are not read in a dry run."))
+                        } else {
+                            fs::read(funge.path(&file)).map_err(Error::from)
+                        };
+                        match bytes {
+                            Ok(bytes) => {
                                 let (width, height) = if flags.is_odd() {  // binary mode
-                                    let code: Vec<char> = text.chars().collect();
-                                    funge.insert_code(vec![join(&code, "")], x0, y0)?;
-                                    (text.len(), 1)
+                                    funge.insert_bytes(&[&bytes], x0, y0)?;
+                                    (bytes.len(), 1)
                                 } else {
-                                    let text: Vec<&str> = text.lines().collect();
-                                    let height = text.len();
-                                    let width = text.iter().map(|i| i.len()).max().or(Some(0)).unwrap();
-                                    let mut code: Vec<String> = Vec::new();
-                                    for line in text {
-                                        code.push(line.to_string());
-                                    }
-                                    funge.insert_code(code, x0, y0)?;
-                                    (width, height)
+                                    let lines = split_lines(&bytes, funge.line_endings);
+                                    let width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+                                    funge.insert_bytes(&lines, x0, y0)?;
+                                    (width, lines.len())
                                 };
-                                self.stack.push(cast_int(width)?);
-                                self.stack.push(cast_int(height)?);
-                                self.stack.push(cast_int(x0)?);
-                                self.stack.push(cast_int(y0)?);
+                                self.stack.push(cast(width)?);
+                                self.stack.push(cast(height)?);
+                                self.stack.push(cast(x0)?);
+                                self.stack.push(cast(y0)?);
+                            }
+                            Err(error) => {
+                                self.reflect();
+                                funge.warn(format!("could not read file {}: {}, reflected", file, error))?;
                             }
-                            _ => self.reflect()
                         }
 
                     }
                     106 => { // j
-                        let n: isize = cast_int(self.stack.pop())?;
+                        let n: isize = cast(self.stack.pop())?;
                         if n < 0 {
                             self.delta = self.delta.iter().map(|i| -i).collect();
                         }
                         for _ in 0..n.abs() {
-                            self.movep(&funge);
+                            self.movep(funge);
                         }
                         if n < 0 {
                             self.delta = self.delta.iter().map(|i| -i).collect();
                         }
-                        return Ok((funge, vec![self], true))
+                        return Ok((vec![self], true))
                     }
                     107 => { // k
-                        let n: isize = cast_int(self.stack.pop())?;
+                        let n: isize = cast(self.stack.pop())?;
                         if n == 0 { // special case
-                            self.movep(&funge);
-                            return Ok((funge, vec![self], true))
+                            self.movep(funge);
+                            return Ok((vec![self], true))
                         } else {
-                            let k_op = self.next_op(&funge)?;
+                            let k_op = self.next_op(funge)?;
                             let mut ips = vec![self];
                             let mut advance = true;
                             for _ in 0..n {
                                 let mut new_ips = Vec::new();
-                                let n_ips = ips.len();
                                 for ip in ips {
-                                    funge = {
-                                        let (f, ips, adv) = ip.exe(funge, k_op, n_ips)?;
-                                        advance = adv;
-                                        new_ips.extend(ips);
-                                        f
-                                    };
+                                    let (ips, adv) = ip.exe(funge, k_op)?;
+                                    advance = adv;
+                                    new_ips.extend(ips);
                                 }
                                 ips = new_ips;
                             }
-                            return Ok((funge, ips, advance))
+                            return Ok((ips, advance))
                         }
                     }
                     110 => self.stack.clear(), // n
                     111 => { // o
                         let file = self.read_string()?;
                         let flags = self.stack.pop();
-                        let y0 = cast_int(self.stack.pop())?;
-                        let x0 = cast_int(self.stack.pop())?;
-                        let height: isize = cast_int(self.stack.pop())?;
-                        let width: isize = cast_int(self.stack.pop())?;
+                        let y0 = cast(self.stack.pop())?;
+                        let x0 = cast(self.stack.pop())?;
+                        let height: isize = cast(self.stack.pop())?;
+                        let width: isize = cast(self.stack.pop())?;
                         let mut text = Vec::new();
                         if flags.is_odd() { // linear mode
                             for y in y0..y0 + height {
                                 let mut line = String::new();
                                 for x in x0..x0 + width {
-                                    line.push(chr(funge.code[&vec![x, y]])?);
+                                    line.push(to_char(funge.code[&vec![x, y]])?);
                                 }
                                 line = line.lines().map(|l| l.trim_end().to_string() + "\n").collect();
                                 line = line.trim_end().to_string();
@@ -881,27 +1375,49 @@ impl<I: Int> IP<I> {
                             for y in y0..y0 + height {
                                 let mut line = String::new();
                                 for x in x0..x0 + width {
-                                    line.push(chr(funge.code[&vec![x, y]])?);
+                                    line.push(to_char(funge.code[&vec![x, y]])?);
                                 }
                                 text.push(line);
                             }
                         }
-                        let mut text = join(&text, "\n");
-                        text.push_str("\n");
-                        if let Err(_) = fs::write(file, text) {
+                        let mut text = convert::join(&text, "\n");
+                        text.push('\n');
+                        if funge.dry_run {
+                            funge.effects.push(Effect::WriteFile(file));
+                        } else if let Err(error) = fs::write(funge.path(&file), text) {
                             self.reflect();
+                            funge.warn(format!("could not write file {}: {}, reflected", file, error))?;
                         }
                     }
+                    113 if funge.dry_run => {
+                        funge.effects.push(Effect::Quit(cast(self.stack.pop())?));
+                        return Ok((Vec::new(), false))
+                    }
                     113 => {
-                        Err(Error::new(FungeError::Quit(cast_int(self.stack.pop())?)))?;
+                        funge.output.flush()?;
+                        Err(Error::new(FungeError::Quit(cast(self.stack.pop())?)))?;
                     } // q
                     114 => self.reflect(), // r
                     115 => { // s
-                        self.movep(&funge);
+                        self.movep(funge);
                         funge.insert(self.stack.pop(), vec![self.position[0], self.position[1]]);
                     }
+                    116 if funge.limits.max_ips.is_some_and(|max_ips| funge.live_ips >= max_ips) => // t
+                        Err(Error::new(FungeError::TooManyIps(funge.live_ips)))?,
+                    116 if funge.live_ips >= funge.rules.max_ips => { // t
+                        match funge.rules.on_ip_cap {
+                            OnIpCap::Reflect => {
+                                self.reflect();
+                                funge.warn(format!("t at ({}, {}) would exceed {} IPs, reflected", self.position[0],
+                                                   self.position[1], funge.rules.max_ips))?;
+                            }
+                            OnIpCap::Error => Err(Error::new(FungeError::TooManyIps(funge.rules.max_ips)))?
+                        }
+                    }
                     116 => { // t
-                        let mut new = self.split(n_ips);
+                        let mut new = self.split(funge.next_ip_id);
+                        funge.next_ip_id += 1;
+                        funge.live_ips += 1;
                         new.reflect();
                         new_ips.push(new);
                     }
@@ -909,7 +1425,7 @@ impl<I: Int> IP<I> {
                         if self.stack.len_stack() <= 1 {
                             self.reflect();
                         } else {
-                            let n = cast_int(self.stack.pop())?;
+                            let n = cast(self.stack.pop())?;
                             let l = self.stack.len_stack();
                             if n > 0 {
                                 for _ in 0..n {
@@ -934,22 +1450,19 @@ impl<I: Int> IP<I> {
                         }
                     }
                     120 => { // x
-                        let dy = cast_int(self.stack.pop())?;
-                        let dx = cast_int(self.stack.pop())?;
+                        let dy = cast(self.stack.pop())?;
+                        let dx = cast(self.stack.pop())?;
                         self.delta = vec![dx, dy];
                     }
                     121 => { // y
-                        let n: isize = cast_int(self.stack.pop())?;
+                        let n: isize = cast(self.stack.pop())?;
                         funge.shrink_extent();
-                        let counter = self.get_info(&funge)?;
+                        let counter = self.get_info(funge)?;
                         if n > 0 {
-                            let n = n as usize;
                             let l = self.stack.len();
-                            let tmp = if n > l {
-                                I::zero()
-                            } else {
-                                self.stack[self.stack.len_stack() - 1][l - n].to_owned()
-                            };
+                            let tmp = self.stack.stackstack.last()
+                                .and_then(|stack| stack.get(l.checked_sub(n as usize)?))
+                                .unwrap_or(I::zero());
                             for _ in 0..counter {
                                 self.stack.pop();
                             }
@@ -957,141 +1470,392 @@ impl<I: Int> IP<I> {
                         }
                     }
                     122 => {} // z
-                    48..=57 => self.stack.push(op - cast_int(48)?), // 0123456789
-                    97..=102 => self.stack.push(op - cast_int(87)?), // abcdef
-                    _ => self.not_implemented(&funge)?
+                    48..=57 => self.stack.push(op - cast(48)?), // 0123456789
+                    97..=102 => self.stack.push(op - cast(87)?), // abcdef
+                    _ => self.not_implemented(funge, op)?
                 }
             } else {
-                self.not_implemented(&funge)?;
+                self.not_implemented(funge, op)?;
             }
         } else {
-            self.not_implemented(&funge)?;
+            self.not_implemented(funge, op)?;
         }
         // let mut ips = Vec::new();
         // ips.extend(new_ips);
         new_ips.push(self);
-        Ok((funge, new_ips, false))
+        Ok((new_ips, false))
     }
 }
 
 
 #[derive(Clone)]
-struct Rules {
+pub struct Rules {
+    version: u8,
     instruction_set: Vec<u8>,
-    on_error: OnError
+    removed: Vec<u8>,  // taken away with without_instructions, these stay away whatever the version
+    on_error: OnError,
+    schedule: Schedule,
+    wrap: Wrap,
+    wrap_chosen: bool,  // set with with_wrap rather than by the version
+    jump_wraps: bool,  // # at the edge skips the first cell on the other side
+    execute: Execute,
+    max_ips: usize,
+    on_ip_cap: OnIpCap,
+    network: bool
 }
 
 impl Rules {
     fn new() -> Result<Self> {
-        Ok(Self {
-            instruction_set: Self::get_instruction_set("B98")?,
-            on_error: Self::get_on_error("Reflect")?
-        })
+        Self::with_rules("B98")
     }
 
     fn with_rules<T: ToString>(version: T) -> Result<Self> {
+        let version = Self::get_version(version)?;
         Ok(Self {
-            instruction_set: Self::get_instruction_set(version.to_string())?,
-            on_error: Self::get_on_error(version)?
+            version,
+            instruction_set: Self::get_instruction_set(version),
+            removed: Vec::new(),
+            on_error: Self::get_on_error(version),
+            schedule: Schedule::RoundRobin,
+            wrap: Self::get_wrap(version),
+            wrap_chosen: false,
+            jump_wraps: version == 98,
+            execute: Execute::Shell,
+            max_ips: MAX_IPS,
+            on_ip_cap: OnIpCap::Reflect,
+            network: true
         })
     }
 
-    fn get_instruction_set<T: ToString>(version: T) -> Result<Vec<u8>> {
+    // only what the version decides changes: its instructions, but those taken away stay away, what unknown ones do,
+    // the wrap unless it was chosen and # at the edge, everything else stays as it was set
+    fn set_version<T: ToString>(&mut self, version: T) -> Result<()> {
+        let version = Self::get_version(version)?;
+        self.version = version;
+        self.instruction_set = Self::get_instruction_set(version);
+        self.instruction_set.retain(|op| !self.removed.contains(op));
+        self.on_error = Self::get_on_error(version);
+        if !self.wrap_chosen {
+            self.wrap = Self::get_wrap(version);
+        }
+        self.jump_wraps = version == 98;
+        Ok(())
+    }
+
+    fn get_version<T: ToString>(version: T) -> Result<u8> {
         Ok(match &version.to_string().to_uppercase()[..] {
-            "B93" => "!\"#$%&*+,-./0123456789:<>?@\\^_`gpv|~",
-            "B97" => "!\"#$%&\'*+,-./0123456789:<>?@\\^_`gpv|~abcdefg",
-            "B98" => "!\"#$%&\'()*+,-./0123456789:;<=>?@[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~",
+            "B93" => 93,
+            "B97" => 97,
+            "B98" => 98,
             _ => Err(Error::new(FungeError::Version(version.to_string())))?
-        }.chars().map(|i| ord(i).unwrap()).collect())
+        })
     }
 
-    fn get_on_error<T: ToString>(action: T) -> Result<OnError> {
-        match OnError::from_str(&action.to_string()) {
-            Ok(on_error) => Ok(on_error),
-            _ => Ok(match &action.to_string().to_uppercase()[..] {
-                    "B93" => Self::get_on_error("Ignore")?,
-                    "B97" => Self::get_on_error("Ignore")?,
-                    "B98" => Self::get_on_error("Reflect")?,
-                    _ => Err(Error::new(FungeError::Version(action.to_string())))?
-                })
+    // the instructions of a version with their descriptions, for tooling
+    pub fn docs<T: ToString>(version: T) -> Result<Vec<&'static ops::OpInfo>> {
+        Ok(Self::with_rules(version)?.instruction_docs())
+    }
+
+    // only the enabled instructions
+    pub fn instruction_docs(&self) -> Vec<&'static ops::OpInfo> {
+        ops::OPS.iter().filter(|info| self.is_enabled(info.op)).collect()
+    }
+
+    fn get_instruction_set(version: u8) -> Vec<u8> {
+        ops::instruction_set(version).into_iter().map(|info| info.op as u8).collect()
+    }
+
+    fn get_wrap(version: u8) -> Wrap {
+        match version {
+            93 => Wrap::Torus,
+            _ => Wrap::Lahey
         }
     }
-}
 
+    fn get_on_error(version: u8) -> OnError {
+        match version {
+            98 => OnError::Reflect,
+            _ => OnError::Ignore
+        }
+    }
 
-#[derive(Clone, EnumString)]
-enum OnError {
-    Ignore,
-    Reflect,
-    #[allow(dead_code)]
-    Quit,
-}
+    pub fn version(&self) -> String {
+        format!("B{}", self.version)
+    }
 
+    pub fn instructions(&self) -> String {
+        self.instruction_set.iter().map(|op| char::from(*op)).collect()
+    }
 
-fn read_file(file: &String) -> Result<String> {
-    Ok(join(&fs::read(file)?.iter().map(|i| chr(*i)).collect::<Result<Vec<char>>>()?, ""))
-}
+    pub fn is_enabled(&self, op: char) -> bool {
+        u8::try_from(op).is_ok_and(|op| self.instruction_set.contains(&op))
+    }
 
+    pub fn on_error(&self) -> OnError {
+        self.on_error
+    }
 
-#[derive(Clone)]
-pub struct Rect {
-    pub left: isize,
-    pub right: isize,
-    pub top: isize,
-    pub bottom: isize
-}
+    pub fn schedule(&self) -> Schedule {
+        self.schedule
+    }
 
-impl Rect {
-    pub fn new(left: isize, right: isize, top: isize, bottom: isize) -> Self {
-        Self { left, right, top, bottom }
+    pub fn wrap(&self) -> Wrap {
+        self.wrap
     }
 
-    pub fn width(&self) -> isize {
-        self.right - self.left
+    pub fn jump_wraps(&self) -> bool {
+        self.jump_wraps
     }
 
-    pub fn height(&self) -> isize {
-        self.bottom - self.top
+    pub fn execute(&self) -> Execute {
+        self.execute
     }
 
-    pub fn contains(&self, pos: &Vec<isize>) -> bool {
-        (self.left <= pos[0]) & (pos[0] < self.right) & (self.top <= pos[1]) & (pos[1] < self.bottom)
+    pub fn max_ips(&self) -> usize {
+        self.max_ips
     }
-}
 
+    pub fn on_ip_cap(&self) -> OnIpCap {
+        self.on_ip_cap
+    }
 
-#[derive(Clone)]
-pub struct FungeSpace<I: Int> {
-    pub orig_code: Vec<Vec<I>>,
-    pub orig_rect: Rect,
-    pub new_code: HashMap<Vec<isize>, I>,
-    space: I
-}
+    pub fn can_split(&self) -> bool {
+        self.is_enabled('t')
+    }
 
-impl<I: Int> FungeSpace<I> {
+    pub fn can_read_files(&self) -> bool {
+        self.is_enabled('i')
+    }
+
+    pub fn can_write_files(&self) -> bool {
+        self.is_enabled('o')
+    }
+
+    pub fn can_execute(&self) -> bool {
+        self.is_enabled('=')
+    }
+
+    pub fn can_network(&self) -> bool {
+        self.network
+    }
+}
+
+impl Display for Rules {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        writeln!(f, "version: {}", self.version())?;
+        writeln!(f, "instructions: {}", self.instructions())?;
+        writeln!(f, "on error: {:?}", self.on_error)?;
+        writeln!(f, "wrap: {:?}", self.wrap)?;
+        writeln!(f, "# at the edge skips the wrapped-to cell: {}", yes_no(self.jump_wraps))?;
+        writeln!(f, "schedule: {:?}", self.schedule)?;
+        writeln!(f, "max IPs: {}, then t does: {:?}", self.max_ips, self.on_ip_cap)?;
+        write!(f, "split (t): {}, read files (i): {}, write files (o): {}, execute (=): {}", yes_no(self.can_split()),
+               yes_no(self.can_read_files()), yes_no(self.can_write_files()), yes_no(self.can_execute()))?;
+        if self.can_execute() {
+            write!(f, " in {:?}", self.execute)?;
+        }
+        write!(f, ", network (SOCK, SCKE): {}", yes_no(self.can_network()))
+    }
+}
+
+
+// what . writes after the number, the spec requires a space
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum NumberFormat {
+    Spec,
+    Bare,
+    Newline
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum Validation {
+    Warn,
+    Strict
+}
+
+
+// Lahey-space wraps back along the delta to the other side of the code (B98), a torus wraps modulo a fixed
+// size of at least 80x25 (B93), and unbounded space never wraps
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum Wrap {
+    Lahey,
+    Torus,
+    Unbounded
+}
+
+
+// what t does when there are already as many IPs as allowed
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum OnIpCap {
+    Reflect,
+    Error
+}
+
+
+// what = does with its command: run it in a shell, or run the funge file it names in this interpreter
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum Execute {
+    Shell,
+    Funge
+}
+
+
+// the order in which IPs execute within one step, the IP list itself keeps its order
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum Schedule {
+    RoundRobin,
+    Reverse,
+    Random
+}
+
+
+// normalize: \r\n and a lone \r end a line too, preserve: only \n ends a line and \r is a cell
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum LineEndings {
+    Normalize,
+    Preserve
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum OnUnderflow {
+    Count,
+    Warn,
+    Strict
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum OnError {
+    Ignore,
+    Reflect,
+    Quit,
+}
+
+
+// how a run that did not halt exits: with 1 and an error like any other error, or with a code of its own
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(ascii_case_insensitive)]
+#[non_exhaustive]
+pub enum ExitStatus {
+    Simple,
+    Distinct
+}
+
+
+fn read_file(file: &String) -> Result<String> {
+    Ok(fs::read(file)?.into_iter().map(char::from).collect())
+}
+
+
+// like str::lines, but on bytes, a final line ending does not start another line
+fn split_lines(bytes: &[u8], line_endings: LineEndings) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut n = 0;
+    while n < bytes.len() {
+        match (bytes[n], line_endings) {
+            (b'\n', _) => {
+                lines.push(&bytes[start..n]);
+                start = n + 1;
+            }
+            (b'\r', LineEndings::Normalize) => {
+                lines.push(&bytes[start..n]);
+                if bytes.get(n + 1) == Some(&b'\n') {
+                    n += 1;
+                }
+                start = n + 1;
+            }
+            _ => {}
+        }
+        n += 1;
+    }
+    if start < bytes.len() {
+        lines.push(&bytes[start..]);
+    }
+    lines
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub left: isize,
+    pub right: isize,
+    pub top: isize,
+    pub bottom: isize
+}
+
+impl Rect {
+    pub fn new(left: isize, right: isize, top: isize, bottom: isize) -> Self {
+        Self { left, right, top, bottom }
+    }
+
+    pub fn width(&self) -> isize {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> isize {
+        self.bottom - self.top
+    }
+
+    pub fn contains(&self, pos: &[isize]) -> bool {
+        (self.left <= pos[0]) & (pos[0] < self.right) & (self.top <= pos[1]) & (pos[1] < self.bottom)
+    }
+}
+
+
+#[derive(Clone)]
+pub struct FungeSpace<I: Int> {
+    pub(crate) orig_code: Vec<Vec<I>>,
+    pub(crate) orig_rect: Rect,
+    pub(crate) new_code: HashMap<Vec<isize>, I>,
+    space: I
+}
+
+impl<I: Int> FungeSpace<I> {
     fn new(code: Vec<String>) -> Result<Self> {
-        let code = code.into_iter().map(|line| line.replace(chr(12).unwrap(), "")).collect::<Vec<String>>();
+        let code = code.into_iter().map(|line| line.replace(to_char(12).unwrap(), "")).collect::<Vec<String>>();
         let mut new = Self {
             orig_code: Vec::new(),
             orig_rect: Rect::new(
-                0,code.iter().map(|line| line.len()).max().or(Some(0)).unwrap() as isize,
+                0,code.iter().map(|line| line.len()).max().unwrap_or(0) as isize,
                 0,code.len() as isize
             ),
             new_code: HashMap::new(),
-            space: cast_int(32)?
+            space: cast(32)?
         };
         let width = new.orig_rect.width() as usize;
         for line in code {
-            let mut i = line.chars().map(|c| ord(c)).collect::<Result<Vec<I>>>()?;
-            i.extend(vec![new.space; &width - i.len()]);
+            let mut i = line.chars().map(|c| from_char(c)).collect::<Result<Vec<I>>>()?;
+            i.extend(vec![new.space; width - i.len()]);
             new.orig_code.push(i);
         }
         Ok(new)
     }
 
     pub fn insert(&mut self, index: Vec<isize>, op: I) {
-        if self.orig_rect.contains(&index) {
-            self.orig_code[index[1] as usize][index[0] as usize] = op;
+        if let Some(cell) = self.orig_cell_mut(&index) {
+            *cell = op;
         } else if op == self.space {
             self.new_code.remove(&index);
         } else {
@@ -1099,49 +1863,368 @@ impl<I: Int> FungeSpace<I> {
         }
     }
 
-    pub fn get_string(&self, rect: Rect) -> Vec<String> {
-        let mut string = Vec::new();
-        for y in rect.top..rect.bottom {
-            let mut line = Vec::new();
+    // a cell of the original code, None outside it or where a row is shorter than the rect
+    fn orig_cell(&self, index: &[isize]) -> Option<&I> {
+        match index {
+            [x, y, ..] if self.orig_rect.contains(index) => self.orig_code.get(*y as usize)?.get(*x as usize),
+            _ => None
+        }
+    }
+
+    fn orig_cell_mut(&mut self, index: &[isize]) -> Option<&mut I> {
+        match index {
+            [x, y, ..] if self.orig_rect.contains(index) => self.orig_code.get_mut(*y as usize)?.get_mut(*x as usize),
+            _ => None
+        }
+    }
+
+    // any cell, None only for a position with fewer than two coordinates
+    pub fn get(&self, pos: &[isize]) -> Option<I> {
+        (pos.len() >= 2).then(|| *self.orig_cell(pos).or_else(|| self.new_code.get(pos)).unwrap_or(&self.space))
+    }
+
+    // the cells of any rectangle row by row, the original code is copied in bulk
+    pub fn region(&self, rect: &Rect) -> Vec<Vec<I>> {
+        (rect.top..rect.bottom).map(|y| {
+            let (mut left, mut right) = (rect.right, rect.right);
             if (self.orig_rect.top <= y) & (y < self.orig_rect.bottom) {
-                for x in rect.left..0 {
-                    line.push(*self.new_code.get(&vec![x, y]).unwrap_or(&self.space));
-                }
-                let left = max(self.orig_rect.left, rect.left) as usize;
-                let right = min(self.orig_rect.right, rect.right) as usize;
-                let orig_line = self.orig_code[y as usize][left..right].to_vec();
-                let width = orig_line.len();
-                line.extend(orig_line);
-                if rect.right > self.orig_rect.right {
-                    line.extend(vec![self.space; (self.orig_rect.right as usize) - width]);
-                    for x in self.orig_rect.right..rect.right {
-                        line.push(*self.new_code.get(&vec![x, y]).unwrap_or(&self.space));
-                    }
+                left = min(max(self.orig_rect.left, rect.left), rect.right);
+                right = max(left, min(self.orig_rect.right, rect.right));
+            }
+            let mut line = Vec::with_capacity(max(rect.width(), 0) as usize);
+            for x in rect.left..left {
+                line.push(*self.new_code.get(&vec![x, y]).unwrap_or(&self.space));
+            }
+            match self.orig_code.get(y as usize).and_then(|row| row.get(left as usize..right as usize)) {
+                Some(cells) => line.extend_from_slice(cells),
+                None => line.extend((left..right).map(|x| self[&vec![x, y]]))
+            }
+            for x in right..rect.right {
+                line.push(*self.new_code.get(&vec![x, y]).unwrap_or(&self.space));
+            }
+            line
+        }).collect()
+    }
+
+    pub fn get_string(&self, rect: Rect) -> Vec<String> {
+        self.region(&rect).into_iter().map(cells_to_string).collect()
+    }
+
+    pub fn get_string_with(&self, rect: Rect, printable: &convert::Printable) -> Vec<String> {
+        self.region(&rect).into_iter().map(|line| printable.show_cells(line)).collect()
+    }
+}
+
+impl<I: Int> FungeSpace<I> {
+    // all cells that are not a space, by row
+    pub fn cells(&self) -> Vec<(Vec<isize>, I)> {
+        let mut cells: Vec<(Vec<isize>, I)> = self.new_code.iter().map(|(pos, op)| (pos.to_owned(), *op)).collect();
+        for (y, row) in self.orig_code.iter().enumerate() {
+            for (x, op) in row.iter().enumerate() {
+                if *op != self.space {
+                    cells.push((vec![x as isize, y as isize], *op));
                 }
-            } else {
+            }
+        }
+        cells.sort_by_key(|(pos, _)| (pos[1], pos[0]));
+        cells
+    }
+
+    // cells that differ from the original: position, original value, current value
+    pub fn diff(&self, original: &Self) -> Vec<(Vec<isize>, I, I)> {
+        let mut positions: Vec<Vec<isize>> = self.new_code.keys().chain(original.new_code.keys()).cloned().collect();
+        for rect in [&self.orig_rect, &original.orig_rect] {
+            for y in rect.top..rect.bottom {
                 for x in rect.left..rect.right {
-                    line.push(*self.new_code.get(&vec![x, y]).unwrap_or(&self.space));
+                    positions.push(vec![x, y]);
+                }
+            }
+        }
+        positions.sort_by_key(|pos| (pos[1], pos[0]));
+        positions.dedup();
+        positions.into_iter().filter_map(|pos| {
+            let (old, new) = (original[&pos], self[&pos]);
+            (old != new).then_some((pos, old, new))
+        }).collect()
+    }
+
+    // changed lines in a unified diff like format
+    pub fn diff_listing(&self, original: &Self) -> String {
+        let diff = self.diff(original);
+        if diff.is_empty() {
+            return String::new()
+        }
+        let left = diff.iter().map(|(pos, _, _)| pos[0]).min().unwrap_or(0).min(0);
+        let right = diff.iter().map(|(pos, _, _)| pos[0] + 1).max().unwrap_or(0)
+            .max(self.orig_rect.right).max(original.orig_rect.right);
+        let mut rows = diff.iter().map(|(pos, _, _)| pos[1]).collect::<Vec<isize>>();
+        rows.dedup();
+        let mut listing = String::from("--- original\n+++ current\n");
+        for y in rows {
+            let rect = Rect::new(left, right, y, y + 1);
+            listing.push_str(&format!("@@ {},{} @@\n-{}\n+{}\n", left, y, original.get_string(rect.clone())[0].trim_end(),
+                                      self.get_string(rect)[0].trim_end()));
+        }
+        listing
+    }
+
+    // the cells of the original code and those written outside it, kept up to date by insert
+    pub fn len(&self) -> usize {
+        (self.orig_rect.width() * self.orig_rect.height()) as usize + self.new_code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self, extent: Rect) -> SpaceStats {
+        // every row of the original code is padded to the width of the rect
+        let dense_cells = (self.orig_rect.width() * self.orig_rect.height()) as usize;
+        let sparse_cells = self.new_code.len();
+        let key = size_of::<Vec<isize>>() + 2 * size_of::<isize>();
+        SpaceStats {
+            dense_cells,
+            sparse_cells,
+            extent,
+            bytes: dense_cells * size_of::<I>() + self.new_code.capacity() * (key + size_of::<I>() + 1),
+            ips: BTreeMap::new(),
+            stopped: IpStats::default()
+        }
+    }
+}
+
+
+// sandboxing limits, None is unlimited, exceeding a limit stops the funge with an error
+#[derive(Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct Limits {
+    pub max_steps: Option<usize>,
+    pub max_time: Option<Duration>,
+    pub max_cells: Option<usize>,
+    pub max_output: Option<usize>,
+    pub max_ips: Option<usize>,
+    pub max_stack: Option<usize>
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // comma separated key=value: steps, time (seconds), cells, output, ips, stack
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut limits = Self::new();
+        for item in text.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let error = || Error::new(FungeError::Limits(item.to_string()));
+            let (key, value) = item.split_once('=').ok_or_else(error)?;
+            let value = value.trim();
+            match key.trim() {
+                "time" => limits.max_time = Some(value.parse().ok().and_then(|s| Duration::try_from_secs_f64(s).ok())
+                    .ok_or_else(error)?),
+                key => {
+                    let value = Some(value.parse().map_err(|_| error())?);
+                    match key {
+                        "steps" => limits.max_steps = value,
+                        "cells" => limits.max_cells = value,
+                        "output" => limits.max_output = value,
+                        "ips" => limits.max_ips = value,
+                        "stack" => limits.max_stack = value,
+                        _ => Err(error())?
+                    }
                 }
             }
-            string.push(vec_to_string(line));
         }
-        string
+        Ok(limits)
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum StopReason {
+    Halted,
+    StepLimit,
+    Timeout,
+    Cancelled,
+    // one of the limits on cells, output, stack depth or IPs
+    Limit(String),
+    Error(String)
+}
+
+impl StopReason {
+    // for ExitStatus::Distinct: 71 when a limit was reached and 70 when the interpreter failed
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            StopReason::Halted => 0,
+            StopReason::StepLimit | StopReason::Timeout | StopReason::Limit(_) => 71,
+            StopReason::Cancelled | StopReason::Error(_) => 70
+        }
+    }
+}
+
+// any error, also one from before or outside a run, the reason is Error when it is not one of the others
+impl From<&Error> for StopReason {
+    fn from(error: &Error) -> Self {
+        match error.downcast_ref::<FungeError>() {
+            Some(FungeError::Quit(_)) => StopReason::Halted,
+            Some(FungeError::StepLimit(_)) => StopReason::StepLimit,
+            Some(FungeError::Timeout(_)) => StopReason::Timeout,
+            Some(FungeError::Cancelled) => StopReason::Cancelled,
+            Some(FungeError::CellLimit(_) | FungeError::OutputLimit(_) | FungeError::StackLimit(_) | FungeError::TooManyIps(_)) =>
+                StopReason::Limit(error.to_string()),
+            Some(FungeError::Stopped(reason, _)) => reason.clone(),
+            _ => StopReason::Error(error.to_string())
+        }
+    }
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            StopReason::Halted => write!(f, "halted"),
+            StopReason::StepLimit => write!(f, "step limit reached"),
+            StopReason::Timeout => write!(f, "time limit reached"),
+            StopReason::Cancelled => write!(f, "cancelled"),
+            StopReason::Limit(error) => write!(f, "{}", error),
+            StopReason::Error(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+
+// why and after how many steps a run ended, the exit code is only there when the funge halted
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct RunOutcome {
+    pub exit_code: Option<i32>,
+    pub steps: isize,
+    pub reason: StopReason,
+    pub unconsumed: Vec<Argument>
+}
+
+impl RunOutcome {
+    pub fn into_result(self) -> Result<i32> {
+        match (self.exit_code, self.reason) {
+            (Some(exit_code), StopReason::Halted) => Ok(exit_code),
+            (_, reason @ (StopReason::Error(_) | StopReason::Limit(_))) => Err(Error::new(FungeError::Stopped(reason.clone(), reason.to_string()))),
+            (_, reason) => Err(Error::new(FungeError::Stopped(reason.clone(), format!("{} after {} steps", reason, self.steps))))
+        }
+    }
+}
+
+
+// result of running a slice of a program: either it can be resumed, or it quit with a return code
+pub enum RunStatus<I: Int> {
+    Paused(Box<Funge<I>>),
+    Finished(i32)
+}
+
+
+// how far a run is, for a reporter called every so often while running
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Progress {
+    pub steps: isize,
+    pub elapsed: Duration,
+    pub max_steps: Option<usize>,
+    pub max_time: Option<Duration>
+}
+
+impl Progress {
+    pub fn rate(&self) -> f64 {
+        self.steps as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    // until the first limit is reached, if there is any
+    pub fn eta(&self) -> Option<Duration> {
+        let by_steps = self.max_steps
+            .and_then(|max_steps| Duration::try_from_secs_f64((max_steps as f64 - self.steps as f64).max(0.0) / self.rate()).ok());
+        let by_time = self.max_time.map(|max_time| max_time.saturating_sub(self.elapsed));
+        by_steps.into_iter().chain(by_time).min()
+    }
+}
+
+impl Display for Progress {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.max_steps {
+            Some(max_steps) => write!(f, "{}/{} steps", self.steps, max_steps)?,
+            None => write!(f, "{} steps", self.steps)?
+        }
+        write!(f, ", {:.0} steps/s", self.rate())?;
+        match self.eta() {
+            Some(eta) => write!(f, ", {:.0} s left", eta.as_secs_f64()),
+            None => Ok(())
+        }
+    }
+}
+
+
+// instructions executed by an IP and the deepest its stack has been, kept after the IP stops
+#[derive(Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct IpStats {
+    pub instructions: usize,
+    pub max_stack: usize
+}
+
+impl IpStats {
+    pub fn to_json(&self) -> String {
+        format!("{{\"instructions\":{},\"max_stack\":{}}}", self.instructions, self.max_stack)
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct SpaceStats {
+    pub dense_cells: usize,
+    pub sparse_cells: usize,
+    pub extent: Rect,
+    pub bytes: usize,
+    pub ips: BTreeMap<usize, IpStats>,
+    // IPs that stopped long ago, added up
+    pub stopped: IpStats
+}
+
+impl SpaceStats {
+    pub fn cells(&self) -> usize {
+        self.dense_cells + self.sparse_cells
+    }
+
+    pub fn to_json(&self) -> String {
+        let ips = self.ips.iter().map(|(id, stats)| format!("\"{}\":{}", id, stats.to_json())).collect::<Vec<String>>();
+        format!("{{\"dense_cells\":{},\"sparse_cells\":{},\"extent\":[{},{},{},{}],\"bytes\":{},\"ips\":{{{}}},\"stopped\":{}}}",
+                self.dense_cells, self.sparse_cells, self.extent.left, self.extent.top, self.extent.right,
+                self.extent.bottom, self.bytes, convert::join(&ips, ","), self.stopped.to_json())
+    }
+}
+
+impl Display for SpaceStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "cells: {} dense, {} sparse, extent: {}x{}, {} kB", self.dense_cells, self.sparse_cells,
+               self.extent.width(), self.extent.height(), self.bytes.div_ceil(1024))
     }
 }
 
+
 impl<I: Int> Index<&Vec<isize>> for FungeSpace<I> {
     type Output = I;
 
     fn index(&self, index: &Vec<isize>) -> &Self::Output {
-        if self.orig_rect.contains(index) {
-            &self.orig_code[index[1] as usize][index[0] as usize]
-        } else {
-            self.new_code.get(index).unwrap_or(&self.space)
-        }
+        self.orig_cell(index).or_else(|| self.new_code.get(index)).unwrap_or(&self.space)
     }
 }
 
 
+// the values pushed by a string or a run of digits read along a row, and the x of its last cell
+#[derive(Clone, Debug)]
+struct Literal<I: Int> {
+    values: Vec<I>,
+    end: isize,
+    steps: usize
+}
+
+
 #[derive(Clone)]
 pub struct Funge<I: Int> {
     pub extent: Rect,
@@ -1151,15 +2234,81 @@ pub struct Funge<I: Int> {
     pub ips: Vec<IP<I>>,
     pub input: IO,
     pub output: IO,
+    pub diagnostics: Option<IO>,
+    env: Vec<(String, String)>,
+    argv: Vec<String>,
+    rng: StdRng,
+    clock: Option<NaiveDateTime>,
+    handprint: i128,
+    dry_run: bool,
+    effects: Vec<Effect>,
+    hooks: Vec<Hook<I>>,
+    trace: Option<Tracer>,
+    ip_names: HashMap<usize, IpName>,
+    next_ip_id: usize,
+    on_underflow: OnUnderflow,
+    line_endings: LineEndings,
+    underflows: HashMap<Vec<isize>, usize>,
+    ip_stats: BTreeMap<usize, IpStats>,
+    stopped_ip_stats: IpStats,
+    live_ips: usize,
+    nesting: usize,
+    // the steps of the funges = ran during this step, they count for this funge too
+    nested_steps: isize,
+    limits: Limits,
+    cancel: Option<Arc<AtomicBool>>,
+    started: Option<Instant>,
+    output_len: usize,
+    last_output: String,
+    last_writes: Vec<(Vec<isize>, I)>,
+    last_command: Option<String>,
+    arguments: Vec<Argument>,
+    finalizers: Vec<Finalizer>,
+    // the reporter with when the run started and when it was last called
+    reporter: Option<(Reporter, Instant, Instant)>,
+    // REFC: vectors referenced by their index, shared by all IPs
+    references: Vec<Vec<I>>,
+    sockets: Sockets,
+    files: Files,
+    // i, o, =, FILE and DIRF resolve relative paths against this, DIRF C changes it instead of the process
+    working_dir: PathBuf,
+    timer: Timer<I>,
+    literals: Option<HashMap<(isize, isize, isize), Literal<I>>>
 }
 
 impl<I: Int> Funge<I> {
-    pub fn new<T: ToString>(code: T) -> Result<Self> {
-        let mut code: Vec<String> = code.to_string().lines().map(|i| String::from(i)).collect();
+    // strips a BOM, also when the file was read byte for byte, and the shebang line
+    fn source_lines<T: ToString>(code: T, line_endings: LineEndings) -> Result<Vec<String>> {
+        let code = code.to_string();
+        let code = code.strip_prefix('\u{feff}').or(code.strip_prefix("\u{ef}\u{bb}\u{bf}")).unwrap_or(&code);
+        let code = match line_endings {
+            LineEndings::Normalize => code.replace("\r\n", "\n").replace('\r', "\n"),
+            LineEndings::Preserve => code.to_string()
+        };
+        let mut code: Vec<String> = match code.strip_suffix('\n').unwrap_or(&code) {
+            "" => Vec::new(),
+            code => code.split('\n').map(String::from).collect()
+        };
         let exe = env::current_exe()?.file_name().ok_or(Error::msg("No exe name"))?.to_str().unwrap().to_string();
-        if code[0].starts_with(&*format!(r"#!/usr/bin/env {}", exe)) | code[0].starts_with(&*format!(r"#!/usr/bin/env -S {}", exe)) {
+        if code.first().is_some_and(|line| line.starts_with(&*format!(r"#!/usr/bin/env {}", exe))
+            | line.starts_with(&*format!(r"#!/usr/bin/env -S {}", exe))) {
             code.remove(0);
         }
+        Ok(code)
+    }
+
+    pub fn builder() -> FungeBuilder<I> {
+        FungeBuilder::new()
+    }
+
+    pub fn new<T: ToString>(code: T) -> Result<Self> {
+        Self::new_with_line_endings(code, LineEndings::Normalize)
+    }
+
+    // the line endings are also used by i
+    pub fn new_with_line_endings<T: ToString>(code: T, line_endings: LineEndings) -> Result<Self> {
+        let code = Self::source_lines(code, line_endings)?;
+        let ip_names = IpName::from_pragmas(&code);
         let funge_space = FungeSpace::new(code)?;
         let mut new = Self {
             extent: funge_space.orig_rect.clone(),
@@ -1168,51 +2317,445 @@ impl<I: Int> Funge<I> {
             steps: 0,
             ips: Vec::new(),
             input: IO::new(),
-            output: IO::new()
+            output: IO::new(),
+            diagnostics: None,
+            env: Vec::new(),
+            argv: Vec::new(),
+            rng: StdRng::from_entropy(),
+            clock: None,
+            handprint: handprint("wprf"),
+            dry_run: false,
+            effects: Vec::new(),
+            hooks: Vec::new(),
+            trace: None,
+            ip_names,
+            next_ip_id: 1,
+            on_underflow: OnUnderflow::Count,
+            line_endings,
+            underflows: HashMap::new(),
+            ip_stats: BTreeMap::new(),
+            stopped_ip_stats: IpStats::default(),
+            live_ips: 0,
+            nesting: 0,
+            nested_steps: 0,
+            limits: Limits::new(),
+            cancel: None,
+            started: None,
+            output_len: 0,
+            last_output: String::new(),
+            last_writes: Vec::new(),
+            last_command: None,
+            arguments: Vec::new(),
+            finalizers: Vec::new(),
+            reporter: None,
+            references: Vec::new(),
+            sockets: Sockets::default(),
+            files: Files::default(),
+            working_dir: PathBuf::from("."),
+            timer: |_| SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(),
+            literals: None
         };
         new.ips.push(IP::new(&new)?);
         Ok(new)
     }
 
     pub fn from_file(file: &String) -> Result<Self> {
-        Ok(Self::new(read_file(file)?)?)
+        Self::new(read_file(file)?)
+    }
+
+    // y reports the file as the program, with no arguments until with_argv
+    pub fn from_file_with_line_endings(file: &String, line_endings: LineEndings) -> Result<Self> {
+        Ok(Self::new_with_line_endings(read_file(file)?, line_endings)?.with_argv(vec![file.to_owned()]))
     }
 
     pub fn with_version<T: ToString>(mut self, version: T) -> Result<Self> {
-        self.rules = Rules::with_rules(version)?;
+        self.rules.set_version(version)?;
         Ok(self)
     }
 
-    pub fn with_arguments(mut self, args: Vec<String>) -> Self {
-        self.input = IO::new().with_store(args);
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    // the IP limit stops the funge like the other limits, the IP cap of the rules (with_max_ips) is what t reflects on
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
         self
     }
 
-    pub fn with_input(mut self, input: IO) -> Self {
-        self.input = input;
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    // the funge stops before its next step once the flag is set, from any thread
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
         self
     }
 
-    pub fn with_output(mut self, output: IO) -> Self {
-        self.output = output;
+    // find the strings and digit runs along the rows of the source once, so they can be pushed in one step
+    pub fn with_literal_cache(mut self) -> Self {
+        self.literals = Some(self.scan_literals());
         self
     }
 
-    fn shrink_extent(&mut self) {
-        let space = cast_int(32).expect("space");
-        'left: for x in self.extent.left..self.extent.right {
-            for y in self.extent.top..self.extent.bottom {
-                if self.code[&vec![x, y]] != space {
-                    self.extent.left = x;
-                    break 'left
+    fn scan_literals(&self) -> HashMap<(isize, isize, isize), Literal<I>> {
+        let mut literals = HashMap::new();
+        let (quote, space) = (I::from(34).expect("a cell holds 34"), self.code.space);
+        let digit = |op: I| match op.to_u8() {
+            Some(n @ 48..=57) if self.rules.instruction_set.contains(&n) => I::from(n - 48),
+            Some(n @ 97..=102) if self.rules.instruction_set.contains(&n) => I::from(n - 87),
+            _ => None
+        };
+        for (y, row) in self.code.orig_code.iter().enumerate() {
+            for dx in [1, -1] {
+                let mut cells: Vec<(isize, I)> = row.iter().enumerate().map(|(x, op)| (x as isize, *op)).collect();
+                if dx == -1 {
+                    cells.reverse();
+                }
+                for (start, (x, op)) in cells.iter().enumerate() {
+                    let mut values = Vec::new();
+                    if *op == quote {
+                        for (end, op) in &cells[start + 1..] {
+                            if *op == quote {
+                                let steps = values.len() + 2;
+                                literals.insert((*x, y as isize, dx), Literal { values, end: *end, steps });
+                                break
+                            } else if *op != space || values.last() != Some(&space) {
+                                values.push(*op);  // string mode reads a row of spaces as one space
+                            }
+                        }
+                    } else {
+                        let mut end = *x;
+                        for (x, op) in &cells[start..] {
+                            match digit(*op) {
+                                Some(value) => {
+                                    values.push(value);
+                                    end = *x;
+                                }
+                                None => break
+                            }
+                        }
+                        if values.len() > 1 {
+                            let steps = values.len();
+                            literals.insert((*x, y as isize, dx), Literal { values, end, steps });
+                        }
+                    }
                 }
             }
         }
-        'right: for x in (self.extent.left..self.extent.right).rev() {
-            for y in self.extent.top..self.extent.bottom {
-                if self.code[&vec![x, y]] != space {
-                    self.extent.right = x + 1;
-                    break 'right
+        literals
+    }
+
+    // forget the literals running through a cell that changes
+    fn invalidate_literals(&mut self, position: &[isize]) {
+        if let Some(literals) = self.literals.as_mut() {
+            literals.retain(|(x, y, _), literal| *y != position[1] ||
+                position[0] < min(*x, literal.end) || max(*x, literal.end) < position[0]);
+        }
+    }
+
+    // a single IP without hooks or tracing pushes a cached literal at once, nothing can observe the skipped steps
+    fn step_literal(&mut self) -> Result<bool> {
+        let literals = match &self.literals {
+            Some(literals) if self.ips.len() == 1 && self.hooks.is_empty() && self.trace.is_none() && !self.dry_run
+                => literals,
+            _ => return Ok(false)
+        };
+        let ip = &mut self.ips[0];
+        if ip.string || ip.delta[1] != 0 || !ip.fingerprint_ops.is_empty() || ip.stack.invert || ip.stack.queue {
+            return Ok(false)
+        }
+        let Some(literal) = literals.get(&(ip.position[0], ip.position[1], ip.delta[0])) else {
+            return Ok(false)
+        };
+        if self.limits.max_steps.is_some_and(|max_steps| self.steps as usize + literal.steps > max_steps) {
+            return Ok(false)
+        }
+        ip.stack.extend(literal.values.to_owned());
+        ip.position[0] = literal.end;
+        let steps = literal.steps;
+        let ip = self.ips.remove(0);
+        let stats = self.ip_stats.entry(ip.id).or_default();
+        stats.instructions += steps;
+        stats.max_stack = max(stats.max_stack, ip.stack_depth());
+        if let Some(max_stack) = self.limits.max_stack.filter(|max_stack| ip.stack_depth() > *max_stack) {
+            Err(Error::new(FungeError::StackLimit(max_stack)))?
+        }
+        self.ips.push(ip.advance(self, false)?);
+        self.steps += steps as isize;
+        Ok(true)
+    }
+
+    fn check_limits(&mut self) -> Result<()> {
+        self.report();
+        if self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+            Err(Error::new(FungeError::Cancelled))?
+        }
+        if let Some(max_steps) = self.limits.max_steps {
+            if self.steps >= 0 && self.steps as usize >= max_steps {
+                Err(Error::new(FungeError::StepLimit(max_steps)))?
+            }
+        }
+        if let Some(max_time) = self.limits.max_time {
+            if self.started.get_or_insert_with(Instant::now).elapsed() >= max_time {
+                Err(Error::new(FungeError::Timeout(max_time)))?
+            }
+        }
+        if let Some(max_cells) = self.limits.max_cells {
+            if self.code.len() > max_cells {
+                Err(Error::new(FungeError::CellLimit(max_cells)))?
+            }
+        }
+        Ok(())
+    }
+
+    pub fn with_max_ips(mut self, max_ips: usize, on_ip_cap: OnIpCap) -> Self {
+        self.rules.max_ips = max_ips;
+        self.rules.on_ip_cap = on_ip_cap;
+        self
+    }
+
+    pub fn with_execute(mut self, execute: Execute) -> Self {
+        self.rules.execute = execute;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.rules.wrap = wrap;
+        self.rules.wrap_chosen = true;
+        self
+    }
+
+    fn torus_size(&self) -> (isize, isize) {
+        (max(80, self.code.orig_rect.right), max(25, self.code.orig_rect.bottom))
+    }
+
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.rules.schedule = schedule;
+        self
+    }
+
+    pub fn with_arguments(mut self, args: Vec<String>) -> Self {
+        self.input = IO::new().with_store(args);
+        self
+    }
+
+    // read before any other input, an argument of the wrong kind for & or ~ stops the funge with an error
+    pub fn with_typed_arguments(mut self, arguments: Vec<Argument>) -> Self {
+        self.arguments = arguments;
+        self
+    }
+
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn with_argv(mut self, argv: Vec<String>) -> Self {
+        self.argv = argv;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn with_clock(mut self, time: NaiveDateTime) -> Self {
+        self.clock = Some(time);
+        self
+    }
+
+    pub fn with_timer(mut self, timer: Timer<I>) -> Self {
+        self.timer = timer;
+        self
+    }
+
+    pub fn without_instructions(mut self, ops: &str) -> Self {
+        self.rules.instruction_set.retain(|op| !ops.bytes().any(|i| i == *op));
+        self.rules.removed.extend(ops.bytes());
+        self
+    }
+
+    pub fn with_handprint(mut self, handprint: i128) -> Self {
+        self.handprint = handprint;
+        self
+    }
+
+    // fingerprints that use the network do not load
+    pub fn without_network(mut self) -> Self {
+        self.rules.network = false;
+        self
+    }
+
+    pub fn deterministic(self) -> Self {
+        let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).and_then(|date| date.and_hms_opt(0, 0, 0))
+            .expect("the epoch is a valid date");
+        self.with_seed(0)
+            .with_clock(epoch)
+            .with_timer(|funge| Duration::from_micros(funge.steps.max(0) as u64))
+            .with_env(Vec::new())
+            .without_instructions("=")
+            .without_network()
+    }
+
+    // called once with the outcome when a run ends, however it ends
+    pub fn with_finalizer(mut self, finalizer: Finalizer) -> Self {
+        self.finalizers.push(finalizer);
+        self
+    }
+
+    pub fn with_reporter(mut self, reporter: Reporter) -> Self {
+        self.reporter = Some((reporter, Instant::now(), Instant::now()));
+        self
+    }
+
+    fn report(&mut self) {
+        if let Some((reporter, start, last)) = self.reporter.as_mut() {
+            if self.steps % PROGRESS_STEPS == 0 && last.elapsed() >= PROGRESS_INTERVAL {
+                *last = Instant::now();
+                reporter(&Progress {
+                    steps: self.steps,
+                    elapsed: start.elapsed(),
+                    max_steps: self.limits.max_steps,
+                    max_time: self.limits.max_time
+                });
+            }
+        }
+    }
+
+    pub fn with_hook(mut self, hook: Hook<I>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    pub fn with_trace(mut self, trace: Tracer) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    pub fn with_input(mut self, input: IO) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn with_output(mut self, output: IO) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn with_ip_name(mut self, id: usize, name: IpName) -> Self {
+        self.name_ip(id, name);
+        self
+    }
+
+    pub fn name_ip(&mut self, id: usize, name: IpName) {
+        self.ip_names.insert(id, name);
+    }
+
+    pub fn ip_name(&self, id: usize) -> Option<&IpName> {
+        self.ip_names.get(&id)
+    }
+
+    pub fn ip_label(&self, id: usize) -> String {
+        match self.ip_name(id) {
+            Some(name) => format!("{} ({})", name.name, id),
+            None => id.to_string()
+        }
+    }
+
+    // cells of the source outside printable ASCII, B93 interpreters disagree about what these do
+    pub fn non_ascii_cells(&self) -> Vec<(isize, isize, I)> {
+        let mut cells = Vec::new();
+        for (y, line) in self.code.orig_code.iter().enumerate() {
+            for (x, cell) in line.iter().enumerate() {
+                if !matches!(cell.to_u8(), Some(32..=126)) {
+                    cells.push((x as isize, y as isize, *cell));
+                }
+            }
+        }
+        cells
+    }
+
+    // a warning goes to the diagnostics, like any other
+    pub fn check_ascii(mut self, validation: Validation) -> Result<Self> {
+        let cells = self.non_ascii_cells();
+        if !cells.is_empty() {
+            let cells = convert::join(&cells.iter().map(|(x, y, cell)| format!("({}, {}) {}", x, y, cell)).collect::<Vec<_>>(), ", ");
+            match validation {
+                Validation::Warn => self.warn(format!("source is not printable ASCII at: {}", cells))?,
+                Validation::Strict => Err(Error::new(FungeError::NonAscii(cells)))?
+            }
+        }
+        Ok(self)
+    }
+
+    // how i splits the lines of a file, use new_with_line_endings for the program itself
+    pub fn with_line_endings(mut self, line_endings: LineEndings) -> Self {
+        self.line_endings = line_endings;
+        self
+    }
+
+    pub fn line_endings(&self) -> LineEndings {
+        self.line_endings
+    }
+
+    pub fn with_underflow(mut self, on_underflow: OnUnderflow) -> Self {
+        self.on_underflow = on_underflow;
+        self
+    }
+
+    pub fn underflows(&self) -> &HashMap<Vec<isize>, usize> {
+        &self.underflows
+    }
+
+    pub fn underflow_count(&self) -> usize {
+        self.underflows.values().sum()
+    }
+
+    fn underflow(&mut self, position: Vec<isize>, op: I, n: usize) -> Result<()> {
+        match self.on_underflow {
+            OnUnderflow::Strict => Err(Error::new(FungeError::Underflow(position[0], position[1])))?,
+            OnUnderflow::Warn => self.warn(format!("{} at ({}, {}) popped {} from an empty stack in step {}",
+                                                   to_char(op).unwrap_or('¤'), position[0], position[1], n, self.steps))?,
+            _ => {}
+        }
+        *self.underflows.entry(position).or_default() += n;
+        Ok(())
+    }
+
+    pub(crate) fn path(&self, name: &str) -> PathBuf {
+        self.working_dir.join(name)
+    }
+
+    fn warn(&mut self, message: String) -> Result<()> {
+        match self.diagnostics.as_mut() {
+            Some(diagnostics) if !self.dry_run => diagnostics.push(format!("warning: {}\n", message)),
+            _ => Ok(())
+        }
+    }
+
+    pub fn with_diagnostics(mut self, diagnostics: IO) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    fn shrink_extent(&mut self) {
+        let space = cast(32).expect("space");
+        'left: for x in self.extent.left..self.extent.right {
+            for y in self.extent.top..self.extent.bottom {
+                if self.code[&vec![x, y]] != space {
+                    self.extent.left = x;
+                    break 'left
+                }
+            }
+        }
+        'right: for x in (self.extent.left..self.extent.right).rev() {
+            for y in self.extent.top..self.extent.bottom {
+                if self.code[&vec![x, y]] != space {
+                    self.extent.right = x + 1;
+                    break 'right
                 }
             }
         }
@@ -1248,74 +2791,1748 @@ impl<I: Int> Funge<I> {
     }
 
     fn insert(&mut self, op: I, position: Vec<isize>) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.writes.push((position[0], position[1], op.to_i128().unwrap_or_default()));
+        }
+        if self.literals.is_some() && self.code[&position] != op {
+            self.invalidate_literals(&position);
+        }
+        self.last_writes.push((position.to_owned(), self.code[&position]));
         self.code.insert(position.to_owned(), op);
-        if let Ok(32) = cast_int(op) {
+        if let Ok(32) = cast(op) {
             self.shrink_extent();
         } else {
             self.grow_extent(position);
         }
     }
 
-    fn insert_code(&mut self, code: Vec<String>, x0: isize, y0: isize) -> Result<()> {
-        for (y, line) in code.iter().enumerate() {
-            for (x, char) in line.chars().enumerate() {
-                if char != ' ' {
-                    let x1: isize = x.try_into()?;
-                    let y1: isize = y.try_into()?;
-                    self.insert(ord(char)?, vec![x0 + x1, y0 + y1]);
+    // run the funge file named by the first word with the other words as arguments, with the same rules and
+    // reading the same input, its output is passed on after every step
+    fn execute_funge(&mut self, mut command: Vec<String>) -> Result<i32> {
+        if command.is_empty() || command[0].is_empty() {
+            Err(Error::msg("no file given"))?
+        }
+        if self.nesting >= MAX_NESTING {
+            Err(Error::msg(format!("more than {} funges nested", MAX_NESTING)))?
+        }
+        let file = command.remove(0);
+        let mut nested = Self::from_file_with_line_endings(&self.path(&file).to_string_lossy().into_owned(), self.line_endings)?;
+        nested.rules = self.rules.clone();
+        nested.env = self.env.clone();
+        nested.argv = [file].into_iter().chain(command).collect();
+        nested.rng = StdRng::from_rng(&mut self.rng)?;
+        nested.clock = self.clock;
+        nested.on_underflow = self.on_underflow;
+        nested.diagnostics = self.diagnostics.clone();
+        nested.nesting = self.nesting + 1;
+        // the nested funge gets what is left of the steps of this one, and its steps count for this one as well
+        nested.limits = Limits {
+            max_steps: self.limits.max_steps
+                .map(|max_steps| max_steps.saturating_sub((self.steps + self.nested_steps).max(0) as usize)),
+            ..self.limits.clone()
+        };
+        nested.cancel = self.cancel.clone();
+        nested.working_dir = self.working_dir.clone();
+        nested.started = self.started;
+        nested.input = IO { store: Vec::new(), ..self.input.clone() };
+        nested.output = IO::new().with_number_format(self.output.number_format()).with_output(|store, s| {
+            store.push(s);
+            Ok(())
+        });
+        loop {
+            let steps = nested.steps;
+            nested = match nested.step_ips() {
+                Ok(mut nested) => {
+                    let output = convert::join(&std::mem::take(&mut nested.output.store), "");
+                    if !output.is_empty() {
+                        self.write(output)?;
+                    }
+                    if nested.ips.is_empty() {
+                        self.nested_steps += nested.steps;
+                        return Ok(0)
+                    }
+                    nested
+                }
+                Err(error) => match error.downcast::<FungeError>()? {
+                    FungeError::Quit(return_code) => {
+                        self.nested_steps += steps + 1;
+                        return Ok(return_code)
+                    }
+                    FungeError::StepLimit(_) => {
+                        self.nested_steps += steps;
+                        Err(Error::new(FungeError::StepLimit(self.limits.max_steps.unwrap_or_default())))?
+                    }
+                    error => {
+                        self.nested_steps += steps;
+                        Err(Error::new(error))?
+                    }
+                }
+            }
+        }
+    }
+
+    // bulk insert: cells are converted through a lookup table and the extent is grown only once
+    fn insert_bytes(&mut self, lines: &[&[u8]], x0: isize, y0: isize) -> Result<()> {
+        let table: Vec<Option<I>> = (0..=255u8).map(|b| cast(b).ok()).collect();
+        self.insert_cells(lines, x0, y0, false, |byte| Ok(table[byte as usize].ok_or(FungeError::Casting)?))
+    }
+
+    // write code with its top left corner at x0, y0, spaces leave the cells below them alone unless overwrite is
+    // set, the extent grows and shrinks as needed
+    pub fn insert_code<T: ToString>(&mut self, code: T, x0: isize, y0: isize, overwrite: bool) -> Result<()> {
+        let lines: Vec<Vec<char>> = code.to_string().lines().map(|line| line.chars().collect()).collect();
+        let lines: Vec<&[char]> = lines.iter().map(|line| line.as_slice()).collect();
+        self.insert_cells(&lines, x0, y0, overwrite, from_char)
+    }
+
+    // writes a block of cells with its top-left corner at x0, y0, spaces also erase
+    pub fn insert_block(&mut self, lines: &[Vec<I>], x0: isize, y0: isize) -> Result<()> {
+        let lines: Vec<&[I]> = lines.iter().map(|line| line.as_slice()).collect();
+        self.insert_cells(&lines, x0, y0, true, Ok)
+    }
+
+    fn insert_cells<T: Copy>(&mut self, lines: &[&[T]], x0: isize, y0: isize, overwrite: bool,
+                             cell: impl Fn(T) -> Result<I>) -> Result<()> {
+        let space = self.code.space;
+        let mut written = None;
+        let mut erased = false;
+        for (y, line) in lines.iter().enumerate() {
+            let y = y0 + y as isize;
+            for (x, c) in line.iter().enumerate() {
+                let x = x0 + x as isize;
+                let op = cell(*c)?;
+                if op == space && !(overwrite && self.code[&vec![x, y]] != space) {
+                    continue
+                }
+                if let Some(trace) = self.trace.as_mut() {
+                    trace.writes.push((x, y, op.to_i128().unwrap_or_default()));
+                }
+                self.last_writes.push((vec![x, y], self.code[&vec![x, y]]));
+                self.code.insert(vec![x, y], op);
+                if op == space {
+                    erased = true;
+                } else {
+                    let (left, top, right, bottom) = written.unwrap_or((x, y, x, y));
+                    written = Some((min(left, x), min(top, y), max(right, x), max(bottom, y)));
                 }
             }
         }
+        if let Some((left, top, right, bottom)) = written {
+            self.grow_extent(vec![left, top]);
+            self.grow_extent(vec![right, bottom]);
+        }
+        if erased {
+            self.shrink_extent();
+        }
+        if self.literals.is_some() && (erased || written.is_some()) {
+            self.literals = Some(self.scan_literals());
+        }
         Ok(())
     }
 
-    pub fn run(mut self) -> Result<i32> {
-        loop {
-            self = match self.step() {
-                Err(error) => {
-                    let error = error.downcast::<FungeError>()?;
-                    match error {
-                        FungeError::Quit(return_code) => return Ok(return_code),
-                        error => Err(Error::new(error))?
-                    }
+    // write the cells that differ between two versions of the source, returns the number of changed cells
+    pub fn patch_code<T: ToString>(&mut self, old: T, new: T) -> Result<usize> {
+        let old = Self::source_lines(old, self.line_endings)?;
+        let new = Self::source_lines(new, self.line_endings)?;
+        let mut changed = 0;
+        for y in 0..max(old.len(), new.len()) {
+            let old: Vec<char> = old.get(y).map(|line| line.chars().collect()).unwrap_or_default();
+            let new: Vec<char> = new.get(y).map(|line| line.chars().collect()).unwrap_or_default();
+            for x in 0..max(old.len(), new.len()) {
+                let c = new.get(x).copied().unwrap_or(' ');
+                if old.get(x).copied().unwrap_or(' ') != c {
+                    self.insert(from_char(c)?, vec![x as isize, y as isize]);
+                    changed += 1;
                 }
-                Ok(funge) => funge
             }
         }
+        Ok(changed)
     }
 
-    pub fn step(mut self) -> Result<Self> {
-        self.ips.reverse();
-        let mut new_ips = Vec::new();
-        let n_ips = self.ips.len();
-        for _ in 0..self.ips.len() {
-            if let Some(ip) = self.ips.pop() {
-                self = match ip.step(self, n_ips)? {
-                    (f, ips) => {
-                        new_ips.extend(ips);
-                        f
-                    }
+    pub fn run(self) -> RunOutcome {
+        self.run_with_stats().0
+    }
+
+    // like run used to be: the exit code, or an error when the funge did not halt
+    pub fn run_exit_code(self) -> Result<i32> {
+        self.run().into_result()
+    }
+
+    fn outcome(error: &Error, steps: isize, unconsumed: Vec<Argument>) -> RunOutcome {
+        let (exit_code, steps) = match error.downcast_ref::<FungeError>() {
+            Some(FungeError::Quit(return_code)) => (Some(*return_code), steps + 1),
+            _ => (None, steps)
+        };
+        RunOutcome { exit_code, steps, reason: StopReason::from(error), unconsumed }
+    }
+
+    // what is still buffered is written whatever the reason to stop, a sink that fails now does not change that reason
+    fn stop(&mut self, error: &Error, steps: isize) -> (RunOutcome, SpaceStats) {
+        let _ = self.output.flush();
+        let outcome = Self::outcome(error, steps, std::mem::take(&mut self.arguments));
+        let stats = self.space_stats();
+        for finalizer in std::mem::take(&mut self.finalizers) {
+            finalizer(&outcome, &stats);
+        }
+        (outcome, stats)
+    }
+
+    // the stats are those of the funge when it stopped
+    pub fn run_with_stats(mut self) -> (RunOutcome, SpaceStats) {
+        loop {
+            let steps = self.steps;
+            if let Err(error) = self.step_in_place() {
+                return self.stop(&error, steps + self.nested_steps)
+            }
+        }
+    }
+
+    // run at most n steps, so that a single threaded host can do other work in between
+    pub fn run_steps(mut self, n: usize) -> Result<RunStatus<I>> {
+        for _ in 0..n {
+            self = match self.step_or_quit()? {
+                RunStatus::Paused(funge) => *funge,
+                finished => return Ok(finished)
+            }
+        }
+        Ok(RunStatus::Paused(Box::new(self)))
+    }
+
+    // run until the duration has passed, at least one step is executed
+    pub fn run_for(mut self, duration: Duration) -> Result<RunStatus<I>> {
+        let instant = Instant::now();
+        loop {
+            self = match self.step_or_quit()? {
+                RunStatus::Paused(funge) => *funge,
+                finished => return Ok(finished)
+            };
+            if instant.elapsed() >= duration {
+                return Ok(RunStatus::Paused(Box::new(self)))
+            }
+        }
+    }
+
+    // run with the literal cache next to a plain reference in lockstep, comparing state hashes and output every
+    // so many steps, at the first divergence it fails with the difference
+    pub fn cross_check(mut self, every: usize) -> Result<i32> {
+        let mut output = self.output.clone();
+        // the input is read when the optimized funge asks for it, the reference gets the same lines after it
+        let source = Arc::new(Mutex::new(std::mem::take(&mut self.input)));
+        let lines = Arc::new(Mutex::new(VecDeque::new()));
+        let read = lines.clone();
+        self.input = IO::new().with_prompt(move |store, request| {
+            Ok(match store.pop() {
+                None => {
+                    let s = source.lock().map_err(|_| Error::new(FungeError::Lock))?.pop(request)?;
+                    read.lock().map_err(|_| Error::new(FungeError::Lock))?.push_back(s.clone());
+                    s
+                }
+                Some(s) => s
+            })
+        });
+        let capture = |io: &IO| IO { store: Vec::new(), ..io.clone() }.with_buffering(false).with_output(|store, s| {
+            store.push(s);
+            Ok(())
+        });
+        let mut reference = self.clone();
+        reference.literals = None;
+        reference.input = IO::new().with_input(move |store| {
+            Ok(match store.pop() {
+                None => lines.lock().map_err(|_| Error::new(FungeError::Lock))?.pop_front()
+                    .ok_or(Error::new(FungeError::Input))?,
+                Some(s) => s
+            })
+        });
+        reference.output = capture(&self.output);
+        reference.diagnostics = None;
+        reference.trace = None;
+        reference.finalizers = Vec::new();
+        let mut optimized = self.with_literal_cache();
+        optimized.output = capture(&optimized.output);
+        let (mut reference_output, mut optimized_output) = (String::new(), String::new());
+        let mut next = every as isize;
+        loop {
+            let steps = optimized.steps;
+            if let Err(error) = optimized.step_in_place() {
+                let code = match error.downcast_ref::<FungeError>() {
+                    Some(FungeError::Quit(code)) => *code,
+                    _ => return optimized.fail(error)
+                };
+                let _ = optimized.output.flush();
+                optimized_output.push_str(&convert::join(&std::mem::take(&mut optimized.output.store), ""));
+                match reference.step_or_quit() {
+                    Ok(RunStatus::Finished(reference_code)) if reference_code == code => {}
+                    Err(error) => return optimized.fail(error),
+                    _ => return optimized.fail(Error::new(FungeError::Diverged(
+                        steps, format!("the reference did not quit with {}", code))))
+                }
+                if reference_output != optimized_output {
+                    return optimized.fail(Error::new(FungeError::Diverged(
+                        steps, format!("output {:?} != {:?}", reference_output, optimized_output))))
                 }
+                output.push(optimized_output)?;
+                output.flush()?;
+                // the finalizers run after the output is written
+                optimized.stop(&error, steps);
+                return Ok(code)
+            }
+            optimized_output.push_str(&convert::join(&std::mem::take(&mut optimized.output.store), ""));
+            // the program has not stopped, so when the reference fails or differs the finalizers run here
+            while reference.steps < optimized.steps {
+                reference = match reference.step_or_quit() {
+                    Ok(RunStatus::Paused(funge)) => *funge,
+                    Ok(RunStatus::Finished(code)) => return optimized.fail(Error::new(FungeError::Diverged(
+                        optimized.steps, format!("the reference quit with {}", code)))),
+                    Err(error) => return optimized.fail(error)
+                };
+                reference_output.push_str(&convert::join(&std::mem::take(&mut reference.output.store), ""));
+            }
+            if optimized.steps >= next {
+                next = optimized.steps + every as isize;
+                if reference_output != optimized_output {
+                    return optimized.fail(Error::new(FungeError::Diverged(
+                        optimized.steps, format!("output {:?} != {:?}", reference_output, optimized_output))))
+                }
+                if reference.state_hash() != optimized.state_hash() {
+                    let difference = reference.diff(&optimized).to_string();
+                    return optimized.fail(Error::new(FungeError::Diverged(optimized.steps, difference)))
+                }
+                reference_output.clear();
+                output.push(std::mem::take(&mut optimized_output))?;
             }
         }
-        self.ips.extend(new_ips);
-        self.steps += 1;
-        if self.ips.len() == 0 {
-            Err(Error::new(FungeError::Quit(0)))
-        } else {
-            Ok(self)
+    }
+
+    fn fail<T>(&mut self, error: Error) -> Result<T> {
+        let steps = self.steps;
+        self.stop(&error, steps);
+        Err(error)
+    }
+
+    // the finalizers run when the funge quits or fails
+    fn step_or_quit(mut self) -> Result<RunStatus<I>> {
+        Ok(match self.step_or_stop()? {
+            None => RunStatus::Paused(Box::new(self)),
+            Some(return_code) => RunStatus::Finished(return_code)
+        })
+    }
+
+    // one step like run_steps(1), the exit code when the funge quits, but the funge is kept either way so that what
+    // it looked like at the end can still be inspected
+    pub fn step_or_stop(&mut self) -> Result<Option<i32>> {
+        let steps = self.steps;
+        let error = match self.step_in_place() {
+            Ok(()) => return Ok(None),
+            Err(error) => error
+        };
+        self.stop(&error, steps + self.nested_steps);
+        match error.downcast::<FungeError>()? {
+            FungeError::Quit(return_code) => Ok(Some(return_code)),
+            error => Err(Error::new(error))
         }
     }
 
-    pub fn ips_pos(&self) -> Vec<Vec<isize>> {
-        let mut pos = Vec::new();
-        for ip in self.ips.iter() {
-            pos.push(ip.position.to_owned());
+    // ids are never reused, so the stopped IPs with the lowest ids stopped longest ago, half of what may be kept is
+    // kept, so that this happens only once every so many stopped IPs
+    fn fold_ip_stats(&mut self) {
+        let live = self.ips.iter().map(|ip| ip.id).collect::<HashSet<_>>();
+        let stopped = self.ip_stats.keys().filter(|id| !live.contains(id)).copied().collect::<Vec<_>>();
+        for id in &stopped[..stopped.len().saturating_sub(MAX_STOPPED_IP_STATS / 2)] {
+            if let Some(stats) = self.ip_stats.remove(id) {
+                self.stopped_ip_stats.instructions += stats.instructions;
+                self.stopped_ip_stats.max_stack = max(self.stopped_ip_stats.max_stack, stats.max_stack);
+            }
         }
-        pos
     }
 
-    pub fn get_stack_string(&self) -> String {
-        join(&(&self.ips).iter().map(|ip| ip.stack.to_string()).collect(), "\n")
+    pub fn space_stats(&self) -> SpaceStats {
+        SpaceStats { ips: self.ip_stats.clone(), stopped: self.stopped_ip_stats.clone(), ..self.code.stats(self.extent.clone()) }
+    }
+
+    pub fn ip_stats(&self) -> &BTreeMap<usize, IpStats> {
+        &self.ip_stats
+    }
+
+    // Every IP executes once per step, in the order given by the schedule. Afterwards the IP list keeps its
+    // order: an IP that stops is removed, a child from t is inserted directly before its parent, and IP ids
+    // are never reused.
+    pub fn step(mut self) -> Result<Self> {
+        self.step_in_place()?;
+        Ok(self)
+    }
+
+    // like step, but a funge that stops is kept, so what it looked like at the end can still be reported
+    fn step_in_place(&mut self) -> Result<()> {
+        self.execute_ips()?;
+        if self.ips.is_empty() {
+            self.output.flush()?;
+            Err(Error::new(FungeError::Quit(0)))
+        } else {
+            Ok(())
+        }
     }
-}
\ No newline at end of file
+
+    fn step_ips(mut self) -> Result<Self> {
+        self.execute_ips()?;
+        Ok(self)
+    }
+
+    fn execute_ips(&mut self) -> Result<()> {
+        self.check_limits()?;
+        self.nested_steps = 0;
+        self.last_output.clear();
+        self.last_writes.clear();
+        self.last_command = None;
+        if self.step_literal()? {
+            return Ok(())
+        }
+        let n_ips = self.ips.len();
+        self.live_ips = n_ips;
+        let mut order: Vec<usize> = (0..n_ips).collect();
+        match self.rules.schedule {
+            Schedule::RoundRobin => {}
+            Schedule::Reverse => order.reverse(),
+            Schedule::Random => order.shuffle(&mut self.rng)
+        }
+        let mut ips: Vec<Option<IP<I>>> = std::mem::take(&mut self.ips).into_iter().map(Some).collect();
+        let mut new_ips: Vec<Vec<IP<I>>> = vec![Vec::new(); n_ips];
+        for i in order {
+            if let Some(ip) = ips[i].take() {
+                new_ips[i] = ip.step(self)?;
+            }
+        }
+        self.ips = new_ips.into_iter().flatten().collect();
+        if self.ip_stats.len() > self.ips.len() + MAX_STOPPED_IP_STATS {
+            self.fold_ip_stats();
+        }
+        self.steps += 1 + self.nested_steps;
+        Ok(())
+    }
+
+    fn write(&mut self, s: String) -> Result<()> {
+        self.output_len += s.chars().count();
+        if let Some(max_output) = self.limits.max_output.filter(|max_output| self.output_len > *max_output) {
+            Err(Error::new(FungeError::OutputLimit(max_output)))?
+        }
+        if let Some(trace) = self.trace.as_mut() {
+            trace.output.push_str(&s);
+        }
+        if self.dry_run {
+            self.effects.push(Effect::Output(s));
+            Ok(())
+        } else {
+            self.last_output.push_str(&s);
+            self.output.push(s)
+        }
+    }
+
+    // everything written during the last step, also when the output is buffered
+    pub fn last_output(&self) -> &str {
+        &self.last_output
+    }
+
+    // the cells written in the last step with the values they had before, in the order they were written
+    pub fn last_writes(&self) -> &[(Vec<isize>, I)] {
+        &self.last_writes
+    }
+
+    // the command = ran in the last step, its output is the last output
+    pub fn last_command(&self) -> Option<&str> {
+        self.last_command.as_deref()
+    }
+
+    fn read(&mut self, request: InputRequest) -> Result<String> {
+        if self.dry_run {
+            self.effects.push(Effect::Input);
+            Err(Error::new(FungeError::Input))
+        } else {
+            if self.arguments.is_empty() {
+                return self.input.pop(&request)
+            }
+            match (self.arguments.remove(0), request.op) {
+                (Argument::Int(n), '&') => Ok(n.to_string()),
+                (Argument::Str(s), '~') => {
+                    let mut chars = s.chars();
+                    let c = chars.next().ok_or(Error::new(FungeError::Input))?;
+                    if !chars.as_str().is_empty() {
+                        self.arguments.insert(0, Argument::Str(chars.collect()));
+                    }
+                    Ok(c.to_string())
+                }
+                (argument, _) => Err(Error::new(FungeError::Argument(argument.to_string(), request.to_string())))
+            }
+        }
+    }
+
+    fn dry_clone(&self) -> Self {
+        let mut funge = self.clone();
+        funge.dry_run = true;
+        funge.effects = Vec::new();
+        funge.trace = None;
+        funge
+    }
+
+    pub fn dry_step(&self) -> Result<(Self, Vec<Effect>)> {
+        let mut funge = self.dry_clone().step_ips()?;
+        funge.dry_run = false;
+        let effects = std::mem::take(&mut funge.effects);
+        Ok((funge, effects))
+    }
+
+    pub fn predict(&self, index: usize) -> Result<Prediction<I>> {
+        let ip = self.ips.get(index).ok_or(Error::msg("No such IP."))?.clone();
+        let mut funge = self.dry_clone();
+        let op = ip.op(&funge);
+        let before = ip.stack.top();
+        let ips = ip.step(&mut funge)?;
+        let (after, position) = match ips.last() {
+            Some(ip) => (ip.stack.top(), Some(ip.position.to_owned())),
+            None => (before.clone(), None)
+        };
+        let common = before.iter().zip(after.iter()).take_while(|(a, b)| a == b).count();
+        Ok(Prediction {
+            op,
+            pops: before[common..].to_vec(),
+            pushes: after[common..].to_vec(),
+            position,
+            effects: funge.effects
+        })
+    }
+
+    // runs code in a funge of its own on a copy of the stacks of an IP, as a dry run so that nothing is read or written,
+    // and returns the top stack and the output when the code is done
+    pub fn scratch(&self, index: usize, code: &str, steps: usize) -> Result<(Vec<I>, String)> {
+        let ip = self.ips.get(index).ok_or(Error::msg("No such IP."))?;
+        let mut funge = Funge::new(format!("{}@", code))?;
+        funge.rules = self.rules.clone();
+        funge.dry_run = true;
+        funge.ips[0].stack = ip.stack.clone();
+        let end = vec![code.chars().count() as isize, 0];
+        for _ in 0..steps {
+            match funge.ips.first() {
+                Some(ip) if ip.position == end => {
+                    let output = funge.effects.iter().filter_map(|effect| match effect {
+                        Effect::Output(s) => Some(s.as_str()),
+                        _ => None
+                    }).collect();
+                    return Ok((ip.stack(), output))
+                }
+                Some(_) => funge = funge.step_ips()?,
+                None => Err(Error::msg("The code stopped before its end."))?
+            }
+        }
+        Err(Error::msg(format!("The code did not reach its end in {} steps.", steps)))
+    }
+
+    // the next positions and ops of each IP by id, dry running at most steps steps, it stops early at input or quit
+    pub fn lookahead(&self, steps: usize) -> BTreeMap<usize, Vec<(Vec<isize>, I)>> {
+        let mut ahead: BTreeMap<usize, Vec<(Vec<isize>, I)>> = BTreeMap::new();
+        let mut funge = self.dry_clone();
+        for _ in 0..steps {
+            funge = match funge.step_ips() {
+                Ok(funge) if !funge.effects.iter().any(|effect| matches!(effect, Effect::Quit(_))) => funge,
+                _ => break
+            };
+            for ip in &funge.ips {
+                ahead.entry(ip.id).or_default().push((ip.position.to_owned(), ip.op(&funge)));
+            }
+        }
+        ahead
+    }
+
+    // FNV-1a of funge-space, the IPs and their stacks, but not IO, the same in every run and build
+    pub fn state_hash(&self) -> u64 {
+        let mut hash = 0xcbf29ce484222325;
+        let mut feed = |value: i128| {
+            for byte in value.to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+        let cells = self.code.cells();
+        feed(cells.len() as i128);
+        for (pos, op) in cells {
+            feed(pos[0] as i128);
+            feed(pos[1] as i128);
+            feed(op.to_i128().unwrap_or_default());
+        }
+        feed(self.ips.len() as i128);
+        for ip in &self.ips {
+            feed(ip.id as i128);
+            for value in ip.position.iter().chain(&ip.delta).chain(&ip.offset) {
+                feed(*value as i128);
+            }
+            feed(ip.string as i128);
+            feed(ip.stack.stackstack.len() as i128);
+            for stack in &ip.stack.stackstack {
+                feed(stack.len() as i128);
+                for value in &stack.stack {
+                    feed(value.to_i128().unwrap_or_default());
+                }
+            }
+        }
+        hash
+    }
+
+    // the steps, IPs with their stacks, the cells that are not a space and the output so far
+    pub fn to_json(&self) -> String {
+        let vector = |v: &[isize]| format!("[{}]", convert::join(v, ","));
+        let ips = self.ips.iter().map(|ip| {
+            let stacks = ip.stacks().iter().map(|stack| format!("[{}]", convert::join(stack, ","))).collect::<Vec<_>>();
+            format!("{{\"id\":{},\"position\":{},\"delta\":{},\"offset\":{},\"string_mode\":{},\"stacks\":[{}]}}",
+                    ip.id, vector(&ip.position), vector(&ip.delta), vector(&ip.offset), ip.string, convert::join(&stacks, ","))
+        }).collect::<Vec<_>>();
+        let cells = self.code.cells().iter().map(|(pos, op)| format!("[{},{},{}]", pos[0], pos[1], op)).collect::<Vec<_>>();
+        format!("{{\"steps\":{},\"ips\":[{}],\"cells\":[{}],\"output\":{}}}", self.steps, convert::join(&ips, ","),
+                convert::join(&cells, ","), trace::json_string(&self.output.get()))
+    }
+
+    // everything that differs from another funge, IPs are matched by id, IO is not compared
+    pub fn diff(&self, other: &Self) -> StateDiff<I> {
+        let mut diff = StateDiff { cells: other.code.diff(&self.code), ..StateDiff::default() };
+        let mut ids: Vec<usize> = self.ips.iter().chain(&other.ips).map(|ip| ip.id).collect();
+        ids.sort();
+        ids.dedup();
+        for id in ids {
+            let (a, b) = match (self.ips.iter().find(|ip| ip.id == id), other.ips.iter().find(|ip| ip.id == id)) {
+                (Some(a), Some(b)) => (a, b),
+                (a, b) => {
+                    diff.ips.push((id, "ip".to_string(), a.is_some().to_string(), b.is_some().to_string()));
+                    continue
+                }
+            };
+            for (field, a, b) in [("position", format!("{:?}", a.position), format!("{:?}", b.position)),
+                                  ("delta", format!("{:?}", a.delta), format!("{:?}", b.delta)),
+                                  ("offset", format!("{:?}", a.offset), format!("{:?}", b.offset)),
+                                  ("string mode", a.string.to_string(), b.string.to_string()),
+                                  ("stacks", a.stack.len_stack().to_string(), b.stack.len_stack().to_string())] {
+                if a != b {
+                    diff.ips.push((id, field.to_string(), a, b));
+                }
+            }
+            for (n, (a, b)) in a.stack.stackstack.iter().zip(&b.stack.stackstack).enumerate() {
+                for i in 0..max(a.len(), b.len()) {
+                    let (a, b) = (a.stack.get(i).copied(), b.stack.get(i).copied());
+                    if a != b {
+                        diff.stacks.push((id, n, i, a, b));
+                    }
+                }
+            }
+        }
+        diff
+    }
+
+    pub fn ips_pos(&self) -> Vec<Vec<isize>> {
+        let mut pos = Vec::new();
+        for ip in self.ips.iter() {
+            pos.push(ip.position.to_owned());
+        }
+        pos
+    }
+
+    #[doc(hidden)]
+    pub fn get_stack_string(&self) -> String {
+        convert::join(&self.ips.iter().map(|ip| ip.stack.to_string()).collect::<Vec<_>>(), "\n")
+    }
+
+    // headless text view of the code around the IPs, the stacks and the end of the output, with ansi set the IPs
+    // are shown in reverse video
+    pub fn render(&self, width: usize, height: usize, ansi: bool) -> String {
+        let (width, height) = (max(width, 1) as isize, max(height, 1));
+        let rows = min(height / 2, height.saturating_sub(6)) as isize;
+        let n = max(self.ips.len(), 1) as isize;
+        let (x, y) = (self.ips.iter().map(|ip| ip.position[0]).sum::<isize>() / n,
+                      self.ips.iter().map(|ip| ip.position[1]).sum::<isize>() / n);
+        let top = if rows >= self.extent.height() { self.extent.top } else { max(y - rows / 2, self.extent.top) };
+        let left = if width >= self.extent.width() { self.extent.left } else { max(x - width / 2, self.extent.left) };
+        let rect = Rect::new(left, left + min(width, self.extent.width()), top, top + min(rows, self.extent.height()));
+        let mut lines = Vec::new();
+        for (y, row) in self.code.region(&rect).into_iter().enumerate() {
+            let y = rect.top + y as isize;
+            let mut line = String::new();
+            for (x, op) in row.into_iter().enumerate() {
+                let c = to_char(op).ok().filter(|c| !c.is_control()).unwrap_or('¤');
+                if ansi && self.ips.iter().any(|ip| ip.position == [rect.left + x as isize, y]) {
+                    line.push_str(&format!("\x1b[7m{}\x1b[0m", c));
+                } else {
+                    line.push(c);
+                }
+            }
+            lines.push(line);
+        }
+        lines.push(String::new());
+        lines.push(format!("steps: {}, ips: {}, top-left: {}, {}", self.steps, self.ips.len(), rect.left, rect.top));
+        let chars = |line: &str| line.chars().take(width as usize).collect::<String>();
+        for ip in &self.ips {
+            lines.push(chars(&format!("{} {:?}: [{}]", self.ip_label(ip.id), ip.position, convert::join(&ip.stack.top(), ", "))));
+        }
+        let output = self.output.get();
+        let output: Vec<&str> = output.lines().collect();
+        lines.push("output:".to_string());
+        let room = height.saturating_sub(lines.len());
+        lines.extend(output[output.len().saturating_sub(room)..].iter().map(|line| chars(line)));
+        lines.truncate(height);
+        convert::join(&lines, "\n")
+    }
+}
+
+
+#[non_exhaustive]
+pub enum HookAction<I: Int> {
+    Proceed,
+    Skip,
+    Replace(I)
+}
+
+pub type Hook<I> = fn(&Funge<I>, &IP<I>, I) -> HookAction<I>;
+
+pub type Finalizer = fn(&RunOutcome, &SpaceStats);
+
+pub type Reporter = fn(&Progress);
+
+// the time HRTI measures, with a resolution of microseconds
+pub type Timer<I> = fn(&Funge<I>) -> Duration;
+
+
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct IpName {
+    pub name: String,
+    pub color: Option<String>
+}
+
+impl IpName {
+    pub fn new<T: ToString>(name: T) -> Self {
+        Self { name: name.to_string(), color: None }
+    }
+
+    pub fn with_color<T: ToString>(mut self, color: T) -> Self {
+        self.color = Some(color.to_string());
+        self
+    }
+
+    // "name" or "name:color"
+    pub fn parse(text: &str) -> Option<Self> {
+        let (name, color) = match text.split_once(':') {
+            Some((name, color)) => (name.trim(), Some(color.trim())),
+            None => (text.trim(), None)
+        };
+        if name.is_empty() {
+            return None
+        }
+        let name = Self::new(name);
+        Some(match color.filter(|color| !color.is_empty()) {
+            Some(color) => name.with_color(color),
+            None => name
+        })
+    }
+
+    // pragmas are comments in the code, for example: ;ip 1 worker:red;
+    fn from_pragmas(code: &[String]) -> HashMap<usize, Self> {
+        let re = Regex::new(r";ip (\d+) ([^;\s]+);").expect("valid regex");
+        code.iter().flat_map(|line| re.captures_iter(line)
+            .filter_map(|c| Some((c[1].parse().ok()?, Self::parse(&c[2])?)))
+            .collect::<Vec<_>>()).collect()
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Effect {
+    Output(String),
+    Input,
+    Execute(String),
+    ReadFile(String),
+    WriteFile(String),
+    Network(char),
+    Quit(i32)
+}
+
+impl Display for Effect {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Effect::Output(s) => write!(f, "outputs {:?}", s),
+            Effect::Input => write!(f, "reads input"),
+            Effect::Execute(command) => write!(f, "executes {:?}", command),
+            Effect::ReadFile(file) => write!(f, "reads {}", file),
+            Effect::WriteFile(file) => write!(f, "writes {}", file),
+            Effect::Network(op) => write!(f, "uses the network with {}", op),
+            Effect::Quit(code) => write!(f, "quits with {}", code)
+        }
+    }
+}
+
+
+// id, stack, index from the bottom, this, other
+pub type StackDiff<I> = (usize, usize, usize, Option<I>, Option<I>);
+
+// cells as (position, this, other) and IP fields as (id, field, this, other)
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateDiff<I: Int> {
+    pub cells: Vec<(Vec<isize>, I, I)>,
+    pub ips: Vec<(usize, String, String, String)>,
+    pub stacks: Vec<StackDiff<I>>
+}
+
+impl<I: Int> Default for StateDiff<I> {
+    fn default() -> Self {
+        Self { cells: Vec::new(), ips: Vec::new(), stacks: Vec::new() }
+    }
+}
+
+impl<I: Int> StateDiff<I> {
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty() && self.ips.is_empty() && self.stacks.is_empty()
+    }
+}
+
+impl<I: Int> Display for StateDiff<I> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let value = |value: &Option<I>| value.map(|value| value.to_string()).unwrap_or("-".to_string());
+        for (pos, a, b) in &self.cells {
+            writeln!(f, "cell ({}, {}): {} != {}", pos[0], pos[1], cells_to_string(vec![*a]), cells_to_string(vec![*b]))?;
+        }
+        for (id, field, a, b) in &self.ips {
+            writeln!(f, "ip {} {}: {} != {}", id, field, a, b)?;
+        }
+        for (id, n, i, a, b) in &self.stacks {
+            writeln!(f, "ip {} stack {} entry {}: {} != {}", id, n, i, value(a), value(b))?;
+        }
+        Ok(())
+    }
+}
+
+
+#[non_exhaustive]
+pub struct Prediction<I: Int> {
+    pub op: I,
+    pub pops: Vec<I>,
+    pub pushes: Vec<I>,
+    pub position: Option<Vec<isize>>,
+    pub effects: Vec<Effect>
+}
+
+impl<I: Int> Display for Prediction<I> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", cells_to_string(vec![self.op]))?;
+        if !self.pops.is_empty() {
+            write!(f, " pops {}", convert::join(&self.pops, ", "))?;
+        }
+        if !self.pushes.is_empty() {
+            write!(f, " → pushes {}", convert::join(&self.pushes, ", "))?;
+        }
+        for effect in &self.effects {
+            write!(f, " → {}", effect)?;
+        }
+        match &self.position {
+            Some(pos) => write!(f, ", next ({}, {})", pos[0], pos[1]),
+            None => write!(f, ", stops")
+        }
+    }
+}
+
+
+// used by the debugger, not part of the stable api
+#[doc(hidden)]
+pub struct SharedFunge<T> {
+    funge: Arc<RwLock<T>>
+}
+
+impl<T> SharedFunge<T> {
+    pub fn new(funge: T) -> Self {
+        Self { funge: Arc::new(RwLock::new(funge)) }
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, T>> {
+        self.funge.read().map_err(|_| Error::new(FungeError::Lock))
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, T>> {
+        self.funge.write().map_err(|_| Error::new(FungeError::Lock))
+    }
+
+    pub fn observe<R>(&self, fun: impl FnOnce(&T) -> R) -> Result<R> {
+        Ok(fun(&*self.read()?))
+    }
+
+    pub fn update<R>(&self, fun: impl FnOnce(&mut T) -> R) -> Result<R> {
+        Ok(fun(&mut *self.write()?))
+    }
+}
+
+impl<T> Clone for SharedFunge<T> {
+    fn clone(&self) -> Self {
+        Self { funge: Arc::clone(&self.funge) }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn quiet<I: Int>(funge: Funge<I>) -> Funge<I> {
+        funge.with_output(IO::new().with_output(|store, s| {
+            store.push(s);
+            Ok(())
+        }))
+    }
+
+    #[test]
+    fn version_number_keeps_the_parts_apart() {
+        assert_eq!(version_number("0.1.0").unwrap(), 1000);
+        assert_eq!(version_number("1.2.10").unwrap(), 1002010);
+        assert_eq!(version_number("1.21.0").unwrap(), 1021000);
+        assert_eq!(version_number("12.0.999").unwrap(), 12000999);
+        assert_eq!(version_number("999.999.999").unwrap(), 999999999);
+    }
+
+    #[test]
+    fn version_number_ignores_pre_release_and_build() {
+        assert_eq!(version_number("2.0.0-beta.1").unwrap(), 2000000);
+        assert_eq!(version_number("1.2.3+build.5").unwrap(), 1002003);
+        assert_eq!(version_number("1.2.3-rc.1+build").unwrap(), 1002003);
+    }
+
+    #[test]
+    fn version_number_rejects_other_versions() {
+        for version in ["", "1", "1.2", "1.2.3.4", "1.1000.0", "1.0.1000", "a.b.c", "1.-2.3"] {
+            assert!(version_number(version).is_err(), "{}", version);
+        }
+    }
+
+    #[test]
+    fn y_fits_32_bit_cells() {
+        assert!(i32::try_from(version_number(VERSION).unwrap()).is_ok());
+        assert!(i32::try_from(handprint("wprf")).is_ok());
+        let outcome = quiet(Funge::<i32>::new("0y@").unwrap()).run();
+        assert_eq!(outcome.reason, StopReason::Halted);
+    }
+
+    fn chars(s: &str) -> Vec<isize> {
+        s.chars().map(|c| c as isize).collect()
+    }
+
+    // what 0y pushes, from the bottom up, the first field ends up on top
+    fn y_stack(argv: &[&str], env: &[(&str, &str)]) -> Vec<isize> {
+        let funge = quiet(Funge::<isize>::new("0y@").unwrap()).deterministic()
+            .with_argv(argv.iter().map(|arg| arg.to_string()).collect())
+            .with_env(env.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect());
+        let RunStatus::Paused(funge) = funge.run_steps(2).unwrap() else { panic!("the funge quit") };
+        funge.ips[0].stack()
+    }
+
+    #[test]
+    fn y_layout() {
+        let mut expected = Vec::new();
+        // 20: environment, each variable ends in 0, one more 0 ends the list, read from the top down
+        expected.extend([0, 0].iter().chain(chars("v=K").iter()).chain([0].iter()).chain(chars("2=J").iter()));
+        // 19: program path and arguments, each ends in 0, two more 0s end the list
+        expected.extend([0, 0, 0].iter().chain(chars("ba").iter()).chain([0].iter()).chain(chars("fb.p").iter()));
+        expected.extend([
+            0,  // 18: size of the only stack, y popped its argument
+            1,  // 17: number of stacks
+            0,  // 16: time, midnight
+            70 * 256 * 256 + 256 + 1,  // 15: date, 1 January 1970
+            2, 0,  // 14: size of the code minus one
+            0, 0,  // 13: top left of the code
+            0, 0,  // 12: storage offset
+            1, 0,  // 11: delta
+            1, 0,  // 10: position of y
+            0,  // 9: team
+            0,  // 8: IP id
+            2,  // 7: dimensions
+            std::path::MAIN_SEPARATOR as isize,  // 6
+            1,  // 5: operating paradigm
+            version_number(VERSION).unwrap() as isize,  // 4
+            handprint("wprf") as isize,  // 3
+            std::mem::size_of::<isize>() as isize,  // 2: bytes per cell
+            1 + 2 + 4 + 16  // 1: t, i and o, unbuffered, deterministic disables =
+        ]);
+        assert_eq!(y_stack(&["p.bf", "ab"], &[("J", "2"), ("K", "v")]), expected);
+    }
+
+    #[test]
+    fn y_layout_without_arguments_and_environment() {
+        let stack = y_stack(&[], &[]);
+        // one 0 ends the empty environment and two the empty arguments, then the size of the empty stack comes
+        assert_eq!(stack[..4], [0, 0, 0, 0]);
+        // fields 18 to 1 take 23 cells
+        assert_eq!(stack.len(), 3 + 23);
+    }
+
+    #[test]
+    fn with_version_keeps_what_was_taken_away() {
+        let funge = Funge::<isize>::new("@").unwrap();
+        let before = funge.clone().deterministic().with_version("B98").unwrap();
+        let after = funge.with_version("B98").unwrap().deterministic();
+        assert!(!before.rules().can_execute());
+        assert!(!after.rules().can_execute());
+        assert!(!before.rules().can_network());
+        let funge = Funge::<isize>::new("@").unwrap().without_instructions("t").with_version("B93").unwrap()
+            .with_version("B98").unwrap();
+        assert!(!funge.rules().can_split());
+        assert!(funge.rules().is_enabled('k'));
+    }
+
+    #[test]
+    fn with_version_keeps_chosen_options() {
+        let funge = Funge::<isize>::new("@").unwrap().with_wrap(Wrap::Unbounded).with_schedule(Schedule::Reverse)
+            .with_execute(Execute::Funge).with_version("B93").unwrap();
+        assert_eq!(funge.rules().wrap(), Wrap::Unbounded);
+        assert_eq!(funge.rules().schedule(), Schedule::Reverse);
+        assert_eq!(funge.rules().execute(), Execute::Funge);
+        assert!(!funge.rules().jump_wraps());
+    }
+
+    #[test]
+    fn with_version_sets_what_the_version_decides() {
+        let funge = Funge::<isize>::new("@").unwrap().with_version("B93").unwrap();
+        assert_eq!(funge.rules().wrap(), Wrap::Torus);
+        assert_eq!(funge.rules().on_error(), OnError::Ignore);
+        let funge = funge.with_version("B98").unwrap();
+        assert_eq!(funge.rules().wrap(), Wrap::Lahey);
+        assert_eq!(funge.rules().on_error(), OnError::Reflect);
+        assert!(funge.rules().jump_wraps());
+    }
+
+    // t at the start: the child, inserted before its parent, goes west and prints 2 in the same step as its parent
+    // prints 1
+    fn schedule_output(schedule: Schedule, seed: u64) -> String {
+        let mut funge = quiet(Funge::<isize>::new("t1.@.2").unwrap()).with_schedule(schedule).with_seed(seed);
+        for _ in 0..3 {
+            funge = funge.step().unwrap();
+        }
+        funge.output.get()
+    }
+
+    #[test]
+    fn round_robin_runs_ips_in_list_order() {
+        assert_eq!(schedule_output(Schedule::RoundRobin, 0), "2 1 ");
+    }
+
+    #[test]
+    fn reverse_runs_ips_in_reverse_list_order() {
+        assert_eq!(schedule_output(Schedule::Reverse, 0), "1 2 ");
+    }
+
+    #[test]
+    fn random_order_follows_the_seed() {
+        let outputs = (0..32).map(|seed| schedule_output(Schedule::Random, seed)).collect::<Vec<_>>();
+        assert!(outputs.iter().all(|output| output == "2 1 " || output == "1 2 "));
+        assert!(outputs.contains(&"2 1 ".to_string()) && outputs.contains(&"1 2 ".to_string()));
+        assert_eq!(outputs, (0..32).map(|seed| schedule_output(Schedule::Random, seed)).collect::<Vec<_>>());
+    }
+
+    // the ids and positions of the IPs in the order of the list
+    fn ip_list(funge: &Funge<isize>) -> Vec<(usize, isize)> {
+        funge.ips.iter().map(|ip| (ip.id, ip.position()[0])).collect()
+    }
+
+    #[test]
+    fn ip_list_keeps_its_order() {
+        let mut funge = quiet(Funge::<isize>::new("t t @").unwrap());
+        assert_eq!(ip_list(&funge), [(0, 0)]);
+        // the child is inserted before its parent and goes the other way
+        funge = funge.step().unwrap();
+        assert_eq!(ip_list(&funge), [(1, 4), (0, 2)]);
+        // the child stops and is removed, the next child does not get its id
+        funge = funge.step().unwrap();
+        assert_eq!(ip_list(&funge), [(2, 0), (0, 4)]);
+        funge = funge.step().unwrap();
+        assert_eq!(ip_list(&funge), [(3, 2), (2, 4)]);
+    }
+
+    // the output once the funge quits, at most so many steps
+    fn output<I: Int>(mut funge: Funge<I>) -> String {
+        for _ in 0..1000 {
+            if funge.step_or_stop().unwrap().is_some() {
+                return funge.output.get()
+            }
+        }
+        panic!("the funge did not quit")
+    }
+
+    // 2 is printed before the # in the last cell, 1 only when the # lands on the first cell of the line
+    fn jump_east(rules: fn(Funge<isize>) -> Funge<isize>, width: usize) -> String {
+        output(quiet(rules(Funge::new(format!("12v\n.@>.{:>1$}", "#", width - 4)).unwrap())))
+    }
+
+    // the same going west, the # is in the first cell and 1 is in the last
+    fn jump_west(rules: fn(Funge<isize>) -> Funge<isize>, width: usize) -> String {
+        output(quiet(rules(Funge::new(format!("12v\n#.<{:>1$}", "@.", width - 3)).unwrap())))
+    }
+
+    #[test]
+    fn jump_at_the_edge_lands_on_the_wrapped_to_cell_in_b93() {
+        let rules = |funge: Funge<isize>| funge.with_version("B93").unwrap().with_wrap(Wrap::Lahey);
+        assert_eq!(jump_east(rules, 6), "2 1 ");
+        assert_eq!(jump_west(rules, 6), "2 1 ");
+    }
+
+    #[test]
+    fn jump_at_the_edge_skips_the_wrapped_to_cell_in_b98() {
+        let rules = |funge: Funge<isize>| funge.with_version("B98").unwrap();
+        assert_eq!(jump_east(rules, 6), "2 ");
+        assert_eq!(jump_west(rules, 6), "2 ");
+    }
+
+    #[test]
+    fn jump_at_the_edge_of_the_torus_skips_the_wrapped_to_cell() {
+        let rules = |funge: Funge<isize>| funge.with_version("B93").unwrap();
+        assert_eq!(jump_east(rules, 80), "2 ");
+        assert_eq!(jump_west(rules, 80), "2 ");
+    }
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    // i loads the fixture at 0,5 in text mode, the stack is the width, height, x and y it pushed
+    fn load(name: &str, line_endings: LineEndings) -> (Funge<isize>, Vec<isize>) {
+        let code = format!("050 0\"{}\"i", fixture(name).chars().rev().collect::<String>());
+        let i = code.len() as isize - 1;
+        let mut funge = Funge::new(code).unwrap().with_line_endings(line_endings);
+        while funge.ips[0].position()[0] != i {
+            funge = funge.step().unwrap();
+        }
+        funge = funge.step().unwrap();
+        let stack = funge.ips[0].stack().to_vec();
+        (funge, stack)
+    }
+
+    #[test]
+    fn i_loads_crlf_without_carriage_returns() {
+        for name in ["crlf.txt", "crlf_no_final.txt"] {
+            let (funge, stack) = load(name, LineEndings::Normalize);
+            assert_eq!(stack, [3, 2, 0, 5], "{}", name);
+            assert_eq!(funge.code.get_string(Rect::new(0, 4, 5, 7)), ["ab  ", "cde "], "{}", name);
+        }
+    }
+
+    #[test]
+    fn i_loads_crlf_with_carriage_returns_when_preserved() {
+        let (funge, stack) = load("crlf.txt", LineEndings::Preserve);
+        assert_eq!(stack, [4, 2, 0, 5]);
+        assert_eq!(funge.code[&vec![2, 5]], 13);
+        assert_eq!(funge.code[&vec![3, 6]], 13);
+        let (funge, stack) = load("crlf_no_final.txt", LineEndings::Preserve);
+        assert_eq!(stack, [3, 2, 0, 5]);
+        assert_eq!(funge.code[&vec![2, 5]], 13);
+        assert_eq!(funge.code[&vec![3, 6]], 32);
+    }
+
+    #[test]
+    fn program_with_mixed_line_endings_loads_like_with_newlines() {
+        let funge = Funge::<isize>::from_file(&fixture("mixed.txt")).unwrap();
+        let unix = Funge::<isize>::new("ab\ncde\nf").unwrap();
+        assert_eq!(funge.code.get_string(Rect::new(0, 4, 0, 4)), unix.code.get_string(Rect::new(0, 4, 0, 4)));
+        assert_eq!(funge.code.get_string(Rect::new(0, 4, 0, 3)), ["ab  ", "cde ", "f   "]);
+    }
+
+    #[test]
+    fn program_with_mixed_line_endings_keeps_carriage_returns_when_preserved() {
+        let funge = Funge::<isize>::from_file_with_line_endings(&fixture("mixed.txt"), LineEndings::Preserve).unwrap();
+        assert_eq!(funge.code[&vec![2, 0]], 13);
+        assert_eq!(funge.code[&vec![6, 0]], 13);
+        assert_eq!(funge.code[&vec![0, 1]], 'f' as isize);
+        assert_eq!(funge.code[&vec![0, 2]], 32);
+    }
+
+    #[test]
+    fn program_from_windows_runs_like_from_unix() {
+        let windows = quiet(Funge::<isize>::from_file(&fixture("windows.bf")).unwrap());
+        assert_eq!(windows.code[&vec![0, 0]], 'v' as isize);
+        assert_eq!(output(windows), output(quiet(Funge::<isize>::new("v\n>12+.@").unwrap())));
+        assert_eq!(output(quiet(Funge::<isize>::new("\u{feff}v\r\n>12+.@\r\n").unwrap())), "3 ");
+    }
+
+    fn overlaid(overlay: &str, x0: isize, y0: isize, overwrite: bool) -> (Vec<String>, Rect) {
+        let mut funge = Funge::<isize>::new("abcd\nefgh\nijkl").unwrap();
+        funge.insert_code(overlay, x0, y0, overwrite).unwrap();
+        (funge.code.get_string(Rect::new(0, 4, 0, 3)), funge.extent)
+    }
+
+    #[test]
+    fn overlay_erases_a_region_with_overwrite() {
+        let (lines, extent) = overlaid("  \n  ", 1, 0, true);
+        assert_eq!(lines, ["a  d", "e  h", "ijkl"]);
+        assert_eq!(extent, Rect::new(0, 4, 0, 3));
+    }
+
+    #[test]
+    fn overlay_leaves_cells_below_spaces_without_overwrite() {
+        let (lines, _) = overlaid("  \n  ", 1, 0, false);
+        assert_eq!(lines, ["abcd", "efgh", "ijkl"]);
+        let (lines, _) = overlaid("x \n y", 1, 1, false);
+        assert_eq!(lines, ["abcd", "exgh", "ijyl"]);
+        let (lines, _) = overlaid("x \n y", 1, 1, true);
+        assert_eq!(lines, ["abcd", "ex h", "i yl"]);
+    }
+
+    #[test]
+    fn overlay_that_erases_an_edge_shrinks_the_extent() {
+        let (lines, extent) = overlaid("  \n  \n  ", 2, 0, true);
+        assert_eq!(lines, ["ab  ", "ef  ", "ij  "]);
+        assert_eq!(extent, Rect::new(0, 2, 0, 3));
+        let (lines, extent) = overlaid("    ", 0, 2, true);
+        assert_eq!(lines, ["abcd", "efgh", "    "]);
+        assert_eq!(extent, Rect::new(0, 4, 0, 2));
+    }
+
+    // every rect from -4 to 7, also empty ones, against cell by cell, on rows of different lengths with cells in
+    // all four quadrants and past the end of a short row
+    #[test]
+    fn region_matches_cells_for_rects_in_all_quadrants() {
+        let mut code = FungeSpace::<isize>::new(vec!["abc".to_string(), "de".to_string(), "fghi".to_string()]).unwrap();
+        for (x, y, c) in [(-1, -1, 'A'), (-2, 1, 'B'), (5, -1, 'C'), (1, -2, 'D'), (-1, 4, 'E'), (4, 4, 'F'), (3, 1, 'G')] {
+            code.insert(vec![x, y], c as isize);
+        }
+        for left in -4..=7 {
+            for right in left..=7 {
+                for top in -4..=7 {
+                    for bottom in top..=7 {
+                        let rect = Rect::new(left, right, top, bottom);
+                        let cells = (top..bottom).map(|y| (left..right).map(|x| code.get(&[x, y]).unwrap())
+                            .collect::<Vec<_>>()).collect::<Vec<_>>();
+                        assert_eq!(code.region(&rect), cells, "{:?}", rect);
+                        assert_eq!(code.get_string(rect.clone()), cells.into_iter().map(cells_to_string).collect::<Vec<_>>(),
+                                   "{:?}", rect);
+                    }
+                }
+            }
+        }
+        assert_eq!(code.get_string(Rect::new(-2, 6, -2, 5)), ["   D    ", " A     C", "  abc   ", "B de G  ", "  fghi  ",
+                                                               "        ", " E    F "]);
+    }
+
+    #[test]
+    fn parse_number_stops_before_the_cell_overflows() {
+        let nines = "9".repeat(100);
+        assert_eq!(parse_number::<i8>(&nines), Some((99, &nines[2..])));
+        assert_eq!(parse_number::<i32>(&nines), Some((999_999_999, &nines[9..])));
+        assert_eq!(parse_number::<i64>(&nines), Some((999_999_999_999_999_999, &nines[18..])));
+        assert_eq!(parse_number::<i128>(&nines), Some(("9".repeat(38).parse().unwrap(), &nines[38..])));
+        let long = format!("1{}", "0".repeat(99));
+        assert_eq!(parse_number::<i64>(&long), Some((1_000_000_000_000_000_000, &long[19..])));
+    }
+
+    #[test]
+    fn parse_number_reads_negative_numbers() {
+        let nines = format!("-{}", "9".repeat(100));
+        assert_eq!(parse_number::<i8>(&nines), Some((-99, &nines[3..])));
+        assert_eq!(parse_number::<i32>(&nines), Some((-999_999_999, &nines[10..])));
+        assert_eq!(parse_number::<i8>("-128"), Some((-128, "")));
+        assert_eq!(parse_number::<i8>("128"), Some((12, "8")));
+        assert_eq!(parse_number::<i8>("-129"), Some((-12, "9")));
+        assert_eq!(parse_number::<i32>("-2147483648 x"), Some((i32::MIN, " x")));
+        assert_eq!(parse_number::<i64>("x -42y"), Some((-42, "y")));
+        assert_eq!(parse_number::<i64>("-x42"), Some((42, "")));
+    }
+
+    #[test]
+    fn parse_number_needs_a_digit() {
+        assert_eq!(parse_number::<i64>(""), None);
+        assert_eq!(parse_number::<i64>("- abc"), None);
+    }
+
+    #[test]
+    fn input_keeps_the_digits_that_do_not_fit() {
+        let mut funge = quiet(Funge::<i32>::new("&.&.@").unwrap()
+            .with_input(IO::new().with_store(vec!["-12345678901234567890 x\n".to_string()])));
+        while funge.step_or_stop().unwrap().is_none() {}
+        assert_eq!(funge.output.get(), "-1234567890 1234567890 ");
+    }
+
+    #[test]
+    fn arithmetic_wraps_around() {
+        let run = |code: &str| output(quiet(Funge::<i8>::new(code).unwrap()));
+        assert_eq!(run("ff*.@"), "-31 ");
+        assert_eq!(run("88*2*.@"), "-128 ");
+        assert_eq!(run("88*2*1-.@"), "127 ");
+        assert_eq!(run("88*2*:+.@"), "0 ");
+    }
+
+    // runs a one line program until its last instruction ran, and returns the stack and the delta of the IP then
+    fn last_op(funge: Funge<isize>) -> (Vec<isize>, Vec<isize>) {
+        let mut funge = quiet(funge);
+        let end = funge.code.orig_rect.width() - 1;
+        for _ in 0..10_000 {
+            let at_end = funge.ips[0].position[0] == end;
+            assert!(funge.step_or_stop().unwrap().is_none(), "the funge quit");
+            if at_end {
+                return (funge.ips[0].stack(), funge.ips[0].delta.clone())
+            }
+        }
+        panic!("the IP did not reach the end")
+    }
+
+    // the code runs after the fingerprint is loaded, its id and the 1 ( pushes are dropped
+    fn with_fingerprint(name: &str, code: &str) -> Funge<isize> {
+        Funge::new(format!("\"{}\"4($${}", name.chars().rev().collect::<String>(), code)).unwrap()
+    }
+
+    // the letters of a fingerprint reflect before it is loaded and once it is unloaded again
+    fn reflects_unloaded(name: &str, ops: &str) {
+        for op in ops.chars() {
+            assert_eq!(last_op(Funge::new(format!("1{}", op)).unwrap()), (vec![1], vec![-1, 0]), "{} before", op);
+            let unloaded = format!("\"{}\"4)1{}", name.chars().rev().collect::<String>(), op);
+            assert_eq!(last_op(with_fingerprint(name, &unloaded)), (vec![1], vec![-1, 0]), "{} after", op);
+        }
+    }
+
+    // a directory of its own for each test that touches files, empty at the start
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rusty_funge_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn fingerprints_are_built_once_for_each_type_of_cell() {
+        let roma = fingerprint::get::<isize>(fingerprint::id("ROMA")).unwrap();
+        assert!(std::ptr::eq(roma, fingerprint::get::<isize>(fingerprint::id("ROMA")).unwrap()));
+        assert_eq!(fingerprint::get::<i8>(fingerprint::id("ROMA")).unwrap().name, "ROMA");
+        assert!(fingerprint::get::<isize>(fingerprint::id("ROMB")).is_none());
+        assert_eq!(output(quiet(Funge::<i32>::new("\"AMOR\"4(MCM++.@").unwrap())), "2100 ");
+    }
+
+    #[test]
+    fn io_takes_closures_that_keep_what_they_use() {
+        let written = Arc::new(Mutex::new(String::new()));
+        let sink = written.clone();
+        let lines = Arc::new(Mutex::new(vec!["5\n".to_string(), "7\n".to_string()]));
+        let funge = Funge::<isize>::new("&&+.@").unwrap()
+            .with_input(IO::new().with_input(move |_| lines.lock().unwrap().pop().ok_or(Error::msg("no more lines"))))
+            .with_output(IO::new().with_output(move |_, s| {
+                sink.lock().unwrap().push_str(&s);
+                Ok(())
+            }));
+        assert_eq!(funge.run_exit_code().unwrap(), 0);
+        assert_eq!(*written.lock().unwrap(), "12 ");
+    }
+
+    #[test]
+    fn cross_check_reads_each_line_once() {
+        let lines = Arc::new(Mutex::new(vec!["5\n".to_string(), "7 9\n".to_string()]));
+        let left = lines.clone();
+        let written = Arc::new(Mutex::new(String::new()));
+        let sink = written.clone();
+        let funge = Funge::<isize>::new("&&+.&.@").unwrap()
+            .with_input(IO::new().with_input(move |_| lines.lock().unwrap().pop().ok_or(Error::msg("no more lines"))))
+            .with_output(IO::new().with_output(move |_, s| {
+                sink.lock().unwrap().push_str(&s);
+                Ok(())
+            }));
+        assert_eq!(funge.cross_check(1).unwrap(), 0);
+        assert!(left.lock().unwrap().is_empty());
+        assert_eq!(*written.lock().unwrap(), "16 5 ");
+    }
+
+    #[test]
+    fn file_reflects_when_writing_too_much_at_once() {
+        let dir = scratch_dir("file_cap");
+        let mut funge = Funge::new("\"ELIF\"4($$01 1 0\"txt.w\"O 88*:*:*W").unwrap();
+        funge.working_dir = dir.clone();
+        assert_eq!(last_op(funge), (vec![1], vec![-1, 0]));
+        assert_eq!(std::fs::read_to_string(dir.join("w.txt")).unwrap(), "");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_handles_read_and_write() {
+        let dir = scratch_dir("file");
+        let mut funge = Funge::<isize>::new(concat!("\"ELIF\"4($$01 4 0\"txt.t\"O 0\"olleh\"P 00S G.,,,,,$ L. ",
+            "00S 3R 01g,11g,21g, 20S 3W 00S G.,,,,,,,,$ C@")).unwrap();
+        funge.working_dir = dir.clone();
+        assert_eq!(output(quiet(funge)), "5 hello5 hel8 hellohel");
+        assert_eq!(std::fs::read_to_string(dir.join("t.txt")).unwrap(), "hellohel");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_is_sandboxed_like_i_and_o() {
+        let dir = scratch_dir("file_sandbox");
+        std::fs::write(dir.join("r.txt"), "read").unwrap();
+        let open = |mode: char, name: &str, disabled: &str| {
+            let code = format!("\"ELIF\"4($$01 {} 0\"{}\"O", mode, name.chars().rev().collect::<String>());
+            let mut funge = Funge::new(code).unwrap().without_instructions(disabled);
+            funge.working_dir = dir.clone();
+            last_op(funge)
+        };
+        assert_eq!(open('0', "r.txt", "o"), (vec![1], vec![1, 0]));
+        assert_eq!(open('0', "r.txt", "i"), (vec![], vec![-1, 0]));
+        assert_eq!(open('1', "w.txt", "o"), (vec![], vec![-1, 0]));
+        assert_eq!(open('3', "r.txt", "o"), (vec![], vec![-1, 0]));
+        assert_eq!(open('0', "r.txt", "=io"), (vec![], vec![-1, 0]));
+        assert!(!dir.join("w.txt").exists());
+        let delete = |disabled: &str| {
+            let mut funge = with_fingerprint("FILE", "0\"txt.r\"D").without_instructions(disabled);
+            funge.working_dir = dir.clone();
+            last_op(funge)
+        };
+        assert_eq!(delete("o"), (vec![], vec![-1, 0]));
+        assert!(dir.join("r.txt").exists());
+        assert_eq!(delete(""), (vec![], vec![1, 0]));
+        assert!(!dir.join("r.txt").exists());
+        reflects_unloaded("FILE", "CDGLOPRSW");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_only_notes_what_a_dry_run_would_do() {
+        let dir = scratch_dir("file_dry");
+        let mut funge = Funge::<isize>::new("\"ELIF\"4($$01 1 0\"txt.d\"O@").unwrap();
+        funge.working_dir = dir.clone();
+        let mut funge = quiet(funge);
+        while funge.ips[0].position[0] != 23 {
+            funge.step_or_stop().unwrap();
+        }
+        let prediction = funge.predict(0).unwrap();
+        assert_eq!(prediction.effects, vec![Effect::WriteFile("d.txt".to_string())]);
+        assert_eq!(prediction.position, Some(vec![22, 0]));
+        let (_, effects) = funge.dry_step().unwrap();
+        assert_eq!(effects, vec![Effect::WriteFile("d.txt".to_string())]);
+        assert!(!dir.join("d.txt").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn nested_funges_share_the_steps() {
+        let dir = scratch_dir("nested_steps");
+        std::fs::write(dir.join("c.bf"), "123@").unwrap();
+        std::fs::write(dir.join("loop.bf"), ">").unwrap();
+        let funge = |code: &str, max_steps: Option<usize>| {
+            let mut funge = quiet(Funge::<isize>::new(code).unwrap())
+                .with_execute(Execute::Funge)
+                .with_limits(Limits { max_steps, ..Limits::new() });
+            funge.working_dir = dir.clone();
+            funge.run()
+        };
+        let outcome = funge("0\"fb.c\"=$@", None);
+        assert_eq!((outcome.exit_code, outcome.steps), (Some(0), 10 + 4));
+        let outcome = funge("0\"fb.c\"=$0\"fb.c\"=$@", None);
+        assert_eq!((outcome.exit_code, outcome.steps), (Some(0), 19 + 2 * 4));
+        let outcome = funge("0\"fb.pool\"=$@", Some(100));
+        assert_eq!((outcome.reason, outcome.steps), (StopReason::StepLimit, 100));
+        let outcome = funge(">0\"fb.c\"=$", Some(100));
+        assert_eq!((outcome.reason, outcome.steps), (StopReason::StepLimit, 100));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn the_ip_limit_stops_the_funge() {
+        let run = |funge: Funge<isize>| quiet(funge).run().reason;
+        let limited = |max_ips| Funge::new("t@").unwrap().with_limits(Limits { max_ips: Some(max_ips), ..Limits::new() });
+        assert_eq!(limited(1).rules().max_ips(), MAX_IPS);
+        assert_eq!(run(limited(1)), StopReason::Limit("More than 1 IPs.".to_string()));
+        assert_eq!(run(limited(2)), StopReason::Halted);
+        let capped = limited(2).with_max_ips(1, OnIpCap::Reflect);
+        assert_eq!(capped.limits().max_ips, Some(2));
+        assert_eq!(run(capped), StopReason::Halted);
+        assert_eq!(run(limited(2).with_max_ips(1, OnIpCap::Error)), StopReason::Limit("More than 1 IPs.".to_string()));
+    }
+
+    #[test]
+    fn null_masks_the_fingerprints_loaded_before() {
+        let roma = |code: &str| last_op(with_fingerprint("ROMA", code));
+        assert_eq!(roma("I"), (vec![1], vec![1, 0]));
+        assert_eq!(roma("\"LLUN\"4($$2I"), (vec![2], vec![-1, 0]));
+        assert_eq!(roma("\"LLUN\"4($$\"LLUN\"4)I"), (vec![1], vec![1, 0]));
+        assert_eq!(last_op(with_fingerprint("NULL", "2Z")), (vec![2], vec![-1, 0]));
+        reflects_unloaded("NULL", "AZ");
+    }
+
+    #[test]
+    fn roma_pushes_the_values_of_the_numerals() {
+        assert_eq!(last_op(with_fingerprint("ROMA", "IVXLCDM")), (vec![1, 5, 10, 50, 100, 500, 1000], vec![1, 0]));
+        reflects_unloaded("ROMA", "CDILMVX");
+    }
+
+    #[test]
+    fn modu_takes_the_sign_of_the_divisor_none_or_the_dividend() {
+        let modu = |code: &str| last_op(with_fingerprint("MODU", code)).0;
+        assert_eq!(modu("07-3M 07-3U 07-3R"), vec![2, 1, -1]);
+        assert_eq!(modu("703-M 703-U 703-R"), vec![-2, 1, 1]);
+        assert_eq!(modu("70M 70U 70R"), vec![0, 0, 0]);
+        reflects_unloaded("MODU", "MUR");
+    }
+
+    #[test]
+    fn mode_changes_pushing_popping_and_turning() {
+        let mode = |code: &str| last_op(with_fingerprint("MODE", code));
+        assert_eq!(mode("123Q$"), (vec![2, 3], vec![1, 0]));
+        assert_eq!(mode("12I3"), (vec![3, 1, 2], vec![1, 0]));
+        assert_eq!(mode("12IQ3$"), (vec![1, 2], vec![1, 0]));
+        assert_eq!(mode("123QQ$"), (vec![1, 2], vec![1, 0]));
+        assert_eq!(mode("H>"), (vec![], vec![2, 0]));
+        assert_eq!(mode("HH>"), (vec![], vec![1, 0]));
+        assert_eq!(mode("H<"), (vec![], vec![0, 0]));
+        // [ turns into ] once it ran, the IP turned north and wrapped onto the @ below
+        for (code, turned) in [("S[", ']'), ("SS[", '[')] {
+            let mut funge = quiet(with_fingerprint("MODE", &format!("{}\n{:>2$}", code, "@", code.len() + 10)));
+            while funge.step_or_stop().unwrap().is_none() {}
+            assert_eq!(funge.code[&vec![code.len() as isize + 9, 0]], turned as isize);
+        }
+        reflects_unloaded("MODE", "HIQS");
+    }
+
+    #[test]
+    fn orth_does_bits_cells_and_moves_with_x_first() {
+        let orth = |code: &str| last_op(with_fingerprint("ORTH", code));
+        assert_eq!(orth("65A 65O 65E"), (vec![4, 7, 3], vec![1, 0]));
+        assert_eq!(orth("88*1+13P 13G 31G"), (vec![65, 32], vec![1, 0]));
+        assert_eq!(orth("2V"), (vec![], vec![2, 0]));
+        assert_eq!(orth("1W"), (vec![], vec![1, 1]));
+        assert_eq!(orth("0Z12"), (vec![2], vec![1, 0]));
+        assert_eq!(orth("1Z12"), (vec![1, 2], vec![1, 0]));
+        let orth = |code: &str| output(quiet(with_fingerprint("ORTH", code)));
+        assert_eq!(orth("0\"ih\"S@"), "hi");
+        assert_eq!(orth("7dX@.@"), "7 ");
+        reflects_unloaded("ORTH", "AOEGPVWXYSZ");
+    }
+
+    #[test]
+    fn refc_gives_the_same_vector_the_same_reference() {
+        let refc = |code: &str| last_op(with_fingerprint("REFC", code));
+        assert_eq!(refc("12R 34R 12R"), (vec![0, 1, 0], vec![1, 0]));
+        assert_eq!(refc("12R34R$D"), (vec![1, 2], vec![1, 0]));
+        assert_eq!(refc("12R$1D"), (vec![], vec![-1, 0]));
+        reflects_unloaded("REFC", "RD");
+    }
+
+    #[test]
+    fn strn_works_on_strings_on_the_stack_and_in_funge_space() {
+        let strn = |code: &str| output(quiet(with_fingerprint("STRN", code))
+            .with_input(IO::new().with_store(vec!["line\r\n".to_string()])));
+        assert_eq!(strn("0\"a\"0\"b\"AD@"), "ba");
+        assert_eq!(strn("0\"a\"0\"b\"C. 0\"b\"0\"a\"C. 0\"a\"0\"a\"C.@"), "1 -1 0 ");
+        assert_eq!(strn("0\"c\"0\"dcba\"FD 0\"x\"0\"dcba\"FN.@"), "cd0 ");
+        assert_eq!(strn("0\"olleh\"01P 01GD@"), "hello");
+        assert_eq!(strn("ID@"), "line");
+        assert_eq!(strn("0\"olleh\"2LD 0\"olleh\"3RD 0\"olleh\"13MD@"), "helloell");
+        assert_eq!(strn("0\"olleh\"N.D@"), "5 hello");
+        assert_eq!(strn("f9*SD 0\"x24 \"V. 0\"7-\"V. 0\"x\"V.@"), "13542 -7 0 ");
+        reflects_unloaded("STRN", "ACDFGILMNPRSV");
+    }
+
+    #[test]
+    fn fixp_works_in_degrees_times_10000() {
+        let fixp = |code: &str| last_op(with_fingerprint("FIXP", code));
+        assert_eq!(fixp("65A 65O 65X").0, vec![4, 7, 3]);
+        assert_eq!(fixp("0C 9aaaaa*****I 0T 0J").0, vec![10000, 10000, 0, 0]);
+        assert_eq!(fixp("0B 9aaaaa*****C 1aaaa****U").0, vec![900000, 0, 450000]);
+        assert_eq!(fixp("2P 9Q 5N 05-S 0S 05-V 23R").0, vec![6, 3, -5, -1, 0, 5, 8]);
+        assert_eq!(fixp("0D 1D").0, vec![0, 0]);
+        assert_eq!(fixp("7 01-Q"), (vec![7], vec![-1, 0]));
+        assert_eq!(fixp("7 02R"), (vec![7, 0], vec![1, 0]));
+        assert_eq!(fixp("7 001-R"), (vec![7], vec![-1, 0]));
+        reflects_unloaded("FIXP", "ABCDIJNOPQRSTUVX");
+    }
+
+    #[test]
+    fn fpsp_and_fpdp_keep_floats_in_cells() {
+        for name in ["FPSP", "FPDP"] {
+            let float = |code: &str| output(quiet(with_fingerprint(name, code)));
+            assert_eq!(float("3F4FAP 7F2FDP 3F4FSP 3F4FMP 2F3FYP@"), "7 3.5 -1 12 8 ");
+            assert_eq!(float("0\"5.2\"RP 9FQI. 2FNI. 2FNVI. 0FXI. 1FKI. 1FLI. 0FBI. 0FCI.@"), "2.5 3 -2 2 1 0 0 0 1 ");
+            assert_eq!(float("0FTI. 0FEI. 1FHI. 0FGI. 7F2FDI.@"), "0 0 0 0 3 ");
+            let float = |code: &str| last_op(with_fingerprint(name, code));
+            assert_eq!(float("7 01-FQI"), (vec![7], vec![-1, 0]));
+            assert_eq!(float("7 0\"x\"R"), (vec![7], vec![-1, 0]));
+            reflects_unloaded(name, "ABCDEFGHIKLMNPQRSTVXY");
+        }
+        assert_eq!(last_op(with_fingerprint("FPSP", "1F")).0, vec![0x3f800000]);
+        assert_eq!(last_op(with_fingerprint("FPDP", "1F")).0, vec![0x3ff00000, 0]);
+        assert_eq!(last_op(with_fingerprint("FPDP", "01-F")).0, vec![0xbff00000_u32 as i32 as isize, 0]);
+    }
+
+    #[test]
+    fn long_works_on_integers_of_two_cells() {
+        let long = |code: &str| last_op(with_fingerprint("LONG", code));
+        assert_eq!(long("5E 05-E 1E88*L"), (vec![0, 5, -1, -5, 1, 0], vec![1, 0]));
+        assert_eq!(long("7 0\"x\"Z"), (vec![7], vec![-1, 0]));
+        let long = |code: &str| output(quiet(with_fingerprint("LONG", code)));
+        assert_eq!(long("5E7EAP 5E7ESP 5E7EMP 7E2EDP 7E2EOP 7E0EDP 7E0EOP@"), "12 -2 35 3 1 0 0 ");
+        assert_eq!(long("5ENP 5ENBP 1E8LP 1E88*L88*RP 0\"21-\"ZP@"), "-5 5 256 1 -12 ");
+        assert_eq!(long("1E88*2*1-LP@"), format!("{} ", i128::MIN));
+        assert_eq!(long("0\"987654321098765432109876543210987654321\"ZP@"), "123456789012345678901234567890123456789 ");
+        reflects_unloaded("LONG", "ABDELMNOPRSZ");
+    }
+
+    #[test]
+    fn bool_works_on_the_bits() {
+        assert_eq!(last_op(with_fingerprint("BOOL", "65A 65O 65X 5N 0N")), (vec![4, 7, 3, -6, -1], vec![1, 0]));
+        reflects_unloaded("BOOL", "AONX");
+    }
+
+    #[test]
+    fn hrti_times_in_steps_when_deterministic() {
+        let hrti = |code: &str| last_op(with_fingerprint("HRTI", code).deterministic());
+        assert_eq!(hrti("G"), (vec![1], vec![1, 0]));
+        assert_eq!(hrti("M123T"), (vec![1, 2, 3, 4], vec![1, 0]));
+        assert_eq!(hrti("M1T2T"), (vec![1, 2, 2, 4], vec![1, 0]));
+        assert_eq!(hrti("S"), (vec![10], vec![1, 0]));
+        assert_eq!(hrti("1T"), (vec![1], vec![-1, 0]));
+        assert_eq!(hrti("ME1T"), (vec![1], vec![-1, 0]));
+        let timer = |code: &str| last_op(with_fingerprint("HRTI", code).with_timer(|_| Duration::from_micros(2_000_042)));
+        assert_eq!(timer("MTS"), (vec![0, 42], vec![1, 0]));
+        reflects_unloaded("HRTI", "EGMST");
+    }
+
+    #[test]
+    fn evar_reads_and_changes_the_environment_of_the_funge() {
+        let env = vec![("A".to_string(), "1".to_string()), ("B".to_string(), "x".to_string())];
+        // STRN below EVAR, for D
+        let evar = |code: &str| output(quiet(with_fingerprint("STRN", &format!("\"RAVE\"4($${}", code))
+            .with_env(env.clone())));
+        assert_eq!(evar("N. 0\"A\"GD 0\"B\"GD 0\"Z\"G.@"), "2 1x0 ");
+        assert_eq!(evar("0VD 1VD@"), "A=1B=x");
+        assert_eq!(evar("0\"3=A\"P 0\"EGNUF_YTSUR=C\"P N. 0\"A\"GD 0\"C\"GD 2VD@"), "3 3RUSTY_FUNGEC=RUSTY_FUNGE");
+        assert!(std::env::var("C").map_or(true, |c| c != "RUSTY_FUNGE"));
+        let evar = |code: &str| last_op(with_fingerprint("EVAR", code).with_env(env.clone()));
+        assert_eq!(evar("7 2V"), (vec![7], vec![-1, 0]));
+        assert_eq!(evar("7 0\"A\"P"), (vec![7], vec![-1, 0]));
+        reflects_unloaded("EVAR", "GNPV");
+    }
+
+    #[test]
+    fn subr_calls_and_returns_with_the_arguments_moved() {
+        let subr = |code: &str| output(quiet(with_fingerprint("SUBR", code)));
+        // the return address and delta go below the 2 arguments
+        assert_eq!(subr("56012C@\n......@"), "6 5 0 1 0 15 ");
+        // R takes them away again and continues after C with the values returned
+        assert_eq!(subr("56012C..@\n2R"), "6 5 ");
+        assert_eq!(subr("56012C.@\n+1R"), "11 ");
+        assert_eq!(subr("56012C.@\n$$0R"), "0 ");
+        assert_eq!(subr("7 01J@\n.@"), "7 ");
+        // relative to the storage offset that { sets at 13, 0, also the return address
+        assert_eq!(subr("O0{56012C..@\n             2R"), "6 5 ");
+        assert_eq!(subr("O0{56012C@\n             ......@"), "6 5 0 1 0 5 ");
+        assert_eq!(subr("OA56012C..@\n2R"), "6 5 ");
+        reflects_unloaded("SUBR", "ACJOR");
+    }
+
+    #[test]
+    fn term_writes_escape_sequences() {
+        let term = |code: &str| output(quiet(with_fingerprint("TERM", code)));
+        assert_eq!(term("C H L S@"), "\x1b[2J\x1b[H\x1b[H\x1b[K\x1b[J");
+        assert_eq!(term("3D 3U 03-D 0D@"), "\x1b[3B\x1b[3A\x1b[3A");
+        assert_eq!(term("42G 01-0G@"), "\x1b[3;5H\x1b[1;1H");
+        assert_eq!(term("1F 7B 8F@"), "\x1b[31m\x1b[47m\x1b[0m");
+        reflects_unloaded("TERM", "BCDFGHLSU");
+    }
+
+    // the port is free when the funge binds it, unless another process takes it in between
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn sock_connects_to_a_server() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            let mut bytes = [0; 2];
+            stream.read_exact(&mut bytes).unwrap();
+            stream.write_all(b"ok").unwrap();
+            bytes
+        });
+        let funge = with_fingerprint("SOCK", "221S. 2&0\"1.0.0.721\"I1C 151O 0121W. 0251R. 02g,12g, 1K@\nhi")
+            .with_input(IO::new().with_store(vec![format!("{}\n", port)]));
+        assert_eq!(output(quiet(funge)), "1 2 2 ok");
+        assert_eq!(&server.join().unwrap(), b"hi");
+    }
+
+    #[test]
+    fn sock_serves_a_client() {
+        let port = free_port();
+        let client = std::thread::spawn(move || {
+            let mut stream = (0..100).find_map(|_| std::net::TcpStream::connect(("127.0.0.1", port)).ok().or_else(|| {
+                std::thread::sleep(Duration::from_millis(10));
+                None
+            })).unwrap();
+            stream.write_all(b"ok").unwrap();
+            let mut bytes = [0; 2];
+            stream.read_exact(&mut bytes).unwrap();
+            bytes
+        });
+        let funge = with_fingerprint("SOCK", "221S. 2&0\"1.0.0.721\"I1B 51L 1A.$$ 0252R. 0122W. 02g,12g, 2K1K@\nhi")
+            .with_input(IO::new().with_store(vec![format!("{}\n", port)]));
+        assert_eq!(output(quiet(funge)), "1 2 2 2 ok");
+        assert_eq!(&client.join().unwrap(), b"hi");
+    }
+
+    #[test]
+    fn sock_reflects_without_a_socket_or_the_network() {
+        let sock = |code: &str| last_op(with_fingerprint("SOCK", code));
+        assert_eq!(sock("0\"1.0.0.721\"I"), (vec![0x7f000001], vec![1, 0]));
+        assert_eq!(sock("7 0\"x\"I"), (vec![7], vec![-1, 0]));
+        assert_eq!(sock("221S$ 1K 7 1K"), (vec![7], vec![-1, 0]));
+        assert_eq!(sock("7 0151W"), (vec![7], vec![-1, 0]));
+        assert_eq!(sock("221S 0151W"), (vec![1], vec![-1, 0]));
+        assert_eq!(last_op(Funge::new("\"KCOS\"4(").unwrap()).1, vec![1, 0]);
+        assert_eq!(last_op(Funge::new("\"KCOS\"4(").unwrap().without_network()), (vec![], vec![-1, 0]));
+        let mut funge = quiet(with_fingerprint("SOCK", "221S@"));
+        while funge.ips[0].position[0] != 13 {
+            funge.step_or_stop().unwrap();
+        }
+        let (funge, effects) = funge.dry_step().unwrap();
+        assert_eq!(effects, vec![Effect::Network('S')]);
+        assert_eq!((funge.ips[0].stack(), funge.ips[0].delta.clone()), (vec![2, 2, 1], vec![-1, 0]));
+        reflects_unloaded("SOCK", "ABCIKLORSW");
+    }
+
+    #[test]
+    fn scke_looks_up_hosts_and_peeks() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            stream.write_all(b"xy").unwrap();
+            stream.read_to_end(&mut Vec::new()).unwrap();
+        });
+        // SOCK below SCKE, for the socket
+        let code = "\"EKCS\"4($$221S$ 2&0\"1.0.0.721\"I1C 1P. 0111W$ 0211R$ 1P. 0211R$ 1P. 1K@\nh";
+        let funge = with_fingerprint("SOCK", code).with_input(IO::new().with_store(vec![format!("{}\n", port)]));
+        assert_eq!(output(quiet(funge)), "0 1 0 ");
+        server.join().unwrap();
+        let scke = |code: &str| last_op(with_fingerprint("SCKE", code));
+        assert_eq!(scke("0\"1.0.0.721\"H 0\"tsohlacol\"H"), (vec![0x7f000001, 0x7f000001], vec![1, 0]));
+        assert_eq!(scke("7 0H"), (vec![7], vec![-1, 0]));
+        assert_eq!(scke("7 1P"), (vec![7], vec![-1, 0]));
+        assert_eq!(last_op(Funge::new("\"EKCS\"4(").unwrap().without_network()), (vec![], vec![-1, 0]));
+        reflects_unloaded("SCKE", "HP");
+    }
+
+    #[test]
+    fn dirf_changes_the_directory_of_the_funge() {
+        let dir = scratch_dir("dirf");
+        let cwd = std::env::current_dir().unwrap();
+        let dirf = |code: &str, disabled: &str| {
+            let mut funge = with_fingerprint("DIRF", code).without_instructions(disabled);
+            funge.working_dir = dir.clone();
+            funge
+        };
+        let mut funge = quiet(dirf("0\"d\"M 0\"d\"C@", ""));
+        while funge.step_or_stop().unwrap().is_none() {}
+        assert_eq!(funge.working_dir, dir.join("d").canonicalize().unwrap());
+        assert_eq!(std::env::current_dir().unwrap(), cwd);
+        assert_eq!(last_op(dirf("0\"d\"R", "o")), (vec![], vec![-1, 0]));
+        assert_eq!(last_op(dirf("0\"d\"R", "")), (vec![], vec![1, 0]));
+        assert!(!dir.join("d").exists());
+        assert_eq!(last_op(dirf("0\"e\"M", "o")), (vec![], vec![-1, 0]));
+        assert!(!dir.join("e").exists());
+        std::fs::write(dir.join("f"), "").unwrap();
+        assert_eq!(last_op(dirf("0\"f\"C", "")), (vec![], vec![-1, 0]));
+        assert_eq!(last_op(dirf("0\".\"C", "i")), (vec![], vec![-1, 0]));
+        assert_eq!(last_op(dirf("0\"g\"C", "")), (vec![], vec![-1, 0]));
+        reflects_unloaded("DIRF", "CMR");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
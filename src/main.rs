@@ -1,66 +1,504 @@
+mod bundle;
+mod clipboard;
 mod debug;
+mod playback;
+mod script;
+mod terminal;
+mod transport;
+mod tutorial;
 
-use anyhow::Result;
-use clap::Parser;
-use rusty_funge::Funge;
-use debug::FungeView;
+use std::{env, fs::File, io, io::{BufWriter, Write}, path::PathBuf, thread, time::Duration};
+use anyhow::{Error, Result};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use rusty_funge::{Argument, ExitStatus, Funge, Int, IO, RunOutcome, RunStatus, SpaceStats, StopReason, Execute, IpName, Limits, OnIpCap, gallery, LineEndings, NumberFormat, OnUnderflow, Rect, Rules, Schedule, Validation, Wrap, ops, slice, convert::{Printable, Substitute}, trace::{self, TraceFilter, Tracer}};
+use debug::{FungeView, Speed, parse_stack_cells};
 
 
 #[derive(Parser)]
-#[command(version)]
+#[command(version, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(id = "funge code file")]
-    input: String,
+    #[command(subcommand)]
+    command: Option<Commands>,
+    #[arg(id = "funge code file", required = true)]
+    input: Option<String>,
     #[arg(help = "debug, step on key press or steps / second",
           short, long, value_name = "interval", num_args = 0..=1)]
     debug: Option<Option<f64>>,
+    #[arg(help = "in the debugger, show cells that are not printable as this character or as hex escapes (hex)",
+          long, value_name = "char|hex")]
+    unprintable: Option<Substitute>,
+    #[arg(help = "in the debugger, the code points shown as themselves [default: 32-126,161-255]", long,
+          value_name = "start-end,...")]
+    printable: Option<String>,
+    #[arg(help = "show the program running without the debugger, redrawing with plain ansi codes",
+          long, value_name = "steps / second", conflicts_with = "debug")]
+    watch: Option<f64>,
     #[arg(help = "number of bits in cell and funge values", short, long)]
     bits: Option<u8>,
     #[arg(help = "skip steps", short, long)]
     steps: Option<usize>,
     #[arg(help = "befunge version (93, 97, 98)", short = 'B', long)]
     befunge: Option<String>,
+    #[arg(help = "read input from stdin and write only program output to stdout, byte for byte",
+          long, conflicts_with_all = ["debug", "arguments to the funge (& or ~)"])]
+    filter: bool,
+    #[arg(help = "connect the input and output of the program to a server (tcp:HOST:PORT), wait for one client \
+    (tcp-listen:HOST:PORT) or use named pipes, created when missing (fifo:IN:OUT)", long, value_name = "transport",
+          conflicts_with_all = ["debug", "watch", "filter", "script", "cross_check", "playback"])]
+    io: Option<String>,
+    #[arg(help = "check that the source is printable ASCII, B93 interpreters disagree about other characters (warn, strict)",
+          long, value_name = "mode")]
+    ascii: Option<Validation>,
+    #[arg(help = "what . prints after a number (spec: a space, bare: nothing, newline)", long, value_name = "format",
+          default_value = "spec")]
+    number_format: NumberFormat,
+    #[arg(help = "buffer output, flushing on newline, input and quit", long)]
+    buffered: bool,
+    #[arg(help = "print funge-space and per IP statistics as json to stderr when the program ends", long,
+          conflicts_with = "debug")]
+    mem_stats: bool,
+    #[arg(help = "show the steps done, steps per second and the time left until a limit on stderr while running", long,
+          conflicts_with_all = ["debug", "watch"])]
+    progress: bool,
+    #[arg(help = "how a run that does not halt exits: with 1 (simple), or with 70 when the interpreter fails and 71 \
+    when a limit is reached (distinct)", long, value_name = "mode", default_value = "simple", global = true)]
+    exit_status_mode: ExitStatus,
+    #[arg(help = "print warnings, like reflected unknown instructions, to stderr", long)]
+    warnings: bool,
+    #[arg(help = "print the active rules and exit", long)]
+    print_rules: bool,
+    #[arg(help = "fixed random seed, clock and environment, no = and no network", long)]
+    deterministic: bool,
+    #[arg(help = "no network, SOCK and SCKE do not load", long)]
+    no_network: bool,
+    #[arg(help = "push strings and runs of digits from the source in one step instead of cell by cell", long)]
+    cache_literals: bool,
+    #[arg(help = "run with and without the literal cache in lockstep, compare their states every so many steps \
+    and stop at the first difference, input is read up front", long, value_name = "steps", conflicts_with = "debug")]
+    cross_check: Option<usize>,
+    #[arg(help = "scrub through a recorded trace of this program", long, value_name = "trace file",
+          conflicts_with_all = ["debug", "trace"])]
+    playback: Option<String>,
+    #[arg(help = "run the debugger commands in this file without a screen, failing at the first assertion that does not hold",
+          long, value_name = "file", conflicts_with_all = ["debug", "watch", "playback", "cross_check"])]
+    script: Option<PathBuf>,
+    #[arg(help = "write a trace of every executed instruction to a file", long, value_name = "file")]
+    trace: Option<String>,
+    #[arg(help = "trace file format (json, binary)", long, value_name = "format", requires = "trace",
+          default_value = "json")]
+    trace_format: String,
+    #[arg(help = "only trace these instructions", long, value_name = "ops", requires = "trace")]
+    trace_ops: Option<String>,
+    #[arg(help = "only trace inside this region", long, value_name = "left,top,right,bottom", requires = "trace",
+          value_delimiter = ',')]
+    trace_region: Option<Vec<isize>>,
+    #[arg(help = "only trace these IPs", long, value_name = "id,id,...", requires = "trace", value_delimiter = ',')]
+    trace_ips: Option<Vec<usize>>,
+    #[arg(help = "only trace every nth step", long, value_name = "n", requires = "trace")]
+    trace_every: Option<usize>,
+    #[arg(help = "how the IP wraps around the edge of funge-space (lahey, torus, unbounded) [default: by version]",
+          long, value_name = "strategy")]
+    wrap: Option<Wrap>,
+    #[arg(help = "what = runs: a shell command, or the funge file it names in this interpreter (shell, funge)",
+          long, value_name = "paradigm")]
+    execute: Option<Execute>,
+    #[arg(help = "stop with an error when exceeding any of these limits", long,
+          value_name = "steps=n,time=seconds,cells=n,output=n,ips=n,stack=n")]
+    limits: Option<String>,
+    #[arg(help = "maximum number of IPs, t reflects when there are as many", long, value_name = "n")]
+    max_ips: Option<usize>,
+    #[arg(help = "stop with an error instead of reflecting t when there are max-ips IPs", long, requires = "max_ips")]
+    strict_max_ips: bool,
+    #[arg(help = "order in which IPs execute each step (roundrobin, reverse, random)", long, value_name = "policy")]
+    schedule: Option<Schedule>,
+    #[arg(help = "how the program and i split lines: normalize treats \\r\\n and \\r as line ends, preserve loads \\r as a cell",
+          long, value_name = "mode")]
+    line_endings: Option<LineEndings>,
+    #[arg(help = "what to do when popping an empty stack (count, warn, strict)", long, value_name = "mode")]
+    underflow: Option<OnUnderflow>,
+    #[arg(help = "annotations file for the debugger [default: <funge code file>.notes]", long, value_name = "file",
+          requires = "debug")]
+    annotations: Option<PathBuf>,
+    #[arg(help = "in the debugger, show this many cells from the top and bottom of each stack, 0 for all [default: 16,4]",
+          long, value_name = "top,bottom", requires = "debug")]
+    stack_cells: Option<String>,
+    #[arg(help = "start the debugger with auto speed: near slows down near the op to break on, written cells and output, \
+                  quiet only when there is output or cells are written", long, value_name = "policy", requires = "debug")]
+    auto_speed: Option<Speed>,
+    #[arg(help = "name an IP, shown in the debugger and traces", long, value_name = "id=name[:color]")]
+    name_ip: Vec<String>,
+    #[arg(help = "a number for &, read before other input, in order with --arg-str", long, value_name = "n",
+          allow_negative_numbers = true, conflicts_with_all = ["arguments to the funge (& or ~)", "filter"])]
+    arg_int: Vec<i128>,
+    #[arg(help = "a string for ~, read before other input, in order with --arg-int", long, value_name = "text",
+          conflicts_with_all = ["arguments to the funge (& or ~)", "filter"])]
+    arg_str: Vec<String>,
     #[arg(id = "arguments to the funge (& or ~)")]
     arguments: Vec<String>,
+    #[arg(id = "arguments the funge finds in y", last = true)]
+    argv: Vec<String>,
+}
+
+
+#[derive(Subcommand)]
+enum Commands {
+    #[command(about = "explain what an instruction does, or list all instructions of a version")]
+    Explain {
+        #[arg(help = "instruction(s) to explain")]
+        ops: Option<String>,
+        #[arg(help = "befunge version (93, 97, 98)", short = 'B', long)]
+        befunge: Option<String>
+    },
+    #[command(about = "list the built-in examples, or run one")]
+    Examples {
+        #[arg(help = "example to run")]
+        name: Option<String>,
+        #[arg(help = "open the example in the debugger", short, long, value_name = "interval", num_args = 0..=1)]
+        debug: Option<Option<f64>>,
+        #[arg(help = "arguments to the funge (& or ~)")]
+        arguments: Vec<String>
+    },
+    #[command(about = "learn Befunge step by step")]
+    Tutorial,
+    #[command(about = "write an executable that runs the program without the interpreter installed")]
+    Bundle {
+        program: PathBuf,
+        #[arg(help = "the executable to write", short, long)]
+        output: PathBuf,
+        #[arg(help = "befunge version (93, 97, 98)", short = 'B', long)]
+        befunge: Option<String>
+    },
+    #[command(about = "convert a trace between the json and binary formats")]
+    ConvertTrace {
+        input: PathBuf,
+        output: PathBuf
+    },
+    #[command(about = "show the steps that produced an output character, using an unfiltered trace")]
+    Slice {
+        trace: PathBuf,
+        #[arg(help = "index of the output character, starting at 0")]
+        index: usize
+    },
+    #[cfg(feature = "bench")]
+    #[command(about = "measure steps per second on standard workloads")]
+    Bench {
+        #[arg(help = "steps per workload", short, long, default_value_t = 100000)]
+        steps: usize
+    },
+}
+
+
+fn tracer(args: &Args) -> Result<Option<Tracer>> {
+    Ok(match &args.trace {
+        Some(file) => {
+            let mut filter = TraceFilter::new();
+            if let Some(ops) = &args.trace_ops {
+                filter = filter.with_ops(ops);
+            }
+            if let Some(r) = &args.trace_region {
+                if r.len() != 4 {
+                    Err(Error::msg("--trace-region needs four values: left,top,right,bottom"))?
+                }
+                filter = filter.with_region(Rect::new(r[0], r[2], r[1], r[3]));
+            }
+            if let Some(ips) = &args.trace_ips {
+                filter = filter.with_ips(ips.to_owned());
+            }
+            if let Some(every) = args.trace_every {
+                filter = filter.with_every(every);
+            }
+            let file = BufWriter::new(File::create(file)?);
+            let tracer = match &args.trace_format[..] {
+                "json" => Tracer::new(file),
+                "binary" => Tracer::binary(file),
+                format => Err(Error::msg(format!("Unknown trace format: {}", format)))?
+            };
+            Some(tracer.with_filter(filter))
+        }
+        None => None
+    })
+}
+
+
+// --arg-int and --arg-str in the order they were given
+fn typed_arguments(matches: &ArgMatches) -> Vec<Argument> {
+    let mut arguments: Vec<(usize, Argument)> = Vec::new();
+    if let (Some(indices), Some(values)) = (matches.indices_of("arg_int"), matches.get_many::<i128>("arg_int")) {
+        arguments.extend(indices.zip(values.map(|n| Argument::Int(*n))));
+    }
+    if let (Some(indices), Some(values)) = (matches.indices_of("arg_str"), matches.get_many::<String>("arg_str")) {
+        arguments.extend(indices.zip(values.map(|s| Argument::Str(s.to_owned()))));
+    }
+    arguments.sort_by_key(|(index, _)| *index);
+    arguments.into_iter().map(|(_, argument)| argument).collect()
+}
+
+
+fn report_unconsumed(outcome: &RunOutcome, _: &SpaceStats) {
+    if outcome.reason == StopReason::Halted && !outcome.unconsumed.is_empty() {
+        eprintln!("{} argument(s) not consumed: {}", outcome.unconsumed.len(),
+                  outcome.unconsumed.iter().map(|argument| argument.to_string()).collect::<Vec<_>>().join(" "));
+    }
+}
+
+
+fn ip_names(args: &Args) -> Result<Vec<(usize, IpName)>> {
+    args.name_ip.iter().map(|arg| {
+        arg.split_once('=')
+            .and_then(|(id, name)| Some((id.trim().parse().ok()?, IpName::parse(name)?)))
+            .ok_or(Error::msg(format!("Cannot parse IP name: {}, expected id=name[:color]", arg)))
+    }).collect()
+}
+
+
+#[cfg(feature = "bench")]
+fn bench(steps: usize) -> Result<()> {
+    for workload in rusty_funge::bench::WORKLOADS {
+        println!("{}", workload.run::<isize>(steps)?);
+    }
+    Ok(())
+}
+
+
+fn examples(name: Option<String>, debug: Option<Option<f64>>, arguments: Vec<String>) -> Result<i32> {
+    match name {
+        None => {
+            for example in gallery::EXAMPLES {
+                println!("{:<12} {}", example.name, example.description);
+            }
+        }
+        Some(name) => {
+            let example = gallery::get(&name).ok_or(Error::msg(format!("Unknown example: {}", name)))?;
+            let funge = Funge::<isize>::new(example.code())?;
+            match debug {
+                Some(interval) => FungeView::new(funge, arguments)?.debug(interval),
+                None => return funge.with_arguments(arguments).with_finalizer(report_unconsumed).run_exit_code()
+            }
+        }
+    }
+    Ok(0)
+}
+
+
+fn explain(ops: Option<String>, befunge: Option<String>) -> Result<()> {
+    let version = format!("B{}", befunge.unwrap_or("98".to_string()));
+    let docs = Rules::docs(&version)?;
+    match ops {
+        Some(ops) => for op in ops.chars() {
+            match docs.iter().find(|info| info.op == op) {
+                Some(info) => println!("{}\n", info),
+                None if ops::explain(op).is_some() => Err(Error::msg(format!("Not an instruction in {}: {}", version, op)))?,
+                None => Err(Error::msg(format!("Unknown instruction: {}", op)))?
+            }
+        }
+        None => for info in docs {
+            println!("{}  {:<24} {}", info.op, info.name, info.stack);
+        }
+    }
+    Ok(())
+}
+
+
+// redraw the whole screen after every step, for terminals where cursive does not work
+fn watch<I: Int>(mut funge: Funge<I>, rate: f64) -> Result<i32> {
+    let size = |name: &str, default: usize| env::var(name).ok().and_then(|n| n.parse().ok()).unwrap_or(default);
+    let (width, height) = (size("COLUMNS", 80), size("LINES", 24));
+    let interval = Duration::from_secs_f64(1.0 / rate.max(0.001));
+    loop {
+        print!("\x1b[2J\x1b[H{}", funge.render(width, height, true));
+        io::stdout().flush()?;
+        thread::sleep(interval);
+        funge = match funge.run_steps(1)? {
+            RunStatus::Paused(funge) => *funge,
+            RunStatus::Finished(code) => {
+                println!();
+                return Ok(code)
+            }
+        };
+    }
 }
 
 
 macro_rules! run {
-    ($a:expr, $i:ty) => {
-        let mut funge = Funge::<$i>::from_file(&$a.input)?;
-        if let Some(s) = $a.befunge {
-            funge = funge.with_version(format!("B{}", s))?;
+    ($a:expr, $m:expr, $i:ty) => {
+        let mut builder = Funge::<$i>::builder()
+            .with_file($a.input.as_ref().expect("clap requires an input file"))
+            .with_line_endings($a.line_endings.unwrap_or(LineEndings::Normalize))
+            .with_argv($a.input.iter().chain(&$a.argv).cloned().collect())
+            .with_number_format($a.number_format)
+            .with_typed_arguments(typed_arguments(&$m));
+        if let Some(s) = &$a.befunge {
+            builder = builder.with_version(format!("B{}", s));
+        }
+        if let Some(validation) = $a.ascii {
+            builder = builder.check_ascii(validation);
+        }
+        if let Some(wrap) = $a.wrap {
+            builder = builder.with_wrap(wrap);
+        }
+        if let Some(execute) = $a.execute {
+            builder = builder.with_execute(execute);
+        }
+        if let Some(limits) = &$a.limits {
+            builder = builder.with_limits(Limits::parse(limits)?);
+        }
+        if let Some(max_ips) = $a.max_ips {
+            builder = builder.with_max_ips(max_ips, if $a.strict_max_ips { OnIpCap::Error } else { OnIpCap::Reflect });
+        }
+        if let Some(schedule) = $a.schedule {
+            builder = builder.with_schedule(schedule);
+        }
+        if let Some(on_underflow) = $a.underflow {
+            builder = builder.with_underflow(on_underflow);
+        }
+        if $a.warnings || $a.underflow == Some(OnUnderflow::Warn) || $a.ascii == Some(Validation::Warn) {
+            builder = builder.with_diagnostics(IO::stderr());
+        }
+        // the environment of the process is left out of a deterministic run
+        if $a.deterministic {
+            builder = builder.deterministic();
+        } else {
+            builder = builder.with_env(env::vars().collect());
+        }
+        if $a.no_network {
+            builder = builder.without_network();
+        }
+        if $a.cache_literals {
+            builder = builder.with_literal_cache();
+        }
+        if $a.mem_stats {
+            builder = builder.with_finalizer(|_, stats| eprintln!("{}", stats.to_json()));
+        }
+        builder = builder.with_finalizer(report_unconsumed);
+        if $a.progress {
+            builder = builder.with_reporter(|progress| eprint!("\r\x1b[K{}", progress))
+                .with_finalizer(|_, _| eprint!("\r\x1b[K"));
+        }
+        for (id, name) in ip_names(&$a)? {
+            builder = builder.with_ip_name(id, name);
+        }
+        if let Some(tracer) = tracer(&$a)? {
+            builder = builder.with_trace(tracer);
+        }
+        let funge = builder.build()?;
+        if $a.print_rules {
+            println!("{}", funge.rules());
+            return Ok(0)
         }
-        match $a.debug {
+        if let Some(trace) = &$a.playback {
+            playback::playback(funge, trace)?;
+            return Ok(0)
+        }
+        if let Some(file) = &$a.script {
+            script::script(funge, $a.arguments, file)?;
+            return Ok(0)
+        }
+        return match $a.debug {
             Some(interval) => {
-                let mut funge = FungeView::new(funge, $a.arguments)?;
+                let annotations = $a.annotations.clone()
+                    .unwrap_or_else(|| PathBuf::from(format!("{}.notes", $a.input.as_ref().expect("clap requires an input file"))));
+                let mut printable = Printable::new();
+                if let Some(ranges) = &$a.printable {
+                    printable = printable.with_ranges(Printable::parse_ranges(ranges)?);
+                }
+                if let Some(substitute) = $a.unprintable {
+                    printable = printable.with_substitute(substitute);
+                }
+                let mut funge = FungeView::new(funge, $a.arguments)?.with_annotations(annotations)?.with_printable(printable)
+                    .with_source(PathBuf::from($a.input.as_ref().expect("clap requires an input file")))?;
+                if let Some(cells) = &$a.stack_cells {
+                    funge = funge.with_stack_cells(parse_stack_cells(cells)?)?;
+                }
+                if let Some(speed) = $a.auto_speed {
+                    funge = funge.with_auto_speed(speed)?;
+                }
                 if let Some(s) = $a.steps {
                     funge.step_n(s);
                 }
                 funge.debug(interval);
+                Ok(0)
             }
             None => {
-                std::process::exit(funge.with_arguments($a.arguments).run()?);
+                if let Some(rate) = $a.watch {
+                    let funge = funge.with_arguments($a.arguments).with_output(IO::new().with_output(|store, s| {
+                        store.push(s);
+                        Ok(())
+                    }));
+                    return watch(funge, rate)
+                }
+                if let Some(every) = $a.cross_check {
+                    let funge = funge.with_arguments($a.arguments)
+                        .with_output(IO::new().with_buffering($a.buffered).with_number_format($a.number_format));
+                    return funge.cross_check(every)
+                }
+                let funge = if let Some(io) = &$a.io {
+                    let (input, output) = transport::connect(io)?;
+                    funge.with_input(input.with_store($a.arguments))
+                        .with_output(output.with_buffering($a.buffered).with_number_format($a.number_format))
+                } else if $a.filter {
+                    funge.with_input(IO::binary())
+                        .with_output(IO::binary().with_buffering(true).with_number_format($a.number_format))
+                } else {
+                    funge.with_arguments($a.arguments)
+                        .with_output(IO::new().with_buffering($a.buffered).with_number_format($a.number_format))
+                };
+                funge.run().into_result()
             }
         }
     }
 }
 
 
+// a run that did not halt exits with 1 and the error, or with 70 or 71 in distinct mode, also when it could not start
+fn exit(result: Result<i32>, exit_status: ExitStatus) -> Result<()> {
+    match (result, exit_status) {
+        (Ok(code), _) => std::process::exit(code),
+        (Err(error), ExitStatus::Distinct) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(StopReason::from(&error).exit_code())
+        }
+        (Err(error), _) => Err(error)
+    }
+}
+
+
+fn run(args: Args, matches: ArgMatches) -> Result<i32> {
+    match args.bits {
+        Some(8) => { run!(args, matches, i8); }
+        Some(16) => { run!(args, matches, i16); }
+        Some(32) => { run!(args, matches, i32); }
+        Some(64) => { run!(args, matches, i64); }
+        Some(128) => { run!(args, matches, i128); }
+        None => { run!(args, matches, isize); }
+        Some(bits) => Err(Error::msg(format!("Unsupported number of bits: {}", bits)))
+    }
+}
+
+
 fn main() -> Result<()> {
-    let args = Args::parse();
-    if let None = args.bits {
-        run!(args, isize);
-    } else if let Some(8) = args.bits {
-        run!(args, i8);
-    } else if let Some(16) = args.bits {
-        run!(args, i16);
-    } else if let Some(32) = args.bits {
-        run!(args, i32);
-    } else if let Some(64) = args.bits {
-        run!(args, i64);
-    } else if let Some(128) = args.bits {
-        run!(args, i128);
+    if let Some((code, version, exit_status)) = bundle::embedded()? {
+        return exit(bundle::run(code, version), exit_status)
     }
-    Ok(())
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches)?;
+    if let Some(command) = args.command {
+        return match command {
+            Commands::Explain { ops, befunge } => explain(ops, befunge),
+            Commands::Examples { name, debug, arguments } => exit(examples(name, debug, arguments), args.exit_status_mode),
+            Commands::Tutorial => {
+                tutorial::tutorial();
+                Ok(())
+            }
+            Commands::Bundle { program, output, befunge } => bundle::bundle(&program, &output, befunge, args.exit_status_mode),
+            Commands::ConvertTrace { input, output } => trace::convert(&input, &output),
+            Commands::Slice { trace, index } => {
+                println!("{}", slice::slice(&trace::read(trace)?, index)?);
+                Ok(())
+            }
+            #[cfg(feature = "bench")]
+            Commands::Bench { steps } => bench(steps)
+        }
+    }
+    let exit_status = args.exit_status_mode;
+    exit(run(args, matches), exit_status)
 }
\ No newline at end of file
@@ -0,0 +1,108 @@
+use std::fmt::{self, Display, Formatter};
+
+
+#[derive(Clone, Debug)]
+pub struct OpInfo {
+    pub op: char,
+    pub name: &'static str,
+    pub stack: &'static str,
+    pub since: u8,
+    pub description: &'static str
+}
+
+impl OpInfo {
+    pub fn versions(&self) -> Vec<String> {
+        [93, 97, 98].into_iter().filter(|v| *v >= self.since).map(|v| format!("B{}", v)).collect()
+    }
+}
+
+impl Display for OpInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}  {}  ({})  [{}]\n{}", self.op, self.name, self.stack, self.versions().join(", "), self.description)
+    }
+}
+
+
+const fn op(op: char, name: &'static str, stack: &'static str, since: u8, description: &'static str) -> OpInfo {
+    OpInfo { op, name, stack, since, description }
+}
+
+pub const OPS: &[OpInfo] = &[
+    op('!', "logical not", "a -- !a", 93, "Push 1 if the value is zero, 0 otherwise."),
+    op('"', "string mode", "--", 93, "Toggle string mode: cells are pushed as values until the next \"."),
+    op('#', "trampoline", "--", 93, "Skip the next cell."),
+    op('$', "pop", "a --", 93, "Discard the top value."),
+    op('%', "remainder", "a b -- a%b", 93, "Remainder of a divided by b, 0 when b is zero."),
+    op('&', "input integer", "-- n", 93, "Read a number from the input, reflect on failure."),
+    op('\'', "fetch character", "-- c", 97, "Push the next cell and skip it."),
+    op('(', "load semantics", "en..e1 n --", 98, "Load a fingerprint, reflect if it is unknown."),
+    op(')', "unload semantics", "en..e1 n --", 98, "Unload a fingerprint, reflect if it is unknown."),
+    op('*', "multiply", "a b -- a*b", 93, "Multiply the top two values."),
+    op('+', "add", "a b -- a+b", 93, "Add the top two values."),
+    op(',', "output character", "c --", 93, "Write the value as a character."),
+    op('-', "subtract", "a b -- a-b", 93, "Subtract b from a."),
+    op('.', "output integer", "n --", 93, "Write the value as a number followed by a space."),
+    op('/', "divide", "a b -- a/b", 93, "Integer division of a by b, 0 when b is zero."),
+    op('0', "push 0", "-- 0", 93, "Push 0."),
+    op('1', "push 1", "-- 1", 93, "Push 1."),
+    op('2', "push 2", "-- 2", 93, "Push 2."),
+    op('3', "push 3", "-- 3", 93, "Push 3."),
+    op('4', "push 4", "-- 4", 93, "Push 4."),
+    op('5', "push 5", "-- 5", 93, "Push 5."),
+    op('6', "push 6", "-- 6", 93, "Push 6."),
+    op('7', "push 7", "-- 7", 93, "Push 7."),
+    op('8', "push 8", "-- 8", 93, "Push 8."),
+    op('9', "push 9", "-- 9", 93, "Push 9."),
+    op(':', "duplicate", "a -- a a", 93, "Duplicate the top value."),
+    op(';', "jump over", "--", 98, "Skip all cells up to and including the next ;."),
+    op('<', "go west", "--", 93, "Move left."),
+    op('=', "execute", "0gnirts -- r", 98, "Run a 0gnirts as a command and push its return code."),
+    op('>', "go east", "--", 93, "Move right."),
+    op('?', "go away", "--", 93, "Move in a random direction."),
+    op('@', "stop", "--", 93, "Stop the current IP."),
+    op('[', "turn left", "--", 98, "Rotate the delta 90 degrees counterclockwise."),
+    op('\\', "swap", "a b -- b a", 93, "Swap the top two values."),
+    op(']', "turn right", "--", 98, "Rotate the delta 90 degrees clockwise."),
+    op('^', "go north", "--", 93, "Move up."),
+    op('_', "east-west if", "a --", 93, "Move right if the value is zero, left otherwise."),
+    op('`', "greater than", "a b -- a>b", 93, "Push 1 if a is greater than b, 0 otherwise."),
+    op('a', "push 10", "-- 10", 97, "Push 10."),
+    op('b', "push 11", "-- 11", 97, "Push 11."),
+    op('c', "push 12", "-- 12", 97, "Push 12."),
+    op('d', "push 13", "-- 13", 97, "Push 13."),
+    op('e', "push 14", "-- 14", 97, "Push 14."),
+    op('f', "push 15", "-- 15", 97, "Push 15."),
+    op('g', "get", "x y -- v", 93, "Push the value of the cell at (x, y) relative to the storage offset."),
+    op('h', "go high", "--", 98, "Move up in the third dimension, reflects in Befunge."),
+    op('i', "input file", "0gnirts flags x y -- w h x y", 98, "Load a file into funge-space at (x, y)."),
+    op('j', "jump forward", "n --", 98, "Move n cells forward."),
+    op('k', "iterate", "n --", 98, "Execute the next instruction n times."),
+    op('l', "go low", "--", 98, "Move down in the third dimension, reflects in Befunge."),
+    op('m', "high-low if", "a --", 98, "Branch in the third dimension, reflects in Befunge."),
+    op('n', "clear stack", "... --", 98, "Empty the top stack."),
+    op('o', "output file", "0gnirts flags x y w h --", 98, "Write a region of funge-space to a file."),
+    op('p', "put", "v x y --", 93, "Write v into the cell at (x, y) relative to the storage offset."),
+    op('q', "quit", "r --", 98, "Stop the program with return code r."),
+    op('r', "reflect", "--", 98, "Reverse the delta."),
+    op('s', "store character", "v --", 98, "Write v into the next cell and skip it."),
+    op('t', "split", "--", 98, "Create a new IP moving in the opposite direction."),
+    op('u', "stack under stack", "n --", 98, "Transfer n values from the second stack to the top stack."),
+    op('v', "go south", "--", 93, "Move down."),
+    op('w', "compare", "a b --", 98, "Turn left if a < b, right if a > b."),
+    op('x', "absolute delta", "dx dy --", 98, "Set the delta to (dx, dy)."),
+    op('y', "get sysinfo", "n -- ...", 98, "Push system information, or only the nth cell of it."),
+    op('z', "no operation", "--", 98, "Do nothing."),
+    op('{', "begin block", "n --", 98, "Push a new stack, transferring n values and saving the storage offset."),
+    op('|', "north-south if", "a --", 93, "Move down if the value is zero, up otherwise."),
+    op('}', "end block", "n --", 98, "Pop the top stack, transferring n values and restoring the storage offset."),
+    op('~', "input character", "-- c", 93, "Read a character from the input, reflect on failure."),
+];
+
+
+pub fn explain(op: char) -> Option<&'static OpInfo> {
+    OPS.iter().find(|info| info.op == op)
+}
+
+pub fn instruction_set(version: u8) -> Vec<&'static OpInfo> {
+    OPS.iter().filter(|info| info.since <= version).collect()
+}
@@ -0,0 +1,163 @@
+use std::cmp::{max, min};
+use anyhow::Result;
+use cursive::{Cursive, CursiveExt, Printer, Vec2};
+use cursive::view::{Nameable, View};
+use cursive::theme::{BorderStyle, ColorStyle, Palette, Theme};
+use cursive::event::{Event, EventResult, Key};
+use cursive::views::{Dialog, EditView};
+use rusty_funge::{Int, Funge, FungeSpace, Rect, convert::{cast, join, to_char}, trace::TraceRecord};
+
+
+pub(crate) struct PlaybackView<I: Int> {
+    code: FungeSpace<I>,
+    extent: Rect,
+    records: Vec<TraceRecord>,
+    undo: Vec<Vec<(Vec<isize>, I)>>,
+    output: Vec<usize>,
+    text: String
+}
+
+impl<I: Int> PlaybackView<I> {
+    pub(crate) fn new(funge: Funge<I>, records: Vec<TraceRecord>) -> Self {
+        let mut extent = funge.extent.clone();
+        for record in &records {
+            for pos in record.writes.iter().map(|(x, y, _)| (*x, *y)).chain([record.position]) {
+                extent.left = min(extent.left, pos.0);
+                extent.right = max(extent.right, pos.0 + 1);
+                extent.top = min(extent.top, pos.1);
+                extent.bottom = max(extent.bottom, pos.1 + 1);
+            }
+        }
+        Self { code: funge.code, extent, records, undo: Vec::new(), output: Vec::new(), text: String::new() }
+    }
+
+    fn cursor(&self) -> usize {
+        self.undo.len()
+    }
+
+    fn forward(&mut self) {
+        if let Some(record) = self.records.get(self.cursor()) {
+            let mut undo = Vec::new();
+            for (x, y, value) in &record.writes {
+                let pos = vec![*x, *y];
+                undo.push((pos.clone(), self.code[&pos]));
+                if let Ok(value) = cast(*value) {
+                    self.code.insert(pos, value);
+                }
+            }
+            self.output.push(self.text.len());
+            self.text.push_str(&record.output);
+            self.undo.push(undo);
+        }
+    }
+
+    fn back(&mut self) {
+        if let Some(undo) = self.undo.pop() {
+            for (pos, value) in undo.into_iter().rev() {
+                self.code.insert(pos, value);
+            }
+            if let Some(len) = self.output.pop() {
+                self.text.truncate(len);
+            }
+        }
+    }
+
+    fn jump(&mut self, step: isize) {
+        while self.records.get(self.cursor()).is_some_and(|record| record.step < step) {
+            self.forward();
+        }
+        while self.cursor() > 0 && self.records[self.cursor() - 1].step >= step {
+            self.back();
+        }
+    }
+
+    pub(crate) fn play(self) {
+        let mut app = Cursive::new();
+        app.add_layer(self.with_name("playback"));
+        app.add_global_callback(Key::Esc, |app| {
+            if app.screen().len() > 1 {
+                app.pop_layer();
+            } else {
+                app.quit();
+            }
+        });
+        app.set_theme(Theme { shadow: false, borders: BorderStyle::None, palette: Palette::default() });
+        app.run();
+    }
+}
+
+impl<I: Int> View for PlaybackView<I> {
+    fn draw(&self, printer: &Printer) {
+        let record = self.records.get(self.cursor());
+        let cheight = (printer.size.y / 2) as isize;
+        let cwidth = printer.size.x as isize;
+        let (x, y) = record.map(|r| r.position).unwrap_or((self.extent.left, self.extent.top));
+        let top = if cheight >= self.extent.height() { self.extent.top } else { max(y - cheight / 2, self.extent.top) };
+        let left = if cwidth >= self.extent.width() { self.extent.left } else { max(x - cwidth / 2, self.extent.left) };
+        let bottom = min(top + cheight, self.extent.bottom);
+        let right = min(left + cwidth, self.extent.right);
+        for (n, line) in self.code.get_string(Rect::new(left, right, top, bottom)).iter().enumerate() {
+            printer.print((0, n), line);
+        }
+        if let Some(record) = record {
+            let (x, y) = record.position;
+            if (left <= x) & (x < right) & (top <= y) & (y < bottom) {
+                let c = to_char(self.code[&vec![x, y]]).unwrap_or('¤');
+                printer.with_color(ColorStyle::highlight(), |printer| {
+                    printer.print(((x - left) as usize, (y - top) as usize), &c.to_string());
+                });
+            }
+        }
+        let n = (bottom - top) as usize;
+        match record {
+            Some(record) => {
+                printer.print((0, n + 1), &format!("record {} of {}, step {}, ip {} at ({}, {})",
+                                                   self.cursor() + 1, self.records.len(), record.step, record.ip, x, y));
+                printer.print((0, n + 2), &format!("stack: [{}]", join(&record.stack, ", ")));
+            }
+            None => printer.print((0, n + 1), &format!("end of trace, {} records", self.records.len()))
+        }
+        printer.print((0, n + 4), "output:");
+        let lines: Vec<&str> = self.text.lines().collect();
+        let room = printer.size.y.saturating_sub(n + 7);
+        for (i, line) in lines.iter().skip(lines.len().saturating_sub(room)).enumerate() {
+            printer.print((0, n + 5 + i), line);
+        }
+        printer.print((0, printer.size.y.saturating_sub(1)),
+                      "esc: quit, left/right: step, page up/down: 100 steps, home/end, g: go to step");
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        constraint
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Key(Key::Right) | Event::Key(Key::Enter) => self.forward(),
+            Event::Key(Key::Left) | Event::Key(Key::Backspace) => self.back(),
+            Event::Key(Key::PageDown) => (0..100).for_each(|_| self.forward()),
+            Event::Key(Key::PageUp) => (0..100).for_each(|_| self.back()),
+            Event::Key(Key::Home) => while self.cursor() > 0 { self.back() },
+            Event::Key(Key::End) => while self.cursor() < self.records.len() { self.forward() },
+            Event::Char('g') => {
+                return EventResult::with_cb(|app| {
+                    app.add_layer(Dialog::new().title("Go to step").content(EditView::new().on_submit(|app, text| {
+                        let step = text.parse::<isize>();
+                        app.pop_layer();
+                        if let Ok(step) = step {
+                            app.call_on_all_named::<PlaybackView<I>, _>("playback", |view| view.jump(step));
+                        }
+                    })));
+                })
+            }
+            _ => return EventResult::Ignored
+        }
+        EventResult::Consumed(None)
+    }
+}
+
+
+pub(crate) fn playback<I: Int>(funge: Funge<I>, trace: &str) -> Result<()> {
+    PlaybackView::new(funge, rusty_funge::trace::read(trace)?).play();
+    Ok(())
+}
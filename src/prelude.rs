@@ -0,0 +1,8 @@
+// the stable surface of the library, breaking changes here only come with a new major version, its enums (but
+// RunStatus, a run is paused or finished) and the structs with public fields are non_exhaustive, so that variants and
+// fields can be added in a minor version
+pub use crate::{
+    Argument, Effect, Execute, ExitStatus, Finalizer, Funge, FungeBuilder, Hook, HookAction, InputRequest, Int, IO, IP,
+    IpName, IpStats, Limits, LineEndings, NumberFormat, OnError, OnIpCap, OnUnderflow, Prediction, Progress, Prompt,
+    Rect, Reporter, Rules, RunOutcome, RunStatus, Schedule, SpaceStats, StopReason, Validation, Wrap, run_str
+};
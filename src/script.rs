@@ -0,0 +1,117 @@
+use std::{fs, fmt::Display, path::Path};
+use anyhow::{Error, Result};
+use rusty_funge::{Int, Funge, IO, IP, convert::{from_char, join}};
+
+
+// the debugger without a screen, one command per line, lines starting with # are comments:
+//   break x y                  stop run before an IP executes this cell
+//   clear x y                  remove the breakpoint
+//   step [n]                   n steps, 1 if not given
+//   run                        until a breakpoint or the end of the program
+//   assert stack id == a b c   the top of the stack of IP id, the top last
+//   assert depth id == n       the number of cells on the stack of IP id
+//   assert position id == x y
+//   assert cell x y == v       a number or a character in quotes: 'c'
+//   assert output == text      everything written so far, \n for a newline
+//   assert exit == n           the program has ended with this exit code
+//   dump state file            the steps, IPs, cells and output as json
+struct Script<I: Int> {
+    funge: Funge<I>,
+    exit_code: Option<i32>,
+    breakpoints: Vec<(isize, isize)>
+}
+
+impl<I: Int> Script<I> {
+    // after the program ends the funge is kept as it was when it ended
+    fn step(&mut self) -> Result<bool> {
+        if self.exit_code.is_none() {
+            self.exit_code = self.funge.step_or_stop()?;
+        }
+        Ok(self.exit_code.is_none())
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        self.funge.ips.iter().any(|ip| self.breakpoints.contains(&(ip.position()[0], ip.position()[1])))
+    }
+
+    fn ip(&self, id: &str) -> Result<&IP<I>> {
+        let id: usize = parse(id)?;
+        self.funge.ips.iter().find(|ip| ip.id == id).ok_or(Error::msg(format!("IP {} is not running", id)))
+    }
+
+    fn execute(&mut self, line: &str) -> Result<()> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words[..] {
+            ["break", x, y] => self.breakpoints.push((parse(x)?, parse(y)?)),
+            ["clear", x, y] => {
+                let cell = (parse(x)?, parse(y)?);
+                self.breakpoints.retain(|breakpoint| *breakpoint != cell);
+            }
+            ["step"] => { self.step()?; }
+            ["step", n] => for _ in 0..parse(n)? {
+                self.step()?;
+            }
+            ["run"] => while self.step()? && !self.at_breakpoint() {}
+            ["dump", "state", file] => fs::write(file, self.funge.to_json())?,
+            ["assert", "output", "==", ..] => {
+                let expected = line.split_once("==").map_or("", |(_, text)| text.strip_prefix(' ').unwrap_or(text))
+                    .replace("\\n", "\n").replace("\\t", "\t");
+                check(format!("{:?}", self.funge.output.get()), format!("{:?}", expected))?
+            }
+            ["assert", "stack", id, "==", ref values @ ..] => {
+                let expected = values.iter().map(|value| parse(value)).collect::<Result<Vec<I>>>()?;
+                let stack = self.ip(id)?.stack();
+                let top = stack[stack.len().saturating_sub(expected.len())..].to_vec();
+                check(format!("[{}]", join(&top, ", ")), format!("[{}]", join(&expected, ", ")))?
+            }
+            ["assert", "depth", id, "==", n] => check(self.ip(id)?.stack_depth(), parse(n)?)?,
+            ["assert", "position", id, "==", x, y] => check(join(self.ip(id)?.position(), " "), join(&[parse::<isize>(x)?, parse(y)?], " "))?,
+            ["assert", "cell", x, y, "==", value] => {
+                let value = match value.strip_prefix('\'').and_then(|c| c.strip_suffix('\'')) {
+                    Some(c) if c.chars().count() == 1 => from_char(c.chars().next().unwrap_or(' '))?,
+                    _ => parse(value)?
+                };
+                check(self.funge.code[&vec![parse(x)?, parse(y)?]], value)?
+            }
+            ["assert", "exit", "==", n] => match self.exit_code {
+                Some(code) => check(code, parse(n)?)?,
+                None => Err(Error::msg(format!("the program is still running after {} steps", self.funge.steps)))?
+            }
+            _ => Err(Error::msg("unknown command"))?
+        }
+        Ok(())
+    }
+}
+
+
+fn parse<T: std::str::FromStr>(s: &str) -> Result<T> {
+    s.parse().map_err(|_| Error::msg(format!("cannot read {}", s)))
+}
+
+
+fn check<T: Display + PartialEq>(found: T, expected: T) -> Result<()> {
+    if found == expected {
+        Ok(())
+    } else {
+        Err(Error::msg(format!("expected {}, found {}", expected, found)))
+    }
+}
+
+
+// runs the script, stopping at the first command that fails, with its line in the message
+pub(crate) fn script<I: Int>(funge: Funge<I>, arguments: Vec<String>, file: &Path) -> Result<()> {
+    let text = fs::read_to_string(file).map_err(|error| Error::msg(format!("{}: {}", file.display(), error)))?;
+    let funge = funge.with_arguments(arguments).with_output(IO::new().with_output(|store, s| {
+        store.push(s);
+        Ok(())
+    }));
+    let mut script = Script { funge, exit_code: None, breakpoints: Vec::new() };
+    for (n, line) in text.lines().enumerate() {
+        // trailing spaces can be part of the output
+        let line = line.trim_start();
+        if !line.trim_end().is_empty() && !line.starts_with('#') {
+            script.execute(line).map_err(|error| Error::msg(format!("{}:{}: {}: {}", file.display(), n + 1, line, error)))?;
+        }
+    }
+    Ok(())
+}
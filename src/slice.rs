@@ -0,0 +1,137 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display, Formatter};
+use anyhow::{Error, Result};
+use crate::convert::to_char;
+use crate::trace::TraceRecord;
+
+
+// values popped and pushed by instructions with a fixed stack effect
+fn arity(op: i128) -> Option<(usize, usize)> {
+    Some(match to_char(op).ok()? {
+        '0'..='9' | 'a'..='f' | '&' | '~' | '\'' => (0, 1),
+        '+' | '-' | '*' | '/' | '%' | '`' => (2, 1),
+        '!' => (1, 1),
+        ':' => (1, 2),
+        '\\' => (2, 2),
+        '$' | '.' | ',' | '_' | '|' | 'j' | 's' => (1, 0),
+        'g' => (2, 1),
+        'p' => (3, 0),
+        'w' | 'x' => (2, 0),
+        ' ' | '>' | '<' | '^' | 'v' | '?' | '#' | '@' | ';' | '[' | ']' | 'r' | 'z' => (0, 0),
+        _ => None?
+    })
+}
+
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    deps: Vec<usize>,
+    cells: Vec<(isize, isize)>
+}
+
+
+// the steps and original source cells a value depends on, following data flow only
+#[derive(Clone, Debug)]
+pub struct Slice {
+    pub index: usize,
+    pub output: char,
+    pub steps: Vec<TraceRecord>,
+    pub cells: Vec<(isize, isize)>
+}
+
+impl Display for Slice {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "output {} {:?} depends on {} steps:", self.index, self.output, self.steps.len())?;
+        for record in &self.steps {
+            let op = to_char(record.op).unwrap_or('¤');
+            let stack = record.stack.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", ");
+            writeln!(f, "  step {:>6} ip {} at ({}, {}) {:?} stack [{}]", record.step, record.ip, record.position.0,
+                     record.position.1, op, stack)?;
+        }
+        let cells = self.cells.iter().map(|(x, y)| format!("({}, {})", x, y)).collect::<Vec<String>>();
+        write!(f, "source cells: {}", cells.join(", "))
+    }
+}
+
+
+// approximate backward slice of an output character through the stack, g and p, needs an unfiltered trace
+pub fn slice(records: &[TraceRecord], index: usize) -> Result<Slice> {
+    let mut count = 0;
+    let (root, output) = records.iter().enumerate().find_map(|(n, record)| {
+        let chars = record.output.chars().collect::<Vec<char>>();
+        count += chars.len();
+        (count > index).then(|| (n, chars[chars.len() + index - count]))
+    }).ok_or(Error::msg(format!("The trace has no output character {}.", index)))?;
+
+    // the step that produced each stack value, per IP
+    let mut stacks: HashMap<usize, Vec<Option<usize>>> = HashMap::new();
+    let mut writers: HashMap<(isize, isize), usize> = HashMap::new();
+    let mut nodes = Vec::new();
+    let mut split = Vec::new();
+    for (n, record) in records[..=root].iter().enumerate() {
+        let next = records[n + 1..].iter().find(|r| r.ip == record.ip).map(|r| &r.stack);
+        let stack = stacks.entry(record.ip).or_insert_with(|| split.clone());
+        while stack.len() < record.stack.len() {
+            stack.insert(0, None);
+        }
+        stack.drain(..stack.len() - record.stack.len());
+
+        let mut node = Node::default();
+        if let Some(writer) = writers.get(&record.position) {
+            node.deps.push(*writer);
+        }
+        let effect = arity(record.op).filter(|(pops, pushes)| match next {
+            Some(next) => record.stack.len().saturating_sub(*pops) + pushes == next.len(),
+            None => true
+        });
+        let (pops, pushes) = match (effect, next) {
+            (Some(effect), _) => effect,
+            (None, Some(next)) => {
+                let same = record.stack.iter().zip(next).take_while(|(a, b)| a == b).count();
+                (record.stack.len() - same, next.len() - same)
+            }
+            (None, None) => (record.stack.len(), 0)
+        };
+        let popped = stack.split_off(stack.len().saturating_sub(pops));
+        node.deps.extend(popped.iter().flatten());
+        match to_char(record.op) {
+            Ok(':') if effect.is_some() => stack.extend([popped.first().copied().flatten(); 2]),
+            Ok('\\') if effect.is_some() => stack.extend(popped.iter().rev()),
+            Ok('g') if effect.is_some() => {
+                let cell = match record.stack[..] {
+                    [.., x, y] => Some((x as isize, y as isize)),
+                    _ => None
+                };
+                match cell.and_then(|cell| writers.get(&cell).copied()) {
+                    Some(writer) => node.deps.push(writer),
+                    None => node.cells.extend(cell)
+                }
+                stack.push(Some(n));
+            }
+            _ => stack.extend(vec![Some(n); pushes])
+        }
+        for (x, y, _) in &record.writes {
+            writers.insert((*x, *y), n);
+        }
+        if matches!(to_char(record.op), Ok('t')) {
+            split = stack.clone();
+        }
+        nodes.push(node);
+    }
+
+    let mut seen = BTreeSet::new();
+    let mut cells = BTreeSet::new();
+    let mut todo = vec![root];
+    while let Some(n) = todo.pop() {
+        if seen.insert(n) {
+            cells.extend(nodes[n].cells.iter().map(|(x, y)| (*y, *x)));
+            todo.extend(&nodes[n].deps);
+        }
+    }
+    Ok(Slice {
+        index,
+        output,
+        steps: seen.into_iter().map(|n| records[n].clone()).collect(),
+        cells: cells.into_iter().map(|(y, x)| (x, y)).collect()
+    })
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream, UdpSocket};
+use std::sync::Arc;
+use anyhow::{Error, Result};
+
+
+// R and W move at most this many bytes at once, as much as a UDP datagram can hold, the count they push says how many
+pub(crate) const MAX_TRANSFER: usize = 65536;
+
+// a socket the way SOCK sees it: created, bound, and then listening or connected
+enum Socket {
+    New { udp: bool, address: Option<SocketAddrV4> },
+    Listener(TcpListener),
+    Stream(TcpStream),
+    Udp(UdpSocket)
+}
+
+
+// the open sockets by handle, clones of the funge share them
+#[derive(Clone, Default)]
+pub(crate) struct Sockets {
+    table: HashMap<usize, Arc<Socket>>,
+    last: usize
+}
+
+impl Sockets {
+    fn add(&mut self, socket: Socket) -> usize {
+        self.last += 1;
+        self.table.insert(self.last, Arc::new(socket));
+        self.last
+    }
+
+    fn get(&self, handle: usize) -> Result<&Socket> {
+        self.table.get(&handle).map(|socket| &**socket).ok_or_else(|| Error::msg(format!("no socket {}", handle)))
+    }
+
+    // before anything is read into or from funge-space
+    pub(crate) fn connected(&self, handle: usize) -> Result<()> {
+        match self.get(handle)? {
+            Socket::Stream(_) | Socket::Udp(_) => Ok(()),
+            _ => Err(Error::msg(format!("socket {} is not connected", handle)))
+        }
+    }
+
+    pub(crate) fn create(&mut self, udp: bool) -> usize {
+        self.add(Socket::New { udp, address: None })
+    }
+
+    // a TCP socket binds when it starts listening
+    pub(crate) fn bind(&mut self, handle: usize, address: SocketAddrV4) -> Result<()> {
+        let socket = match self.get(handle)? {
+            Socket::New { udp: true, .. } => Socket::Udp(UdpSocket::bind(address)?),
+            Socket::New { udp: false, .. } => Socket::New { udp: false, address: Some(address) },
+            _ => Err(Error::msg(format!("socket {} is already bound", handle)))?
+        };
+        self.table.insert(handle, Arc::new(socket));
+        Ok(())
+    }
+
+    // std chooses the backlog
+    pub(crate) fn listen(&mut self, handle: usize) -> Result<()> {
+        let socket = match self.get(handle)? {
+            Socket::New { udp: false, address: Some(address) } => Socket::Listener(TcpListener::bind(address)?),
+            _ => Err(Error::msg(format!("socket {} is not a bound TCP socket", handle)))?
+        };
+        self.table.insert(handle, Arc::new(socket));
+        Ok(())
+    }
+
+    pub(crate) fn accept(&mut self, handle: usize) -> Result<(usize, SocketAddr)> {
+        let (stream, peer) = match self.get(handle)? {
+            Socket::Listener(listener) => listener.accept()?,
+            _ => Err(Error::msg(format!("socket {} is not listening", handle)))?
+        };
+        Ok((self.add(Socket::Stream(stream)), peer))
+    }
+
+    pub(crate) fn connect(&mut self, handle: usize, address: SocketAddrV4) -> Result<()> {
+        let socket = match self.get(handle)? {
+            Socket::New { udp: false, .. } => Socket::Stream(TcpStream::connect(address)?),
+            Socket::New { udp: true, .. } => {
+                let udp = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+                udp.connect(address)?;
+                Socket::Udp(udp)
+            }
+            Socket::Udp(udp) => return Ok(udp.connect(address)?),
+            _ => Err(Error::msg(format!("socket {} is already connected", handle)))?
+        };
+        self.table.insert(handle, Arc::new(socket));
+        Ok(())
+    }
+
+    pub(crate) fn receive(&self, handle: usize, length: usize) -> Result<Vec<u8>> {
+        self.connected(handle)?;
+        let mut buffer = vec![0; length.min(MAX_TRANSFER)];
+        let n = match self.get(handle)? {
+            Socket::Stream(stream) => (&*stream).read(&mut buffer)?,
+            Socket::Udp(udp) => udp.recv(&mut buffer)?,
+            _ => Err(Error::msg(format!("socket {} is not connected", handle)))?
+        };
+        buffer.truncate(n);
+        Ok(buffer)
+    }
+
+    pub(crate) fn send(&self, handle: usize, bytes: &[u8]) -> Result<usize> {
+        Ok(match self.get(handle)? {
+            Socket::Stream(stream) => (&*stream).write(bytes)?,
+            Socket::Udp(udp) => udp.send(bytes)?,
+            _ => Err(Error::msg(format!("socket {} is not connected", handle)))?
+        })
+    }
+
+    // whether reading would not block
+    pub(crate) fn peek(&self, handle: usize) -> Result<bool> {
+        let mut buffer = [0];
+        let peeked = match self.get(handle)? {
+            Socket::Stream(stream) => {
+                stream.set_nonblocking(true)?;
+                let peeked = stream.peek(&mut buffer);
+                stream.set_nonblocking(false)?;
+                peeked
+            }
+            Socket::Udp(udp) => {
+                udp.set_nonblocking(true)?;
+                let peeked = udp.peek(&mut buffer);
+                udp.set_nonblocking(false)?;
+                peeked
+            }
+            _ => Err(Error::msg(format!("socket {} is not connected", handle)))?
+        };
+        match peeked {
+            Ok(_) => Ok(true),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error.into())
+        }
+    }
+
+    // std only exposes broadcast (5), the other options are accepted and ignored
+    pub(crate) fn set_option(&self, handle: usize, option: usize, value: bool) -> Result<()> {
+        if let Socket::Udp(udp) = self.get(handle)? {
+            if option == 5 {
+                udp.set_broadcast(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn kill(&mut self, handle: usize) -> Result<()> {
+        self.table.remove(&handle).map(|_| ()).ok_or_else(|| Error::msg(format!("no socket {}", handle)))
+    }
+}
@@ -0,0 +1,121 @@
+use std::cmp::max;
+
+
+// A small virtual screen that interprets output the way a terminal would: \r, backspace, tab, and the common ANSI
+// cursor movement and erase sequences. Colors and other sequences are dropped. Lines wrap at the width, \n also
+// returns to the first column, and rows are counted from the top of a screen of the given height: the cursor does not
+// move below it and a new line at the bottom scrolls the top row away.
+pub(crate) struct Terminal {
+    width: usize,
+    height: usize,
+    rows: Vec<Vec<char>>,
+    row: usize,
+    col: usize
+}
+
+impl Terminal {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Self { width: max(width, 1), height: max(height, 1), rows: vec![Vec::new()], row: 0, col: 0 }
+    }
+
+    pub(crate) fn lines(text: &str, width: usize, height: usize) -> Vec<String> {
+        let mut terminal = Self::new(width, height);
+        terminal.write(text);
+        terminal.rows.into_iter().map(|row| row.into_iter().collect::<String>().trim_end().to_string()).collect()
+    }
+
+    fn goto(&mut self, row: usize, col: usize) {
+        self.row = row.min(self.height - 1);
+        self.col = col.min(self.width - 1);
+        if self.rows.len() <= self.row {
+            self.rows.resize(self.row + 1, Vec::new());
+        }
+    }
+
+    fn new_line(&mut self) {
+        if self.row + 1 >= self.height {
+            self.rows.remove(0);
+            self.rows.push(Vec::new());
+        }
+        self.goto(self.row + 1, 0);
+    }
+
+    fn put(&mut self, c: char) {
+        if self.col >= self.width {
+            self.new_line();
+        }
+        let row = &mut self.rows[self.row];
+        if row.len() <= self.col {
+            row.resize(self.col + 1, ' ');
+        }
+        row[self.col] = c;
+        self.col += 1;
+    }
+
+    fn erase_line(&mut self, mode: usize) {
+        let (col, row) = (self.col, &mut self.rows[self.row]);
+        match mode {
+            0 => row.truncate(col),
+            1 => row.iter_mut().take(col + 1).for_each(|c| *c = ' '),
+            _ => row.clear()
+        }
+    }
+
+    fn erase_display(&mut self, mode: usize) {
+        match mode {
+            0 => {
+                self.erase_line(0);
+                self.rows.truncate(self.row + 1);
+            }
+            1 => {
+                self.rows.iter_mut().take(self.row).for_each(|row| row.clear());
+                self.erase_line(1);
+            }
+            _ => {
+                self.rows = vec![Vec::new()];
+                self.goto(0, 0);
+            }
+        }
+    }
+
+    fn csi(&mut self, params: &str, command: char) {
+        let numbers: Vec<usize> = params.split(';').map(|n| n.parse().unwrap_or(0)).collect();
+        let n = max(numbers[0], 1);
+        match command {
+            'A' => self.goto(self.row.saturating_sub(n), self.col),
+            'B' => self.goto(self.row.saturating_add(n), self.col),
+            'C' => self.goto(self.row, self.col.saturating_add(n)),
+            'D' => self.goto(self.row, self.col.saturating_sub(n)),
+            'G' => self.goto(self.row, n - 1),
+            'H' | 'f' => self.goto(n - 1, max(numbers.get(1).copied().unwrap_or(1), 1) - 1),
+            'J' => self.erase_display(numbers[0]),
+            'K' => self.erase_line(numbers[0]),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn write(&mut self, text: &str) {
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\n' => self.new_line(),
+                '\r' => self.col = 0,
+                '\x08' => self.col = self.col.min(self.width).saturating_sub(1),
+                '\t' => self.goto(self.row, (self.col / 8 + 1) * 8),
+                '\x1b' => if let Some('[') = chars.next() {
+                    let mut params = String::new();
+                    for c in chars.by_ref() {
+                        if c.is_ascii_digit() || c == ';' || c == '?' {
+                            params.push(c);
+                        } else {
+                            self.csi(params.trim_start_matches('?'), c);
+                            break
+                        }
+                    }
+                },
+                c if c.is_control() => {}
+                c => self.put(c)
+            }
+        }
+    }
+}
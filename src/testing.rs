@@ -0,0 +1,133 @@
+use std::fmt::Debug;
+use anyhow::{Error, Result};
+use proptest::prelude::*;
+use proptest::collection::vec;
+use crate::{convert::cast, Funge, Int};
+
+
+const SAFE_OPS: &str = "!$%*+-/0123456789:<>?\\^_`abcdefgnprsvwxz[]{}|\"#";
+// only the stack changes and the IP goes straight on, so a snippet always runs to its end
+const SNIPPET_OPS: &str = "!$%*+-/0123456789:\\`abcdefz";
+
+pub const IDENTITY_SNIPPETS: &[&str] = &[":$", "\\\\", "0$", "0+", "0-", "1*", "1/", "::$$", "zz", "\\:$\\"];
+
+
+// the smallest and largest cell, tried from the widest cell down as Int has no bounds
+fn bounds<I: Int>() -> (i128, i128) {
+    let max = [i128::MAX, i64::MAX.into(), i32::MAX.into(), i16::MAX.into(), i8::MAX.into()].into_iter()
+        .find(|max| cast::<I, i128>(*max).is_ok()).unwrap_or_default();
+    (-max - 1, max)
+}
+
+// small cells most of the time, but any cell and the extremes too
+pub fn cell<I: Int + Debug>() -> impl Strategy<Value = I> {
+    let (min, max) = bounds::<I>();
+    prop_oneof![any::<i8>().prop_map(i128::from), min..=max, Just(min), Just(max)]
+        .prop_map(|i| cast(i).expect("the value is within the bounds of a cell"))
+}
+
+pub fn stack<I: Int + Debug>(max_len: usize) -> impl Strategy<Value = Vec<I>> {
+    vec(cell(), 0..=max_len)
+}
+
+pub fn delta() -> impl Strategy<Value = Vec<isize>> {
+    prop_oneof![
+        Just(vec![1, 0]),
+        Just(vec![-1, 0]),
+        Just(vec![0, 1]),
+        Just(vec![0, -1]),
+        (-3isize..=3, -3isize..=3).prop_filter("delta cannot be zero", |(x, y)| (*x, *y) != (0, 0))
+            .prop_map(|(x, y)| vec![x, y])
+    ]
+}
+
+pub fn snippet(max_len: usize) -> impl Strategy<Value = String> {
+    vec(prop::sample::select(SNIPPET_OPS.chars().collect::<Vec<char>>()), 0..=max_len)
+        .prop_map(|ops| ops.into_iter().collect())
+}
+
+pub fn program(width: usize, height: usize) -> impl Strategy<Value = String> {
+    let ops: Vec<char> = SAFE_OPS.chars().chain(" @".chars()).collect();
+    vec(vec(prop::sample::select(ops), width), height)
+        .prop_map(|lines| lines.into_iter().map(|line| line.into_iter().collect::<String>()).collect::<Vec<String>>().join("\n"))
+}
+
+pub fn identity_snippet() -> impl Strategy<Value = &'static str> {
+    prop::sample::select(IDENTITY_SNIPPETS)
+}
+
+pub fn run_snippet<I: Int>(stack: &[I], snippet: &str) -> Result<Vec<I>> {
+    let end = snippet.chars().count() as isize;
+    let mut funge = Funge::<I>::new(format!("{}@", snippet))?;
+    let ip = funge.ips.first_mut().ok_or(Error::msg("No IP."))?;
+    ip.stack.extend(stack.to_vec());
+    for _ in 0..=4 * end {
+        match funge.ips.first() {
+            Some(ip) if ip.position[0] == end => return Ok(ip.stack.top()),
+            Some(_) => funge = funge.step_ips()?,
+            None => break
+        }
+    }
+    Err(Error::msg("Snippet did not run to its end."))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{strategy::ValueTree, test_runner::TestRunner};
+
+    // cells below the bottom of the stack are zeros
+    fn without_zeros<I: Int>(stack: &[I]) -> &[I] {
+        &stack[stack.iter().position(|cell| !cell.is_zero()).unwrap_or(stack.len())..]
+    }
+
+    #[test]
+    fn bounds_are_those_of_the_cell() {
+        assert_eq!(bounds::<i8>(), (i8::MIN.into(), i8::MAX.into()));
+        assert_eq!(bounds::<i32>(), (i32::MIN.into(), i32::MAX.into()));
+        assert_eq!(bounds::<i128>(), (i128::MIN, i128::MAX));
+    }
+
+    #[test]
+    fn cells_cover_the_whole_width() {
+        let mut runner = TestRunner::deterministic();
+        let cells = (0..1000).map(|_| cell::<i64>().new_tree(&mut runner).unwrap().current()).collect::<Vec<_>>();
+        assert!(cells.contains(&i64::MIN) && cells.contains(&i64::MAX));
+        assert!(cells.iter().any(|cell| i32::try_from(*cell).is_err()));
+        assert!(cells.iter().any(|cell| i8::try_from(*cell).is_ok()));
+    }
+
+    proptest! {
+        #[test]
+        fn stacks_are_not_too_long(stack in stack::<i64>(16)) {
+            prop_assert!(stack.len() <= 16);
+        }
+
+        #[test]
+        fn deltas_move(delta in delta()) {
+            prop_assert!(delta != [0, 0]);
+        }
+
+        #[test]
+        fn identity_snippets_keep_the_stack(stack in stack::<i64>(8), snippet in identity_snippet()) {
+            let result = run_snippet(&stack, snippet).unwrap();
+            prop_assert_eq!(without_zeros(&result), without_zeros(&stack));
+        }
+
+        #[test]
+        fn snippets_run_to_their_end(stack in stack::<i32>(8), snippet in snippet(16)) {
+            prop_assert!(snippet.chars().all(|c| SNIPPET_OPS.contains(c)));
+            prop_assert!(run_snippet(&stack, &snippet).is_ok());
+        }
+
+        #[test]
+        fn programs_have_their_size_and_run(program in program(8, 4)) {
+            let lines = program.lines().collect::<Vec<_>>();
+            prop_assert_eq!(lines.len(), 4);
+            prop_assert!(lines.iter().all(|line| line.chars().count() == 8));
+            // small cells, so that { and } move few cells
+            prop_assert!(Funge::<i8>::new(&program).unwrap().run_steps(256).is_ok());
+        }
+    }
+}
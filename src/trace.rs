@@ -0,0 +1,629 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use anyhow::{Error, Result};
+use crate::Rect;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceRecord {
+    pub step: isize,
+    pub ip: usize,
+    pub position: (isize, isize),
+    pub op: i128,
+    pub stack: Vec<i128>,
+    pub writes: Vec<(isize, isize, i128)>,
+    pub output: String,
+    pub name: Option<String>,
+    // the command = ran, the output of the step is its output
+    pub command: Option<String>
+}
+
+impl TraceRecord {
+    pub fn to_json(&self) -> String {
+        let stack = self.stack.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(",");
+        let writes = self.writes.iter().map(|(x, y, v)| format!("[{},{},{}]", x, y, v)).collect::<Vec<String>>().join(",");
+        let name = self.name.as_ref().map(|name| format!(",\"name\":{}", json_string(name))).unwrap_or_default();
+        let command = self.command.as_ref().map(|command| format!(",\"command\":{}", json_string(command))).unwrap_or_default();
+        format!("{{\"step\":{},\"ip\":{},\"x\":{},\"y\":{},\"op\":{},\"stack\":[{}],\"writes\":[{}],\"output\":{}{}{}}}",
+                self.step, self.ip, self.position.0, self.position.1, self.op, stack, writes, json_string(&self.output), name,
+                command)
+    }
+}
+
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 32 => { let _ = write!(json, "\\u{:04x}", c as u32); }
+            c => json.push(c)
+        }
+    }
+    json.push('"');
+    json
+}
+
+
+#[derive(Clone, Default)]
+pub struct TraceFilter {
+    pub ops: Option<HashSet<i128>>,
+    pub region: Option<Rect>,
+    pub ips: Option<HashSet<usize>>,
+    pub every: Option<usize>
+}
+
+impl TraceFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_ops(mut self, ops: &str) -> Self {
+        self.ops = Some(ops.chars().map(|c| c as i128).collect());
+        self
+    }
+
+    pub fn with_region(mut self, region: Rect) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn with_ips(mut self, ips: Vec<usize>) -> Self {
+        self.ips = Some(ips.into_iter().collect());
+        self
+    }
+
+    pub fn with_every(mut self, every: usize) -> Self {
+        self.every = Some(every);
+        self
+    }
+
+    pub fn matches(&self, record: &TraceRecord) -> bool {
+        self.ops.as_ref().is_none_or(|ops| ops.contains(&record.op)) &
+            self.region.as_ref().is_none_or(|region| region.contains(&[record.position.0, record.position.1])) &
+            self.ips.as_ref().is_none_or(|ips| ips.contains(&record.ip)) &
+            self.every.is_none_or(|every| (every == 0) || (record.step % every as isize == 0))
+    }
+}
+
+
+pub trait TraceSink: Send {
+    fn write_record(&mut self, record: &TraceRecord) -> Result<()>;
+    fn flush(&mut self) -> Result<()>;
+}
+
+
+pub struct JsonTraceWriter<W: Write + Send> {
+    sink: W
+}
+
+impl<W: Write + Send> JsonTraceWriter<W> {
+    pub fn new(sink: W) -> Self {
+        Self { sink }
+    }
+}
+
+impl<W: Write + Send> TraceSink for JsonTraceWriter<W> {
+    fn write_record(&mut self, record: &TraceRecord) -> Result<()> {
+        writeln!(self.sink, "{}", record.to_json())?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.sink.flush()?;
+        Ok(())
+    }
+}
+
+
+#[derive(Clone)]
+pub struct Tracer {
+    filter: TraceFilter,
+    sink: Arc<Mutex<dyn TraceSink>>,
+    pub(crate) writes: Vec<(isize, isize, i128)>,
+    pub(crate) output: String,
+    pub(crate) command: Option<String>
+}
+
+impl Tracer {
+    pub fn new<W: Write + Send + 'static>(sink: W) -> Self {
+        Self::with_sink(JsonTraceWriter::new(sink))
+    }
+
+    pub fn binary<W: Write + Send + 'static>(sink: W) -> Self {
+        Self::with_sink(BinaryTraceWriter::new(sink))
+    }
+
+    pub fn with_sink<S: TraceSink + 'static>(sink: S) -> Self {
+        Self { filter: TraceFilter::new(), sink: Arc::new(Mutex::new(sink)), writes: Vec::new(), output: String::new(), command: None }
+    }
+
+    pub fn with_filter(mut self, filter: TraceFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub(crate) fn record(&mut self, mut record: TraceRecord) -> Result<()> {
+        record.writes = std::mem::take(&mut self.writes);
+        record.output = std::mem::take(&mut self.output);
+        record.command = self.command.take();
+        if self.filter.matches(&record) {
+            self.sink.lock().map_err(|_| Error::msg("Trace sink was poisoned."))?.write_record(&record)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.sink.lock().map_err(|_| Error::msg("Trace sink was poisoned."))?.flush()
+    }
+}
+
+
+const MAGIC: &[u8; 4] = b"FTRC";
+const INDEX_MAGIC: &[u8; 4] = b"FIDX";
+const INDEX_INTERVAL: usize = 1024;
+
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u128) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer.push(byte);
+            break
+        }
+        buffer.push(byte | 0x80);
+    }
+}
+
+fn write_signed(buffer: &mut Vec<u8>, value: i128) {
+    write_varint(buffer, ((value << 1) ^ (value >> 127)) as u128);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<u128> {
+    let mut value = 0u128;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*pos).ok_or(Error::msg("Truncated trace."))?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u128) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value)
+        }
+        shift += 7;
+        if shift >= 128 {
+            Err(Error::msg("Invalid varint in trace."))?
+        }
+    }
+}
+
+fn read_string(bytes: &[u8], pos: &mut usize) -> Result<String> {
+    let len = read_varint(bytes, pos)? as usize;
+    let string = String::from_utf8(pos.checked_add(len).and_then(|end| bytes.get(*pos..end))
+        .ok_or(Error::msg("Truncated trace."))?.to_vec())?;
+    *pos += len;
+    Ok(string)
+}
+
+fn read_signed(bytes: &[u8], pos: &mut usize) -> Result<i128> {
+    let value = read_varint(bytes, pos)?;
+    Ok((value >> 1) as i128 ^ -((value & 1) as i128))
+}
+
+
+#[derive(Clone, Default)]
+struct DeltaState {
+    step: isize,
+    position: (isize, isize)
+}
+
+impl DeltaState {
+    fn encode(&mut self, record: &TraceRecord, buffer: &mut Vec<u8>) {
+        write_signed(buffer, (record.step - self.step) as i128);
+        write_varint(buffer, record.ip as u128);
+        write_signed(buffer, (record.position.0 - self.position.0) as i128);
+        write_signed(buffer, (record.position.1 - self.position.1) as i128);
+        write_signed(buffer, record.op);
+        write_varint(buffer, record.stack.len() as u128);
+        for value in &record.stack {
+            write_signed(buffer, *value);
+        }
+        write_varint(buffer, record.writes.len() as u128);
+        for (x, y, value) in &record.writes {
+            write_signed(buffer, *x as i128);
+            write_signed(buffer, *y as i128);
+            write_signed(buffer, *value);
+        }
+        write_varint(buffer, record.output.len() as u128);
+        buffer.extend(record.output.as_bytes());
+        let name = record.name.as_deref().unwrap_or_default();
+        write_varint(buffer, name.len() as u128);
+        buffer.extend(name.as_bytes());
+        let command = record.command.as_deref().unwrap_or_default();
+        write_varint(buffer, command.len() as u128);
+        buffer.extend(command.as_bytes());
+        self.step = record.step;
+        self.position = record.position;
+    }
+
+    fn decode(&mut self, bytes: &[u8], pos: &mut usize) -> Result<TraceRecord> {
+        let step = self.step + read_signed(bytes, pos)? as isize;
+        let ip = read_varint(bytes, pos)? as usize;
+        let x = self.position.0 + read_signed(bytes, pos)? as isize;
+        let y = self.position.1 + read_signed(bytes, pos)? as isize;
+        let op = read_signed(bytes, pos)?;
+        let mut stack = Vec::new();
+        for _ in 0..read_varint(bytes, pos)? {
+            stack.push(read_signed(bytes, pos)?);
+        }
+        let mut writes = Vec::new();
+        for _ in 0..read_varint(bytes, pos)? {
+            writes.push((read_signed(bytes, pos)? as isize, read_signed(bytes, pos)? as isize, read_signed(bytes, pos)?));
+        }
+        let output = read_string(bytes, pos)?;
+        let name = Some(read_string(bytes, pos)?).filter(|name| !name.is_empty());
+        let command = Some(read_string(bytes, pos)?).filter(|command| !command.is_empty());
+        self.step = step;
+        self.position = (x, y);
+        Ok(TraceRecord { step, ip, position: (x, y), op, stack, writes, output, name, command })
+    }
+}
+
+
+pub struct BinaryTraceWriter<W: Write + Send> {
+    sink: W,
+    state: DeltaState,
+    offset: u64,
+    count: usize,
+    index: Vec<(isize, u64)>,
+    finished: bool
+}
+
+impl<W: Write + Send> BinaryTraceWriter<W> {
+    pub fn new(sink: W) -> Self {
+        Self { sink, state: DeltaState::default(), offset: 0, count: 0, index: Vec::new(), finished: false }
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        if !self.finished {
+            self.finished = true;
+            self.write_header()?;
+            let mut buffer = Vec::new();
+            buffer.extend(INDEX_MAGIC);
+            write_varint(&mut buffer, self.index.len() as u128);
+            for (step, offset) in &self.index {
+                write_signed(&mut buffer, *step as i128);
+                write_varint(&mut buffer, *offset as u128);
+            }
+            buffer.extend(self.offset.to_le_bytes());
+            self.sink.write_all(&buffer)?;
+            self.sink.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        if self.offset == 0 {
+            self.sink.write_all(MAGIC)?;
+            self.offset = MAGIC.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> TraceSink for BinaryTraceWriter<W> {
+    fn write_record(&mut self, record: &TraceRecord) -> Result<()> {
+        self.write_header()?;
+        if self.count.is_multiple_of(INDEX_INTERVAL) {
+            self.state = DeltaState::default();
+            self.index.push((record.step, self.offset));
+        }
+        let mut buffer = Vec::new();
+        self.state.encode(record, &mut buffer);
+        self.sink.write_all(&buffer)?;
+        self.offset += buffer.len() as u64;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.sink.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> Drop for BinaryTraceWriter<W> {
+    fn drop(&mut self) {
+        self.finish().unwrap_or(());
+    }
+}
+
+
+pub struct BinaryTraceReader {
+    bytes: Vec<u8>,
+    end: usize,
+    index: Vec<(isize, usize)>
+}
+
+impl BinaryTraceReader {
+    pub fn new(bytes: Vec<u8>) -> Result<Self> {
+        if !bytes.starts_with(MAGIC) || bytes.len() < MAGIC.len() + 8 {
+            Err(Error::msg("Not a binary trace."))?
+        }
+        let mut offset = [0u8; 8];
+        offset.copy_from_slice(&bytes[bytes.len() - 8..]);
+        let end = u64::from_le_bytes(offset) as usize;
+        if end.checked_add(INDEX_MAGIC.len()).and_then(|index| bytes.get(end..index)) != Some(INDEX_MAGIC) {
+            Err(Error::msg("Binary trace has no index, was it finished?"))?
+        }
+        let mut pos = end + INDEX_MAGIC.len();
+        let mut index = Vec::new();
+        for _ in 0..read_varint(&bytes, &mut pos)? {
+            index.push((read_signed(&bytes, &mut pos)? as isize, read_varint(&bytes, &mut pos)? as usize));
+        }
+        Ok(Self { bytes, end, index })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(fs::read(path)?)
+    }
+
+    pub fn is_binary(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    pub fn records(&self) -> BinaryTraceIter<'_> {
+        self.records_from(MAGIC.len())
+    }
+
+    fn records_from(&self, pos: usize) -> BinaryTraceIter<'_> {
+        let next_index = self.index.partition_point(|(_, offset)| *offset < pos);
+        BinaryTraceIter { reader: self, pos, next_index, state: DeltaState::default() }
+    }
+
+    pub fn seek(&self, step: isize) -> BinaryTraceIter<'_> {
+        let n = self.index.partition_point(|(s, _)| *s <= step).saturating_sub(1);
+        let pos = self.index.get(n).map(|(_, offset)| *offset).unwrap_or(MAGIC.len());
+        let mut iter = self.records_from(pos);
+        let mut peek = iter.clone();
+        while let Some(Ok(record)) = peek.next() {
+            if record.step >= step {
+                break
+            }
+            iter = peek.clone();
+        }
+        iter
+    }
+}
+
+
+#[derive(Clone)]
+pub struct BinaryTraceIter<'a> {
+    reader: &'a BinaryTraceReader,
+    pos: usize,
+    // the first entry of the index not passed yet, the offsets grow with the entries
+    next_index: usize,
+    state: DeltaState
+}
+
+impl Iterator for BinaryTraceIter<'_> {
+    type Item = Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.reader.end {
+            return None
+        }
+        if self.reader.index.get(self.next_index).is_some_and(|(_, offset)| *offset == self.pos) {
+            self.state = DeltaState::default();
+            self.next_index += 1;
+        }
+        Some(self.state.decode(&self.reader.bytes, &mut self.pos))
+    }
+}
+
+
+pub fn read_json(text: &str) -> Result<Vec<TraceRecord>> {
+    text.lines().filter(|line| !line.trim().is_empty()).map(TraceRecord::from_json).collect()
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<TraceRecord>> {
+    let bytes = fs::read(path)?;
+    if BinaryTraceReader::is_binary(&bytes) {
+        BinaryTraceReader::new(bytes)?.records().collect()
+    } else {
+        read_json(&String::from_utf8(bytes)?)
+    }
+}
+
+pub fn convert(input: &Path, output: &Path) -> Result<()> {
+    let bytes = fs::read(input)?;
+    let file = BufWriter::new(File::create(output)?);
+    if BinaryTraceReader::is_binary(&bytes) {
+        let mut writer = JsonTraceWriter::new(file);
+        for record in BinaryTraceReader::new(bytes)?.records() {
+            writer.write_record(&record?)?;
+        }
+        writer.flush()
+    } else {
+        let mut writer = BinaryTraceWriter::new(file);
+        for record in read_json(&String::from_utf8(bytes)?)? {
+            writer.write_record(&record)?;
+        }
+        writer.finish()
+    }
+}
+
+
+enum Json {
+    Number(i128),
+    String(String),
+    Array(Vec<Json>),
+    Object(HashMap<String, Json>)
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Self> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut pos = 0;
+        let json = Self::parse_value(&chars, &mut pos)?;
+        Ok(json)
+    }
+
+    fn skip(chars: &[char], pos: &mut usize) {
+        while chars.get(*pos).is_some_and(|c| c.is_whitespace()) {
+            *pos += 1;
+        }
+    }
+
+    fn expect(chars: &[char], pos: &mut usize, c: char) -> Result<()> {
+        Self::skip(chars, pos);
+        if chars.get(*pos) == Some(&c) {
+            *pos += 1;
+            Ok(())
+        } else {
+            Err(Error::msg(format!("Expected {} at {} in trace record.", c, pos)))
+        }
+    }
+
+    fn parse_value(chars: &[char], pos: &mut usize) -> Result<Self> {
+        Self::skip(chars, pos);
+        match chars.get(*pos) {
+            Some('{') => {
+                *pos += 1;
+                let mut object = HashMap::new();
+                Self::skip(chars, pos);
+                if chars.get(*pos) == Some(&'}') {
+                    *pos += 1;
+                    return Ok(Json::Object(object))
+                }
+                loop {
+                    Self::skip(chars, pos);
+                    let key = match Self::parse_value(chars, pos)? {
+                        Json::String(key) => key,
+                        _ => Err(Error::msg("Expected a key in trace record."))?
+                    };
+                    Self::expect(chars, pos, ':')?;
+                    object.insert(key, Self::parse_value(chars, pos)?);
+                    Self::skip(chars, pos);
+                    match chars.get(*pos) {
+                        Some(',') => *pos += 1,
+                        Some('}') => { *pos += 1; return Ok(Json::Object(object)) }
+                        _ => Err(Error::msg("Expected , or } in trace record."))?
+                    }
+                }
+            }
+            Some('[') => {
+                *pos += 1;
+                let mut array = Vec::new();
+                Self::skip(chars, pos);
+                if chars.get(*pos) == Some(&']') {
+                    *pos += 1;
+                    return Ok(Json::Array(array))
+                }
+                loop {
+                    array.push(Self::parse_value(chars, pos)?);
+                    Self::skip(chars, pos);
+                    match chars.get(*pos) {
+                        Some(',') => *pos += 1,
+                        Some(']') => { *pos += 1; return Ok(Json::Array(array)) }
+                        _ => Err(Error::msg("Expected , or ] in trace record."))?
+                    }
+                }
+            }
+            Some('"') => {
+                *pos += 1;
+                let mut string = String::new();
+                loop {
+                    match chars.get(*pos) {
+                        Some('"') => { *pos += 1; return Ok(Json::String(string)) }
+                        Some('\\') => {
+                            *pos += 1;
+                            match chars.get(*pos) {
+                                Some('n') => string.push('\n'),
+                                Some('r') => string.push('\r'),
+                                Some('t') => string.push('\t'),
+                                Some('u') => {
+                                    let hex: String = chars.get(*pos + 1..*pos + 5).ok_or(Error::msg("Truncated escape."))?.iter().collect();
+                                    string.push(char::from_u32(u32::from_str_radix(&hex, 16)?).ok_or(Error::msg("Invalid escape."))?);
+                                    *pos += 4;
+                                }
+                                Some(c) => string.push(*c),
+                                None => Err(Error::msg("Truncated string in trace record."))?
+                            }
+                        }
+                        Some(c) => string.push(*c),
+                        None => Err(Error::msg("Truncated string in trace record."))?
+                    }
+                    *pos += 1;
+                }
+            }
+            Some(_) => {
+                let start = *pos;
+                while chars.get(*pos).is_some_and(|c| *c == '-' || c.is_ascii_digit()) {
+                    *pos += 1;
+                }
+                Ok(Json::Number(chars[start..*pos].iter().collect::<String>().parse()?))
+            }
+            None => Err(Error::msg("Unexpected end of trace record."))
+        }
+    }
+
+    fn number(&self) -> Result<i128> {
+        match self {
+            Json::Number(n) => Ok(*n),
+            _ => Err(Error::msg("Expected a number in trace record."))
+        }
+    }
+
+    fn string(&self) -> Result<String> {
+        match self {
+            Json::String(s) => Ok(s.to_owned()),
+            _ => Err(Error::msg("Expected a string in trace record."))
+        }
+    }
+
+    fn array(&self) -> Result<&Vec<Json>> {
+        match self {
+            Json::Array(a) => Ok(a),
+            _ => Err(Error::msg("Expected an array in trace record."))
+        }
+    }
+}
+
+
+impl TraceRecord {
+    pub fn from_json(line: &str) -> Result<Self> {
+        let object = match Json::parse(line)? {
+            Json::Object(object) => object,
+            _ => Err(Error::msg("Expected an object in trace record."))?
+        };
+        let field = |key: &str| object.get(key).ok_or(Error::msg(format!("Trace record has no {}.", key)));
+        let mut writes = Vec::new();
+        for write in field("writes")?.array()? {
+            let write = write.array()?;
+            if write.len() != 3 {
+                Err(Error::msg("Trace record writes need three values."))?
+            }
+            writes.push((write[0].number()? as isize, write[1].number()? as isize, write[2].number()?));
+        }
+        Ok(Self {
+            step: field("step")?.number()? as isize,
+            ip: field("ip")?.number()? as usize,
+            position: (field("x")?.number()? as isize, field("y")?.number()? as isize),
+            op: field("op")?.number()?,
+            stack: field("stack")?.array()?.iter().map(|i| i.number()).collect::<Result<Vec<i128>>>()?,
+            writes,
+            output: field("output")?.string()?,
+            name: object.get("name").map(|name| name.string()).transpose()?,
+            command: object.get("command").map(|command| command.string()).transpose()?
+        })
+    }
+}
@@ -0,0 +1,64 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::{fs::{File, OpenOptions}, path::Path, process::Command};
+use std::sync::{Arc, Mutex};
+use anyhow::{Error, Result};
+use rusty_funge::IO;
+
+
+type Connection = (Box<dyn BufRead + Send>, Box<dyn Write + Send>);
+
+
+fn tcp(stream: TcpStream) -> Result<Connection> {
+    Ok((Box::new(BufReader::new(stream.try_clone()?)), Box::new(stream)))
+}
+
+
+// pipes that do not exist yet are created, the input is opened first and both wait until the other side opens them
+#[cfg(unix)]
+fn fifo(input: &str, output: &str) -> Result<Connection> {
+    for path in [input, output] {
+        if !Path::new(path).exists() && !Command::new("mkfifo").arg(path).status()?.success() {
+            Err(Error::msg(format!("Cannot create the pipe {}", path)))?
+        }
+    }
+    Ok((Box::new(BufReader::new(File::open(input)?)), Box::new(OpenOptions::new().write(true).open(output)?)))
+}
+
+
+// input and output of the program over a connection: tcp:HOST:PORT connects to a server,
+// tcp-listen:HOST:PORT waits for one client and fifo:IN:OUT uses named pipes
+pub(crate) fn connect(spec: &str) -> Result<(IO, IO)> {
+    let (reader, writer) = match spec.split_once(':') {
+        Some(("tcp", address)) => tcp(TcpStream::connect(address)?)?,
+        Some(("tcp-listen", address)) => tcp(TcpListener::bind(address)?.accept()?.0)?,
+        #[cfg(unix)]
+        Some(("fifo", paths)) => match paths.split_once(':') {
+            Some((input, output)) => fifo(input, output)?,
+            None => Err(Error::msg(format!("Give both pipes: fifo:IN:OUT, not {}", spec)))?
+        }
+        _ => Err(Error::msg(format!("Unknown io: {}, expected tcp:HOST:PORT, tcp-listen:HOST:PORT or fifo:IN:OUT", spec)))?
+    };
+    // clones of the IO share the connection
+    let (reader, writer) = (Arc::new(Mutex::new(reader)), Arc::new(Mutex::new(writer)));
+    let input = IO::new().with_input(move |store| {
+        Ok(match store.pop() {
+            None => {
+                let mut s = String::new();
+                if reader.lock().map_err(|_| Error::msg("The io connection failed."))?.read_line(&mut s)? == 0 {
+                    Err(Error::msg("The io connection is closed."))?
+                }
+                s
+            }
+            Some(s) => s
+        })
+    });
+    let output = IO::new().with_output(move |_, s| {
+        let mut writer = writer.lock().map_err(|_| Error::msg("The io connection failed."))?;
+        writer.write_all(s.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    });
+    Ok((input, output))
+}
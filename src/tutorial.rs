@@ -0,0 +1,218 @@
+use std::cmp::min;
+use cursive::{Cursive, CursiveExt, Printer, Vec2};
+use cursive::view::{Nameable, View};
+use cursive::theme::{BorderStyle, ColorStyle, Palette, Theme};
+use cursive::event::{Event, EventResult, Key};
+use cursive::views::{Dialog, EditView};
+use rusty_funge::{Funge, IO, Rect, convert::join};
+
+
+struct Lesson {
+    title: &'static str,
+    text: &'static str,
+    code: &'static str,
+    // ask for the stack after this step
+    question: isize
+}
+
+const LESSONS: &[Lesson] = &[
+    Lesson {
+        title: "Numbers and arithmetic",
+        text: "Digits push themselves onto the stack. + pops two values and pushes their sum, . pops a value and \
+               prints it as a number and @ stops the program.",
+        code: "23+.@",
+        question: 2
+    },
+    Lesson {
+        title: "Changing direction",
+        text: "The instruction pointer (IP) starts in the top left corner moving right. The arrows > < ^ v change \
+               its direction, and * multiplies the top two values.",
+        code: "v    @\n>25*.^",
+        question: 4
+    },
+    Lesson {
+        title: "Stack juggling",
+        text: ": duplicates the top value, \\ swaps the top two values and $ throws the top value away. \
+               The top of the stack is printed on the right.",
+        code: "12\\..@",
+        question: 2
+    },
+    Lesson {
+        title: "Strings",
+        text: "\" toggles string mode: until the next \" every cell is pushed as its character code. \
+               , pops a value and prints it as a character, so strings are pushed in reverse.",
+        code: "\"iH\",,@",
+        question: 3
+    },
+    Lesson {
+        title: "Decisions",
+        text: "_ pops a value and sends the IP right if it is zero and left otherwise. | does the same for down and \
+               up. Moving off an edge wraps around to the other side.",
+        code: "0_1.@",
+        question: 1
+    },
+];
+
+
+struct TutorialView {
+    lesson: usize,
+    funge: Funge<isize>,
+    answered: bool,
+    message: String
+}
+
+impl TutorialView {
+    fn new() -> Self {
+        Self { lesson: 0, funge: Self::load(0), answered: false, message: String::new() }
+    }
+
+    fn load(lesson: usize) -> Funge<isize> {
+        Funge::new(LESSONS[lesson].code).expect("lessons are valid funges").deterministic()
+            .with_output(IO::new().with_output(|store, s| {
+                store.push(s);
+                Ok(())
+            }))
+    }
+
+    fn goto(&mut self, lesson: usize) {
+        self.lesson = min(lesson, LESSONS.len() - 1);
+        self.funge = Self::load(self.lesson);
+        self.answered = false;
+        self.message = String::new();
+    }
+
+    fn is_done(&self) -> bool {
+        self.funge.ips.is_empty()
+    }
+
+    fn step(&mut self) {
+        if !self.is_done() {
+            self.funge = match self.funge.clone().step() {
+                Ok(funge) => funge,
+                Err(_) => {
+                    let mut funge = self.funge.clone();
+                    funge.ips.clear();
+                    funge
+                }
+            }
+        }
+    }
+
+    fn stack(&self) -> Vec<isize> {
+        self.funge.ips.first().map(|ip| ip.stack()).unwrap_or_default()
+    }
+
+    fn answer(&mut self, text: &str) {
+        let guess = text.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty())
+            .map(|s| s.parse::<isize>()).collect::<Result<Vec<isize>, _>>();
+        self.step();
+        self.answered = true;
+        let stack = self.stack();
+        self.message = match guess {
+            Ok(guess) if guess == stack => String::from("Correct!"),
+            _ => format!("Not quite, the stack is [{}].", join(&stack, ", "))
+        };
+    }
+
+    fn wrap(text: &str, width: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        for word in text.split_whitespace() {
+            if !line.is_empty() && line.len() + word.len() >= width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+        lines
+    }
+
+    fn play(self) {
+        let mut app = Cursive::new();
+        app.add_layer(self.with_name("tutorial"));
+        app.add_global_callback(Key::Esc, |app| {
+            if app.screen().len() > 1 {
+                app.pop_layer();
+            } else {
+                app.quit();
+            }
+        });
+        app.set_theme(Theme { shadow: false, borders: BorderStyle::None, palette: Palette::default() });
+        app.run();
+    }
+}
+
+impl View for TutorialView {
+    fn draw(&self, printer: &Printer) {
+        let lesson = &LESSONS[self.lesson];
+        printer.print((0, 0), &format!("Lesson {} of {}: {}", self.lesson + 1, LESSONS.len(), lesson.title));
+        let mut n = 2;
+        for line in Self::wrap(lesson.text, printer.size.x.max(20)) {
+            printer.print((0, n), &line);
+            n += 1;
+        }
+        n += 1;
+        let extent = &self.funge.extent;
+        for (y, line) in self.funge.code.get_string(Rect::new(0, extent.right, 0, extent.bottom)).iter().enumerate() {
+            printer.print((2, n + y), line);
+        }
+        for ip in &self.funge.ips {
+            let (x, y) = (ip.position()[0], ip.position()[1]);
+            if (0 <= x) & (x < extent.right) & (0 <= y) & (y < extent.bottom) {
+                let c = char::from_u32(self.funge.code[&vec![x, y]] as u32).unwrap_or('¤');
+                printer.with_color(ColorStyle::highlight(), |printer| {
+                    printer.print((2 + x as usize, n + y as usize), &c.to_string());
+                });
+            }
+        }
+        n += extent.bottom as usize + 1;
+        printer.print((0, n), &format!("stack: [{}]", join(&self.stack(), ", ")));
+        printer.print((0, n + 1), &format!("output: {}", self.funge.output.get()));
+        if let Some(ip) = self.funge.ips.first().filter(|ip| !ip.is_string_mode()) {
+            let op = char::from_u32(self.funge.code[&ip.position().to_vec()] as u32);
+            if let Some(info) = self.funge.rules().instruction_docs().into_iter().find(|info| Some(info.op) == op) {
+                printer.print((0, n + 2), &format!("next: {} {} ({})", info.op, info.name, info.stack));
+            }
+        }
+        if self.is_done() {
+            printer.print((0, n + 3), "The program has stopped, press n for the next lesson or r to try again.");
+        } else if !self.answered && self.funge.steps == lesson.question {
+            printer.print((0, n + 3), "Question: what will the stack be after this instruction? Press enter.");
+        }
+        printer.print((0, n + 4), &self.message);
+        printer.print((0, printer.size.y.saturating_sub(1)),
+                      "esc: quit, enter: step, n: next lesson, p: previous lesson, r: restart");
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        constraint
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Key(Key::Enter) if !self.answered && self.funge.steps == LESSONS[self.lesson].question => {
+                return EventResult::with_cb(|app| {
+                    app.add_layer(Dialog::new().title("Predict the stack, for example: 1 2 3")
+                        .content(EditView::new().on_submit(|app: &mut Cursive, text| {
+                            app.pop_layer();
+                            app.call_on_name("tutorial", |view: &mut TutorialView| view.answer(text));
+                        })));
+                })
+            }
+            Event::Key(Key::Enter) => self.step(),
+            Event::Char('n') => self.goto(self.lesson + 1),
+            Event::Char('p') => self.goto(self.lesson.saturating_sub(1)),
+            Event::Char('r') => self.goto(self.lesson),
+            _ => return EventResult::Ignored
+        }
+        EventResult::Consumed(None)
+    }
+}
+
+
+pub(crate) fn tutorial() {
+    TutorialView::new().play()
+}